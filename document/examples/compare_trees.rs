@@ -0,0 +1,46 @@
+//! Compares the trees that can hold a `Document`'s pieces, by loading a file and making the same
+//! edits to it with each of them.
+//!
+//! Usage: `cargo run --release --example compare_trees -- <file> [edits]`
+//! (`scripts/lines.py` generates files with lots of lines to try it on.)
+use document::{Document, PieceNode};
+use document::ranked_tree::RankedTree;
+use document::rb_tree::RBTree;
+use document::scapegoat_tree::ScapegoatTree;
+use std::env::args;
+use std::time::{Duration, Instant};
+
+fn run<T: RankedTree<PieceNode> + Default>(name: &str, path: &str, edits: usize) {
+    let start = Instant::now();
+    let mut doc: Document<T> = Document::new(path).expect("file should be readable");
+    let load = start.elapsed();
+
+    // spread edits all over the document, like jumping around a file while editing it
+    let lines = doc.num_lines();
+    let mut edit = Duration::ZERO;
+    let mut lookup = Duration::ZERO;
+    for i in 0..edits {
+        let line = (i * 7919) % lines;
+        let start = Instant::now();
+        let offset = doc.line_start(line).expect("line should be in the document");
+        if i % 3 == 2 && offset < doc.graphemes() {
+            doc.delete(offset..offset + 1).expect("offset should be in the document");
+        } else {
+            doc.insert(offset, "edit\n").expect("offset should be in the document");
+        }
+        edit += start.elapsed();
+
+        let start = Instant::now();
+        doc.line((i * 104729) % doc.num_lines()).expect("line should be in the document");
+        lookup += start.elapsed();
+    }
+    println!("{:>10}: load {:>10.2?}, {} edits {:>10.2?}, {} lookups {:>10.2?}", name, load, edits, edit, edits, lookup);
+}
+
+fn main() {
+    let mut args = args().skip(1);
+    let path = args.next().expect("usage: compare_trees <file> [edits]");
+    let edits = args.next().map(|e| e.parse().expect("edits should be a number")).unwrap_or(10000);
+    run::<ScapegoatTree<PieceNode>>("scapegoat", &path, edits);
+    run::<RBTree<PieceNode>>("red-black", &path, edits);
+}
@@ -1,3 +1,4 @@
+use std::cell::Cell;
 use std::cmp::Ordering;
 use std::ops::{Bound, RangeBounds};
 use unicode_segmentation::{GraphemeCursor, UnicodeSegmentation};
@@ -48,7 +49,7 @@ pub struct Buffer {
     #[doc(hidden)]
     num_graphemes: u16,
     #[doc(hidden)]
-    cached_idx: Option<u16>,
+    cached_idx: Cell<Option<u16>>,
     #[doc(hidden)]
     mutable: bool,
     #[doc(hidden)]
@@ -64,7 +65,7 @@ impl Buffer {
         assert!(buf.len() <= u16::MAX.into());
         Self::convert_crlf(&mut buf);
         if buf.is_empty() {
-            return Self { buf, ascii: Vec::new(), unicode: Vec::new(), newlines: Vec::new(), num_graphemes: 0, cached_idx: None, mutable: true };
+            return Self { buf, ascii: Vec::new(), unicode: Vec::new(), newlines: Vec::new(), num_graphemes: 0, cached_idx: Cell::new(None), mutable: true };
         }
         let length = buf.len() as u16;
         let b = buf.as_str();
@@ -81,7 +82,7 @@ impl Buffer {
             let next_start = cursor.next_boundary(b, 0).unwrap().map(|i| i as u16).unwrap_or(length);
             let grapheme = &buf[(idx as usize)..(next_start as usize)];
             if grapheme.is_ascii() && !grapheme.is_empty() {
-                if let None = saved_ascii_idx {
+                if saved_ascii_idx.is_none() {
                     saved_ascii_idx = Some(idx);
                 }
                 if grapheme == "\n" {
@@ -117,7 +118,7 @@ impl Buffer {
             idx = next_start;
         }
 
-        Buffer{ num_graphemes, buf, ascii, unicode, newlines, cached_idx: None, mutable: true }
+        Buffer{ num_graphemes, buf, ascii, unicode, newlines, cached_idx: Cell::new(None), mutable: true }
     }
 
     /// Returns the grapheme indices of the newlines in the Buffer.
@@ -131,8 +132,8 @@ impl Buffer {
     ///
     /// # Panics
     /// No bounds checking is done. `get` will panic if it is given invalid indices.
-    pub fn get(&mut self, bounds: impl RangeBounds<u16>) -> &str {
-        if self.buf.len() == 0 { return ""; }
+    pub fn get(&self, bounds: impl RangeBounds<u16>) -> &str {
+        if self.buf.is_empty() { return ""; }
         enum Index {
             Ascii(u16),
             Unicode(u16)
@@ -189,7 +190,7 @@ impl Buffer {
                         return (mid, match index(mid) {
                             Index::Ascii(idx) => {
                                 let range = &self.ascii[idx as usize];
-                                range.byte_start + needle - range.grapheme_start
+                                range.byte_start + (needle - range.grapheme_start)
                             },
                             Index::Unicode(idx) => {
                                 let range = &self.unicode[idx as usize];
@@ -208,7 +209,7 @@ impl Buffer {
             Bound::Included(i) => *i,
             Bound::Excluded(i) => *i + 1u16,
             Bound::Unbounded => 0u16
-        }, None, self.cached_idx, None);
+        }, None, self.cached_idx.get(), None);
 
         let (end_chunk, end) = binary_search(match bounds.end_bound() {
             Bound::Included(i) => *i + 1,
@@ -216,7 +217,7 @@ impl Buffer {
             Bound::Unbounded => self.num_graphemes
         }, Some(start_chunk), Some(start_chunk), None);
 
-        self.cached_idx = Some(end_chunk);
+        self.cached_idx.set(Some(end_chunk));
         &self.buf[start as usize..end as usize]
     }
 
@@ -247,6 +248,9 @@ impl Buffer {
                     self.unicode.push(UnicodeRange{ grapheme_start: self.num_graphemes, graphemes: vec![idx] });
                 }
             }
+            if grapheme == "\n" {
+                self.newlines.push(self.num_graphemes);
+            }
             self.num_graphemes += 1;
             self.buf.push_str(grapheme);
         }
@@ -1,5 +1,12 @@
 use crate::buffer::Buffer;
+use crate::ranked_tree::{Augmented, RankedTree};
 use crate::scapegoat_tree::ScapegoatTree;
+use std::cmp::Ordering;
+use std::fs::read_to_string;
+use std::io::{Result, Write};
+use std::ops::Range;
+use std::path::Path;
+use unicode_segmentation::{GraphemeCursor, UnicodeSegmentation};
 
 // TODO: choose bounds for numerics later
 type NewlineCount = usize;
@@ -7,24 +14,641 @@ type PieceIndex = usize;
 type BufferIndex = usize;
 type GraphemeIndex = u16;
 
-struct Node {
-    count: NewlineCount,
-    left_count: NewlineCount,
-    piece: PieceIndex,
+// the most bytes a `Buffer` can hold
+const MAX_BUFFER_BYTES: usize = u16::MAX as usize;
+
+/// A node in the tree that holds a [`Document`]'s pieces.
+///
+/// Along with its piece, a node keeps the number of newlines and graphemes in its subtree, so
+/// that lines and offsets can be found in O(log n) time.
+pub struct PieceNode {
+    piece: Piece,
+    count: NewlineCount, // newlines in this piece
+    left_count: NewlineCount, // newlines in the left subtree
+    total_count: NewlineCount, // newlines in the whole subtree
+    left_graphemes: usize, // graphemes in the left subtree
+    total_graphemes: usize, // graphemes in the whole subtree
+}
+
+impl PieceNode {
+    fn new(piece: Piece, count: NewlineCount) -> Self {
+        PieceNode{ piece, count, left_count: 0, total_count: count, left_graphemes: 0, total_graphemes: piece.graphemes() }
+    }
+}
+
+impl Augmented for PieceNode {
+    fn update(&mut self, left: Option<&Self>, right: Option<&Self>) {
+        self.left_count = left.map_or(0, |n| n.total_count);
+        self.left_graphemes = left.map_or(0, |n| n.total_graphemes);
+        self.total_count = self.left_count + self.count + right.map_or(0, |n| n.total_count);
+        self.total_graphemes = self.left_graphemes + self.piece.graphemes() + right.map_or(0, |n| n.total_graphemes);
+    }
 }
 
+#[derive(Clone, Copy)]
 struct Piece {
     buffer: BufferIndex,
     start: GraphemeIndex, // inclusive
     end: GraphemeIndex, // exclusive
 }
 
-pub struct Document {
-    #[doc(hidden)]
-    newline_tree: ScapegoatTree<Node>,
+impl Piece {
+    fn graphemes(&self) -> usize {
+        (self.end - self.start) as usize
+    }
+}
+
+/// Enum that describes why a [`Document`] edit couldn't be made.
+#[derive(Debug, PartialEq, Eq)]
+pub enum EditError {
+    /// The grapheme offset (or range of offsets) is past the end of the document.
+    OutOfBounds,
+}
+
+/// Struct that represents a line of text in a [`Document`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Line {
+    /// The content of the line, without the trailing newline.
+    pub text: String,
+    /// The number of graphemes in the line (again, without the trailing newline).
+    pub graphemes: usize,
+}
+
+/// Struct that represents an open file.
+///
+/// A `Document` is a piece table: the text lives in a list of [`Buffer`]s, and the document is
+/// an ordered list of 'pieces', each of which is a range of graphemes in one of those buffers.
+/// The buffers holding the original file are never modified; inserted text is appended to the
+/// last buffer (a new one is started whenever it fills up), and a delete only ever shrinks or
+/// removes pieces.
+///
+/// Positions in a `Document` are grapheme offsets from the beginning of the document. Lines are
+/// separated by `\n` (CRLFs are converted when text enters the document), so a document always
+/// has one more line than it has newlines.
+///
+/// The pieces are kept in a tree that is indexed by both newlines and graphemes, so finding a
+/// line, or the grapheme offset a line starts at, takes O(log n) time in the number of pieces.
+/// Any [`RankedTree`] can be used to hold them, which is mostly useful for comparing trees.
+pub struct Document<T = ScapegoatTree<PieceNode>> {
     #[doc(hidden)]
-    pieces: Vec<Piece>, // TODO: use other storage for pieces?
+    pieces: T,
     #[doc(hidden)]
     buffers: Vec<Buffer>,
+    #[doc(hidden)]
+    newlines: NewlineCount,
+    #[doc(hidden)]
+    graphemes: usize,
+}
+
+impl<T: RankedTree<PieceNode> + Default> Document<T> {
+    /// Create a new `Document` from the contents of a file.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let text = read_to_string(path)?;
+        Ok(Self::from(text))
+    }
+}
+
+impl<T: RankedTree<PieceNode>> Document<T> {
+    /// Returns the number of lines in the document.
+    pub fn num_lines(&self) -> usize {
+        self.newlines + 1
+    }
+
+    /// Returns the number of graphemes in the document.
+    pub fn graphemes(&self) -> usize {
+        self.graphemes
+    }
+
+    /// Returns the line at the given (zero-based) index, or `None` if there is no such line.
+    pub fn line(&self, idx: usize) -> Option<Line> {
+        let range = self.line_range(idx)?;
+        let graphemes = range.len();
+        Some(Line{ text: self.text_unchecked(range), graphemes })
+    }
+
+    /// Returns the number of graphemes in the line at the given (zero-based) index, or `None` if
+    /// there is no such line.
+    pub fn line_graphemes(&self, idx: usize) -> Option<usize> {
+        self.line_range(idx).map(|range| range.len())
+    }
+
+    /// Returns the grapheme offset of the first grapheme of the line at the given (zero-based)
+    /// index, or `None` if there is no such line.
+    pub fn line_start(&self, idx: usize) -> Option<usize> {
+        if idx == 0 {
+            Some(0)
+        } else {
+            self.newline_offset(idx - 1).map(|offset| offset + 1)
+        }
+    }
+
+    /// Returns the (zero-based) index of the line that the grapheme at `offset` is in, or `None` if
+    /// `offset` is past the end of the document.
+    ///
+    /// The newline at the end of a line is in that line, and the end of the document (an offset of
+    /// [`Document::graphemes`]) is in the last line.
+    pub fn line_of(&self, offset: usize) -> Option<usize> {
+        if offset >= self.graphemes {
+            return if offset == self.graphemes { Some(self.newlines) } else { None };
+        }
+        let mut remaining = offset;
+        let mut line = 0;
+        let node = self.pieces.search_with(|node| {
+            if remaining < node.left_graphemes {
+                Ordering::Less
+            } else if remaining < node.left_graphemes + node.piece.graphemes() {
+                remaining -= node.left_graphemes;
+                line += node.left_count;
+                Ordering::Equal
+            } else {
+                remaining -= node.left_graphemes + node.piece.graphemes();
+                line += node.left_count + node.count;
+                Ordering::Greater
+            }
+        })?;
+        let piece = node.piece;
+        let newlines = self.buffers[piece.buffer].newlines();
+        let grapheme = piece.start + remaining as GraphemeIndex;
+        Some(line + newlines.partition_point(|&g| g < grapheme) - newlines.partition_point(|&g| g < piece.start))
+    }
+
+    /// Returns an iterator over the lines of the document, starting from the given (zero-based)
+    /// index, inclusive.
+    ///
+    /// Returns `None` if there is no line with index `line_idx`.
+    pub fn iter_from(&self, line_idx: usize) -> Option<Lines<'_, T>> {
+        if line_idx < self.num_lines() {
+            Some(Lines{ doc: self, line: line_idx })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the text in the given range of grapheme offsets, or `None` if the range goes past
+    /// the end of the document.
+    pub fn text(&self, range: Range<usize>) -> Option<String> {
+        if range.start > range.end || range.end > self.graphemes {
+            None
+        } else {
+            Some(self.text_unchecked(range))
+        }
+    }
+
+    /// Write the whole document to `writer`, piece by piece (without copying it into a `String`
+    /// first).
+    ///
+    /// Newlines are written as `\n`, since CRLFs are converted when text is put in the document.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        for idx in 0..self.pieces.len() {
+            let piece = self.pieces.get(idx).expect("piece should be in the document").piece;
+            writer.write_all(self.buffers[piece.buffer].get(piece.start..piece.end).as_bytes())?;
+        }
+        writer.flush()
+    }
+
+    /// Insert `text` so that its first grapheme ends up at the grapheme offset `offset`.
+    ///
+    /// Inserting at `self.graphemes()` appends to the document.
+    pub fn insert(&mut self, offset: usize, text: &str) -> std::result::Result<(), EditError> {
+        if offset > self.graphemes {
+            return Err(EditError::OutOfBounds);
+        }
+        let mut text = text.to_string();
+        Buffer::convert_crlf(&mut text);
+        let new_pieces = self.append(&text);
+        if new_pieces.is_empty() {
+            return Ok(());
+        }
+        let idx = self.split_at(offset);
+        let mut new_pieces = new_pieces.into_iter();
+        // typing usually appends to the piece that was just added, so try to extend it
+        if idx > 0 {
+            let previous = self.pieces.get(idx - 1).expect("previous piece should exist").piece;
+            let first = new_pieces.as_slice()[0];
+            if previous.buffer == first.buffer && previous.end == first.start {
+                let extended = Piece{ end: first.end, ..previous };
+                self.set_piece(idx - 1, extended);
+                new_pieces.next();
+            }
+        }
+        for (idx, piece) in (idx..).zip(new_pieces) {
+            let count = self.piece_newlines(&piece);
+            self.pieces.insert_rank(idx, PieceNode::new(piece, count));
+        }
+        Ok(())
+    }
+
+    /// Delete the graphemes in the given range of grapheme offsets.
+    pub fn delete(&mut self, range: Range<usize>) -> std::result::Result<(), EditError> {
+        if range.start > range.end || range.end > self.graphemes {
+            return Err(EditError::OutOfBounds);
+        }
+        if range.is_empty() {
+            return Ok(());
+        }
+        let start = self.split_at(range.start);
+        let end = self.split_at(range.end);
+        for _ in start..end {
+            let node = self.pieces.delete_rank(start).expect("piece in range should exist");
+            self.newlines -= node.count;
+            self.graphemes -= node.piece.graphemes();
+        }
+        Ok(())
+    }
+
+    // pushes the text to the end of the mutable buffers, and returns the pieces that cover it
+    fn append(&mut self, text: &str) -> Vec<Piece> {
+        let mut pieces: Vec<Piece> = Vec::new();
+        for grapheme in text.graphemes(true) {
+            let mut buffer = self.buffers.len() - 1;
+            let mut start = self.buffers[buffer].graphemes();
+            if self.buffers[buffer].push(grapheme).is_err() {
+                // either full or one of the file's buffers, so start a new one
+                self.buffers.push(Buffer::new(String::new()));
+                buffer += 1;
+                start = 0;
+                self.buffers[buffer].push(grapheme).expect("a single grapheme should fit in an empty buffer");
+            }
+            if grapheme == "\n" {
+                self.newlines += 1;
+            }
+            self.graphemes += 1;
+            match pieces.last_mut() {
+                Some(piece) if piece.buffer == buffer && piece.end == start => piece.end += 1,
+                _ => pieces.push(Piece{ buffer, start, end: start + 1 }),
+            }
+        }
+        pieces
+    }
+
+    // splits the piece containing `offset` (if necessary), so that `offset` is the first grapheme
+    // of a piece; returns the index of that piece (or the number of pieces, if `offset` is the end)
+    fn split_at(&mut self, offset: usize) -> PieceIndex {
+        let (idx, within) = match self.piece_at(offset) {
+            Some(found) => found,
+            None => return self.pieces.len()
+        };
+        if within == 0 {
+            return idx;
+        }
+        let piece = self.pieces.get(idx).expect("found piece should exist").piece;
+        let split = piece.start + within as GraphemeIndex;
+        self.set_piece(idx, Piece{ end: split, ..piece });
+        let right = Piece{ start: split, ..piece };
+        let count = self.piece_newlines(&right);
+        self.pieces.insert_rank(idx + 1, PieceNode::new(right, count));
+        idx + 1
+    }
+
+    // index of the piece containing the grapheme at `offset`, and how far into the piece it is
+    fn piece_at(&self, offset: usize) -> Option<(PieceIndex, usize)> {
+        let mut remaining = offset;
+        let (idx, _) = self.pieces.search_rank_with(|node| {
+            if remaining < node.left_graphemes {
+                Ordering::Less
+            } else if remaining < node.left_graphemes + node.piece.graphemes() {
+                remaining -= node.left_graphemes;
+                Ordering::Equal
+            } else {
+                remaining -= node.left_graphemes + node.piece.graphemes();
+                Ordering::Greater
+            }
+        })?;
+        Some((idx, remaining))
+    }
+
+    // replaces the piece at `idx`, keeping the newline counts up to date
+    fn set_piece(&mut self, idx: PieceIndex, piece: Piece) {
+        let count = self.piece_newlines(&piece);
+        self.pieces.modify(idx, |node| {
+            node.piece = piece;
+            node.count = count;
+        });
+    }
+
+    fn piece_newlines(&self, piece: &Piece) -> NewlineCount {
+        let newlines = self.buffers[piece.buffer].newlines();
+        newlines.partition_point(|&g| g < piece.end) - newlines.partition_point(|&g| g < piece.start)
+    }
+
+    // grapheme offset of the newline with the given (zero-based) index
+    fn newline_offset(&self, idx: usize) -> Option<usize> {
+        let mut remaining = idx;
+        let mut piece_start = 0;
+        let node = self.pieces.search_with(|node| {
+            if remaining < node.left_count {
+                Ordering::Less
+            } else if remaining < node.left_count + node.count {
+                remaining -= node.left_count;
+                piece_start += node.left_graphemes;
+                Ordering::Equal
+            } else {
+                remaining -= node.left_count + node.count;
+                piece_start += node.left_graphemes + node.piece.graphemes();
+                Ordering::Greater
+            }
+        })?;
+        let piece = node.piece;
+        let newlines = self.buffers[piece.buffer].newlines();
+        let first = newlines.partition_point(|&g| g < piece.start);
+        let grapheme = newlines[first + remaining];
+        Some(piece_start + (grapheme - piece.start) as usize)
+    }
+
+    fn line_range(&self, idx: usize) -> Option<Range<usize>> {
+        let start = self.line_start(idx)?;
+        let end = if idx == self.newlines { self.graphemes } else { self.newline_offset(idx)? };
+        Some(start..end)
+    }
+
+    fn text_unchecked(&self, range: Range<usize>) -> String {
+        let mut text = String::new();
+        if range.is_empty() {
+            return text;
+        }
+        let (mut idx, mut start) = self.piece_at(range.start).expect("range should be in the document");
+        let mut remaining = range.len();
+        while remaining > 0 {
+            let piece = self.pieces.get(idx).expect("range should be in the document").piece;
+            let end = piece.graphemes().min(start + remaining);
+            text.push_str(self.buffers[piece.buffer].get(piece.start + start as GraphemeIndex..piece.start + end as GraphemeIndex));
+            remaining -= end - start;
+            start = 0;
+            idx += 1;
+        }
+        text
+    }
+}
+
+impl<T: RankedTree<PieceNode> + Default> From<String> for Document<T> {
+    /// Create a new `Document` from a `String`.
+    ///
+    /// If the string fits in a single [`Buffer`] (2^16 - 1 bytes), it is moved into it. Otherwise,
+    /// it is cut into as many buffers as needed, at grapheme boundaries.
+    fn from(text: String) -> Self {
+        let buffers = if text.len() <= MAX_BUFFER_BYTES {
+            vec![Buffer::new(text)]
+        } else {
+            chunks(&text).map(|chunk| Buffer::new(chunk.to_string())).collect::<Vec<Buffer>>()
+        };
+        let mut doc = Document{ pieces: T::default(), buffers, newlines: 0, graphemes: 0 };
+        for buffer in 0..doc.buffers.len() {
+            doc.buffers[buffer].set_immutable();
+            let end = doc.buffers[buffer].graphemes();
+            if end > 0 {
+                let count = doc.buffers[buffer].newlines().len();
+                let idx = doc.pieces.len();
+                doc.pieces.insert_rank(idx, PieceNode::new(Piece{ buffer, start: 0, end }, count));
+                doc.newlines += count;
+                doc.graphemes += end as usize;
+            }
+        }
+        doc
+    }
+}
+
+impl<T: RankedTree<PieceNode> + Default> From<&str> for Document<T> {
+    /// Create a new `Document` from a string slice, copying its contents.
+    fn from(text: &str) -> Self {
+        Self::from(text.to_string())
+    }
+}
+
+// cuts `text` into pieces that each fit in a `Buffer`, without splitting any graphemes (so a CRLF
+// is never split either)
+fn chunks(text: &str) -> impl Iterator<Item = &str> {
+    let mut start = 0;
+    std::iter::from_fn(move || {
+        if start == text.len() {
+            return None;
+        }
+        let mut end = (start + MAX_BUFFER_BYTES).min(text.len());
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end < text.len() {
+            let mut cursor = GraphemeCursor::new(end, text.len(), true);
+            if !cursor.is_boundary(text, 0).expect("whole text is given as context") {
+                let previous = cursor.prev_boundary(text, 0).expect("whole text is given as context");
+                // a single grapheme larger than a buffer has to be split somewhere
+                end = previous.filter(|&b| b > start).unwrap_or(end);
+            }
+        }
+        let chunk = &text[start..end];
+        start = end;
+        Some(chunk)
+    })
+}
+
+/// Iterator over the lines of a [`Document`].
+pub struct Lines<'a, T = ScapegoatTree<PieceNode>> {
+    #[doc(hidden)]
+    doc: &'a Document<T>,
+    #[doc(hidden)]
+    line: usize,
+}
+
+impl<'a, T: RankedTree<PieceNode>> Iterator for Lines<'a, T> {
+    type Item = Line;
+
+    fn next(&mut self) -> Option<Line> {
+        let line = self.doc.line(self.line)?;
+        self.line += 1;
+        Some(line)
+    }
 }
 
+impl<'a, T: RankedTree<PieceNode>> IntoIterator for &'a Document<T> {
+    type Item = Line;
+    type IntoIter = Lines<'a, T>;
+
+    fn into_iter(self) -> Lines<'a, T> {
+        Lines{ doc: self, line: 0 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{chunks, Document, EditError, Line, PieceNode, MAX_BUFFER_BYTES};
+    use crate::ranked_tree::RankedTree;
+    use crate::rb_tree::RBTree;
+    use crate::scapegoat_tree::ScapegoatTree;
+
+    // every test that uses a `Document` is run once for each tree that can hold its pieces
+    macro_rules! document_tests {
+        ($($name:ident: $tree:ty),*) => {$(
+            mod $name {
+                use super::*;
+
+                type Doc = Document<$tree>;
+
+                fn lines(doc: &Doc) -> Vec<String> {
+                    doc.into_iter().map(|l| l.text).collect()
+                }
+
+                #[test]
+                fn from_str_lines() {
+                    let doc = Doc::from("hëllo\r\nwörld\n\nlast");
+                    assert_eq!(doc.num_lines(), 4);
+                    assert_eq!(doc.graphemes(), 17);
+                    assert_eq!(doc.line(0), Some(Line{ text: "hëllo".to_string(), graphemes: 5 }));
+                    assert_eq!(doc.line(1), Some(Line{ text: "wörld".to_string(), graphemes: 5 }));
+                    assert_eq!(doc.line(2), Some(Line{ text: String::new(), graphemes: 0 }));
+                    assert_eq!(doc.line(3), Some(Line{ text: "last".to_string(), graphemes: 4 }));
+                    assert_eq!(doc.line(4), None);
+                    assert_eq!(doc.line_start(3), Some(13));
+                }
+
+                #[test]
+                fn empty() {
+                    let doc = Doc::from("");
+                    assert_eq!(doc.num_lines(), 1);
+                    assert_eq!(doc.line(0), Some(Line::default()));
+                    assert_eq!(lines(&doc), vec![""]);
+                }
+
+                #[test]
+                fn insert_middle_and_ends() {
+                    let mut doc = Doc::from("abc\ndef");
+                    doc.insert(1, "X").unwrap();
+                    doc.insert(0, "<").unwrap();
+                    doc.insert(doc.graphemes(), ">").unwrap();
+                    assert_eq!(lines(&doc), vec!["<aXbc", "def>"]);
+                    assert_eq!(doc.insert(100, "?"), Err(EditError::OutOfBounds));
+                }
+
+                #[test]
+                fn insert_typing_extends_piece() {
+                    let mut doc = Doc::from("ac");
+                    for (i, c) in ["b", "b", "b"].iter().enumerate() {
+                        doc.insert(1 + i, c).unwrap();
+                    }
+                    assert_eq!(lines(&doc), vec!["abbbc"]);
+                    assert_eq!(doc.pieces.len(), 3);
+                }
+
+                #[test]
+                fn insert_newlines() {
+                    let mut doc = Doc::from("first line");
+                    doc.insert(5, "\n").unwrap();
+                    assert_eq!(lines(&doc), vec!["first", " line"]);
+                    doc.insert(0, "zeroth\r\n").unwrap();
+                    assert_eq!(lines(&doc), vec!["zeroth", "first", " line"]);
+                    assert_eq!(doc.num_lines(), 3);
+                    assert_eq!(doc.line_start(2), Some(13));
+                }
+
+                #[test]
+                fn delete_within_and_across_pieces() {
+                    let mut doc = Doc::from("one\ntwo\nthree");
+                    doc.insert(3, " and a half").unwrap();
+                    doc.delete(1..2).unwrap();
+                    assert_eq!(lines(&doc), vec!["oe and a half", "two", "three"]);
+                    // join lines by deleting a newline
+                    doc.delete(13..14).unwrap();
+                    assert_eq!(lines(&doc), vec!["oe and a halftwo", "three"]);
+                    doc.delete(2..19).unwrap();
+                    assert_eq!(lines(&doc), vec!["oeree"]);
+                    assert_eq!(doc.num_lines(), 1);
+                    assert_eq!(doc.delete(3..10), Err(EditError::OutOfBounds));
+                }
+
+                #[test]
+                fn delete_everything() {
+                    let mut doc = Doc::from("a\nb\nc");
+                    doc.delete(0..doc.graphemes()).unwrap();
+                    assert_eq!(doc.graphemes(), 0);
+                    assert_eq!(lines(&doc), vec![""]);
+                    doc.insert(0, "new").unwrap();
+                    assert_eq!(lines(&doc), vec!["new"]);
+                }
+
+                #[test]
+                fn text_ranges() {
+                    let mut doc = Doc::from("grapheme: ö");
+                    doc.insert(0, "é\n").unwrap();
+                    assert_eq!(doc.text(0..3), Some("é\ng".to_string()));
+                    assert_eq!(doc.text(11..13), Some(" ö".to_string()));
+                    assert_eq!(doc.text(0..doc.graphemes()), Some("é\ngrapheme: ö".to_string()));
+                    assert_eq!(doc.text(5..100), None);
+                }
+
+                #[test]
+                fn many_lines_and_pieces() {
+                    let text = (0..1000).map(|i| format!("{}\n", i)).collect::<String>();
+                    let mut doc = Doc::from(text.as_str());
+                    assert_eq!(doc.num_lines(), 1001);
+                    assert_eq!(doc.line(567).unwrap().text, "567");
+                    // split the document into lots of pieces
+                    for i in (0..1000).rev().step_by(7) {
+                        let start = doc.line_start(i).unwrap();
+                        doc.insert(start, "x").unwrap();
+                    }
+                    for i in 0..1000 {
+                        let expected = if (999 - i) % 7 == 0 { format!("x{}", i) } else { i.to_string() };
+                        assert_eq!(doc.line(i).unwrap().text, expected);
+                    }
+                    doc.delete(doc.line_start(10).unwrap()..doc.line_start(990).unwrap()).unwrap();
+                    assert_eq!(doc.num_lines(), 21);
+                    assert_eq!(lines(&doc)[9..12], ["9", "990", "991"]);
+                    for i in [0, 9, 10, 20] {
+                        let start = doc.line_start(i).unwrap();
+                        assert_eq!(doc.line_of(start), Some(i));
+                        assert_eq!(doc.line_of(start + doc.line_graphemes(i).unwrap()), Some(i));
+                    }
+                }
+
+                #[test]
+                fn line_of() {
+                    let mut doc = Doc::from("ab\n\ncd");
+                    doc.insert(1, "x\ny").unwrap();
+                    // "ax\nyb\n\ncd"
+                    let expected = [0, 0, 0, 1, 1, 1, 2, 3, 3, 3];
+                    for (offset, line) in expected.iter().enumerate() {
+                        assert_eq!(doc.line_of(offset), Some(*line));
+                    }
+                    assert_eq!(doc.line_of(doc.graphemes() + 1), None);
+                    assert_eq!(Doc::from("").line_of(0), Some(0));
+                }
+
+                #[test]
+                fn large_text_is_chunked() {
+                    // multi-byte graphemes and CRLFs, so that some of them land on chunk seams
+                    let line = "añb\u{0065}\u{0301}ç\r\n";
+                    let text = line.repeat(40000);
+                    let doc = Doc::from(text.as_str());
+                    assert!(doc.buffers.len() > 1);
+                    assert!(doc.buffers.iter().all(|b| (b.bytes() as usize) <= MAX_BUFFER_BYTES));
+                    assert_eq!(doc.num_lines(), 40001);
+                    assert_eq!(doc.graphemes(), 40000 * 6);
+                    for i in (0..40000).step_by(97) {
+                        assert_eq!(doc.line(i), Some(Line{ text: "añbe\u{0301}ç".to_string(), graphemes: 5 }));
+                    }
+                    assert_eq!(doc.line(40000), Some(Line::default()));
+                }
+
+                #[test]
+                fn write_to() {
+                    let mut doc = Doc::from("first\r\nsecond\n");
+                    doc.insert(6, "new\n").unwrap();
+                    doc.delete(0..1).unwrap();
+                    let mut bytes = Vec::new();
+                    doc.write_to(&mut bytes).unwrap();
+                    assert_eq!(String::from_utf8(bytes).unwrap(), "irst\nnew\nsecond\n");
+                }
+            }
+        )*};
+    }
+
+    document_tests!(scapegoat: ScapegoatTree<PieceNode>, red_black: RBTree<PieceNode>);
+
+    #[test]
+    fn chunks_only_split_at_grapheme_boundaries() {
+        // every grapheme is 3 codepoints (e + 2 combining marks), 5 bytes
+        let text = "e\u{0301}\u{0302}".repeat(30000);
+        let chunks = chunks(&text).collect::<Vec<&str>>();
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.concat(), text);
+        assert!(chunks.iter().all(|c| c.len() % 5 == 0 && c.len() <= MAX_BUFFER_BYTES));
+    }
+}
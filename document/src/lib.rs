@@ -2,8 +2,8 @@
 
 pub mod buffer;
 mod document;
-mod ranked_tree;
-mod rb_tree;
-mod scapegoat_tree;
+pub mod ranked_tree;
+pub mod rb_tree;
+pub mod scapegoat_tree;
 
-pub use crate::document::Document;
+pub use crate::document::{Document, EditError, Line, Lines, PieceNode};
@@ -1,36 +1,89 @@
+//! Traits shared by the trees that can hold a [`crate::Document`]'s pieces.
 use std::{borrow::Borrow, cmp::Ordering};
 
-pub trait RankedTree<T> {
+/// Trait for values that keep information about the subtree they are the root of (e.g. the
+/// number of newlines in it), which a [`RankedTree`] updates whenever the subtree changes.
+pub trait Augmented {
+    /// Recompute the information kept in `self` from its (possibly new) children.
+    fn update(&mut self, _left: Option<&Self>, _right: Option<&Self>) {}
+}
+
+/// Trait for binary search trees whose elements can also be found by their rank (their
+/// zero-based index in an in-order traversal of the tree).
+///
+/// The `*_with` methods take a closure that compares the element being looked for with an element
+/// of the tree. The closure is called at most once per node, going down from the root, so it may
+/// keep state (for example, a rank or offset relative to the current subtree).
+pub trait RankedTree<T: Augmented> {
+    /// Returns the number of elements in the tree.
+    fn len(&self) -> usize;
+
+    /// Returns `true` if the tree has no elements.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Deletes the element equal to `item`, returning it.
     fn delete<R: Borrow<T>>(&mut self, item: R) -> Option<T>
     where
         T: Ord;
 
+    /// Deletes the element that `f` returns `Ordering::Equal` for, returning it.
     fn delete_with<F>(&mut self, f: F) -> Option<T>
     where
         F: FnMut(&T) -> Ordering;
 
+    /// Deletes the element with the given rank, returning it.
+    fn delete_rank(&mut self, rank: usize) -> Option<T>;
+
+    /// Returns the element equal to `item`.
     fn search<R: Borrow<T>>(&self, item: R) -> Option<&T>
     where
         T: Ord;
 
+    /// Returns the element that `f` returns `Ordering::Equal` for.
     fn search_with<F>(&self, f: F) -> Option<&T>
     where
         F: FnMut(&T) -> Ordering;
 
+    /// Like [`RankedTree::search_with`], but also returns the rank of the element.
+    fn search_rank_with<F>(&self, f: F) -> Option<(usize, &T)>
+    where
+        F: FnMut(&T) -> Ordering;
+
+    /// Returns the element equal to `item`.
+    ///
+    /// The ancestors of the element aren't updated, so nothing that [`Augmented::update`] depends
+    /// on should be changed through the reference; use [`RankedTree::modify`] instead.
     fn search_mut<R: Borrow<T>>(&mut self, item: R) -> Option<&mut T>
     where
         T: Ord;
 
+    /// Returns the element that `f` returns `Ordering::Equal` for. See
+    /// [`RankedTree::search_mut`].
     fn search_with_mut<F>(&mut self, f: F) -> Option<&mut T>
     where
         F: FnMut(&T) -> Ordering;
 
+    /// Returns the element with the given rank.
     fn get(&self, rank: usize) -> Option<&T>;
 
+    /// Returns the element with the given rank. See [`RankedTree::search_mut`].
     fn get_mut(&mut self, rank: usize) -> Option<&mut T>;
 
+    /// Applies `f` to the element with the given rank, then updates the augmented data of the
+    /// element and its ancestors.
+    ///
+    /// Returns `None` (without calling `f`) if there is no element with that rank.
+    fn modify<F, R>(&mut self, rank: usize, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut T) -> R;
+
+    /// Inserts `new` so that it has the given rank. Does nothing if `rank` is greater than the
+    /// length of the tree.
     fn insert_rank(&mut self, rank: usize, new: T);
 
+    /// Inserts `new` in sorted order. Does nothing if an equal element is already in the tree.
     fn insert(&mut self, new: T)
     where
         T: Ord;
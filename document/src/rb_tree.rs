@@ -1,35 +1,71 @@
+use std::borrow::Borrow;
 use std::cmp::Ordering;
-use crate::ranked_tree::RankedTree;
+use std::mem::replace;
+use crate::ranked_tree::{Augmented, RankedTree};
 
+// [1]: https://sedgewick.io/wp-content/themes/sedgewick/papers/2008LLRB.pdf
+// [2]: https://algs4.cs.princeton.edu/33balanced/RedBlackBST.java.html
+// A left-leaning red-black tree ([1]), where every node also keeps the size of its subtree, so
+// that it can be indexed by rank. Deletion follows [2], but finds nodes by rank instead of by key.
+
+/// A left-leaning red-black tree, indexed by rank.
+///
+/// Every operation is O(log n), including deletion.
 pub struct RBTree<T>
 {
     #[doc(hidden)]
-    root: Option<Box<RBNode<T>>>,
+    root: Link<T>,
 }
 
+type Link<T> = Option<Box<RBNode<T>>>;
+
 struct RBNode<T>
 {
     value: T,
-    left: Option<Box<RBNode<T>>>,
-    right: Option<Box<RBNode<T>>>,
+    left: Link<T>,
+    right: Link<T>,
     red: bool,
+    size: usize, // size of the subtree rooted at this node
 }
 
-impl<T> RankedTree<T> for RBTree<T>
+impl<T: Augmented> RankedTree<T> for RBTree<T>
 {
-    fn delete<R: std::borrow::Borrow<T>>(&mut self, item: R) -> Option<T>
+    fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    fn delete<R: Borrow<T>>(&mut self, item: R) -> Option<T>
         where T: Ord
     {
-        todo!()
+        let item = item.borrow();
+        self.delete_with(|el| item.cmp(el))
     }
 
     fn delete_with<F>(&mut self, f: F) -> Option<T>
         where F: FnMut(&T) -> Ordering
     {
-        todo!()
+        // `f` may keep state, so it can't be called again while the tree is being rebalanced
+        let rank = self.search_rank_with(f).map(|(rank, _)| rank)?;
+        self.delete_rank(rank)
+    }
+
+    fn delete_rank(&mut self, rank: usize) -> Option<T> {
+        if rank >= self.len() {
+            return None;
+        }
+        let mut root = self.root.take().expect("tree with a valid rank has a root");
+        if !is_red(&root.left) && !is_red(&root.right) {
+            root.red = true;
+        }
+        let (root, value) = RBNode::delete_at(root, rank);
+        self.root = root;
+        if let Some(root) = self.root.as_mut() {
+            root.red = false;
+        }
+        Some(value)
     }
 
-    fn search<R: std::borrow::Borrow<T>>(&self, item: R) -> Option<&T>
+    fn search<R: Borrow<T>>(&self, item: R) -> Option<&T>
         where T: Ord
     {
         let ptr = item.borrow();
@@ -37,73 +73,286 @@ impl<T> RankedTree<T> for RBTree<T>
     }
 
     fn search_with<F>(&self, f: F) -> Option<&T>
-        where F: FnMut(&T) -> Ordering 
+        where F: FnMut(&T) -> Ordering
     {
         self.node_search_with(f).map(|node| &node.value)
     }
 
-    fn search_mut<R: std::borrow::Borrow<T>>(&mut self, item: R) -> Option<&mut T>
+    fn search_rank_with<F>(&self, mut f: F) -> Option<(usize, &T)>
+        where F: FnMut(&T) -> Ordering
+    {
+        let mut node_opt = self.root.as_ref();
+        let mut rank = 0;
+        while let Some(node) = node_opt {
+            match f(&node.value) {
+                Ordering::Less => node_opt = node.left.as_ref(),
+                Ordering::Equal => return Some((rank + size(&node.left), &node.value)),
+                Ordering::Greater => {
+                    rank += size(&node.left) + 1;
+                    node_opt = node.right.as_ref();
+                }
+            }
+        }
+        None
+    }
+
+    fn search_mut<R: Borrow<T>>(&mut self, item: R) -> Option<&mut T>
         where T: Ord
-    { 
+    {
         let ptr = item.borrow();
         self.search_with_mut(|el| ptr.cmp(el))
     }
 
     fn search_with_mut<F>(&mut self, f: F) -> Option<&mut T>
-        where F: FnMut(&T) -> Ordering 
+        where F: FnMut(&T) -> Ordering
     {
         self.node_search_with_mut(f).map(|node| &mut node.value)
     }
 
     fn get(&self, mut rank: usize) -> Option<&T> {
-        let mut node_opt: Option<&Box<RBNode<T>>> = self.root.as_ref();
+        let mut node_opt = self.root.as_ref();
+        while let Some(node) = node_opt {
+            let left_size = size(&node.left);
+            match rank.cmp(&left_size) {
+                Ordering::Less => node_opt = node.left.as_ref(),
+                Ordering::Equal => return Some(&node.value),
+                Ordering::Greater => {
+                    rank -= left_size + 1;
+                    node_opt = node.right.as_ref();
+                }
+            }
+        }
+        None
+    }
 
+    fn get_mut(&mut self, mut rank: usize) -> Option<&mut T>
+    {
+        let mut node_opt = self.root.as_mut();
+        while let Some(node) = node_opt {
+            let left_size = size(&node.left);
+            match rank.cmp(&left_size) {
+                Ordering::Less => node_opt = node.left.as_mut(),
+                Ordering::Equal => return Some(&mut node.value),
+                Ordering::Greater => {
+                    rank -= left_size + 1;
+                    node_opt = node.right.as_mut();
+                }
+            }
+        }
         None
     }
 
-    fn get_mut(&mut self, rank: usize) -> Option<&mut T>
+    fn modify<F, R>(&mut self, rank: usize, f: F) -> Option<R>
+        where F: FnOnce(&mut T) -> R
     {
-        todo!()
+        if rank >= self.len() {
+            return None;
+        }
+        Some(RBNode::modify_at(self.root.as_mut().expect("tree with a valid rank has a root"), rank, f))
     }
 
     fn insert_rank(&mut self, rank: usize, new: T)
     {
-        todo!()
+        if rank > self.len() {
+            return;
+        }
+        let mut root = RBNode::insert_at(self.root.take(), rank, new);
+        root.red = false;
+        self.root = Some(root);
     }
 
     fn insert(&mut self, new: T)
-        where T: Ord 
+        where T: Ord
     {
-        todo!()
+        let mut root = RBNode::insert_ord(self.root.take(), new);
+        root.red = false;
+        self.root = Some(root);
     }
 }
 
-impl<T> RBNode<T>
+impl<T: Augmented> RBNode<T>
 {
     fn new(value: T) -> Self
     {
-        Self { value, left: None, right: None, red: false }
+        // new nodes are always red, and are joined to their parent's 3- or 4-node
+        let mut node = Self { value, left: None, right: None, red: true, size: 1 };
+        node.fix();
+        node
+    }
+
+    // recomputes the size and augmented data of this node from its children
+    fn fix(&mut self)
+    {
+        self.size = 1 + size(&self.left) + size(&self.right);
+        self.value.update(self.left.as_deref().map(|n| &n.value), self.right.as_deref().map(|n| &n.value));
     }
 
     fn rotate_left(mut node: Box<RBNode<T>>) -> Box<RBNode<T>>
     {
-        let mut right = node.right.unwrap();
+        let mut right = node.right.take().unwrap();
         node.right = right.left.take();
         right.red = node.red;
         node.red = true;
+        node.fix();
         right.left = Some(node);
+        right.fix();
         right
     }
 
     fn rotate_right(mut node: Box<RBNode<T>>) -> Box<RBNode<T>>
     {
-        let mut left = node.left.unwrap();
+        let mut left = node.left.take().unwrap();
         node.left = left.right.take();
         left.red = node.red;
         node.red = true;
+        node.fix();
         left.right = Some(node);
+        left.fix();
         left
     }
+
+    fn flip_colors(node: &mut RBNode<T>)
+    {
+        node.red = !node.red;
+        for child in [node.left.as_mut(), node.right.as_mut()].into_iter().flatten() {
+            child.red = !child.red;
+        }
+    }
+
+    // restores the left-leaning red-black invariants on the way back up the tree
+    fn balance(mut node: Box<RBNode<T>>) -> Box<RBNode<T>>
+    {
+        if is_red(&node.right) && !is_red(&node.left) {
+            node = Self::rotate_left(node);
+        }
+        if is_red(&node.left) && is_red_left(&node.left) {
+            node = Self::rotate_right(node);
+        }
+        if is_red(&node.left) && is_red(&node.right) {
+            Self::flip_colors(&mut node);
+        }
+        node.fix();
+        node
+    }
+
+    // assuming that `node` is red and both of its children are black, makes its left child or one
+    // of its left child's children red
+    fn move_red_left(mut node: Box<RBNode<T>>) -> Box<RBNode<T>>
+    {
+        Self::flip_colors(&mut node);
+        if node.right.as_ref().is_some_and(|r| is_red(&r.left)) {
+            node.right = node.right.take().map(Self::rotate_right);
+            node = Self::rotate_left(node);
+            Self::flip_colors(&mut node);
+        }
+        node
+    }
+
+    // assuming that `node` is red and both of its children are black, makes its right child or
+    // one of its right child's children red
+    fn move_red_right(mut node: Box<RBNode<T>>) -> Box<RBNode<T>>
+    {
+        Self::flip_colors(&mut node);
+        if is_red_left(&node.left) {
+            node = Self::rotate_right(node);
+            Self::flip_colors(&mut node);
+        }
+        node
+    }
+
+    fn insert_at(link: Link<T>, rank: usize, value: T) -> Box<RBNode<T>>
+    {
+        let mut node = match link {
+            Some(node) => node,
+            None => return Box::new(Self::new(value))
+        };
+        let left_size = size(&node.left);
+        if rank <= left_size {
+            node.left = Some(Self::insert_at(node.left.take(), rank, value));
+        } else {
+            node.right = Some(Self::insert_at(node.right.take(), rank - left_size - 1, value));
+        }
+        Self::balance(node)
+    }
+
+    fn insert_ord(link: Link<T>, value: T) -> Box<RBNode<T>>
+        where T: Ord
+    {
+        let mut node = match link {
+            Some(node) => node,
+            None => return Box::new(Self::new(value))
+        };
+        match value.cmp(&node.value) {
+            Ordering::Less => node.left = Some(Self::insert_ord(node.left.take(), value)),
+            Ordering::Greater => node.right = Some(Self::insert_ord(node.right.take(), value)),
+            Ordering::Equal => return node // same as ScapegoatTree, equal values aren't inserted
+        }
+        Self::balance(node)
+    }
+
+    // deletes the node with the given rank in the subtree rooted at `node` (which must be red, or
+    // have a red left child), returning the new root of the subtree and the deleted value
+    fn delete_at(mut node: Box<RBNode<T>>, rank: usize) -> (Link<T>, T)
+    {
+        let value;
+        if rank < size(&node.left) {
+            if !is_red(&node.left) && !is_red_left(&node.left) {
+                node = Self::move_red_left(node);
+            }
+            // the moves only ever add nodes to the left subtree, so `rank` is still in it
+            let (left, deleted) = Self::delete_at(node.left.take().expect("rank is in left subtree"), rank);
+            node.left = left;
+            value = deleted;
+        } else {
+            if is_red(&node.left) {
+                node = Self::rotate_right(node);
+            }
+            if rank == size(&node.left) && node.right.is_none() {
+                // in a left-leaning tree, a node without a right child has no left child either
+                return (None, node.value);
+            }
+            if !is_red(&node.right) && !is_red_left(&node.right) {
+                node = Self::move_red_right(node);
+            }
+            let left_size = size(&node.left);
+            if rank == left_size {
+                let (right, min) = Self::delete_min(node.right.take().expect("node has a right child"));
+                node.right = right;
+                value = replace(&mut node.value, min);
+            } else {
+                let (right, deleted) = Self::delete_at(node.right.take().expect("rank is in right subtree"), rank - left_size - 1);
+                node.right = right;
+                value = deleted;
+            }
+        }
+        (Some(Self::balance(node)), value)
+    }
+
+    fn delete_min(mut node: Box<RBNode<T>>) -> (Link<T>, T)
+    {
+        if node.left.is_none() {
+            return (None, node.value);
+        }
+        if !is_red(&node.left) && !is_red_left(&node.left) {
+            node = Self::move_red_left(node);
+        }
+        let (left, min) = Self::delete_min(node.left.take().expect("node has a left child"));
+        node.left = left;
+        (Some(Self::balance(node)), min)
+    }
+
+    fn modify_at<F, R>(node: &mut RBNode<T>, rank: usize, f: F) -> R
+        where F: FnOnce(&mut T) -> R
+    {
+        let left_size = size(&node.left);
+        let ret = match rank.cmp(&left_size) {
+            Ordering::Less => Self::modify_at(node.left.as_mut().expect("rank is in left subtree"), rank, f),
+            Ordering::Equal => f(&mut node.value),
+            Ordering::Greater => Self::modify_at(node.right.as_mut().expect("rank is in right subtree"), rank - left_size - 1, f)
+        };
+        node.fix();
+        ret
+    }
 }
 
 impl<T> RBTree<T>
@@ -141,3 +390,180 @@ impl<T> RBTree<T>
         None
     }
 }
+
+impl<T> Default for RBTree<T>
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+fn size<T>(link: &Link<T>) -> usize
+{
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+fn is_red<T>(link: &Link<T>) -> bool
+{
+    link.as_ref().is_some_and(|node| node.red)
+}
+
+// whether the left child of the node at `link` is red
+fn is_red_left<T>(link: &Link<T>) -> bool
+{
+    link.as_ref().is_some_and(|node| is_red(&node.left))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RBNode, RBTree, is_red, Link};
+    use crate::ranked_tree::{Augmented, RankedTree};
+    use std::cmp::Ordering;
+
+    #[derive(Debug)]
+    struct Sum {
+        value: usize,
+        total: usize
+    }
+
+    // only the value is compared, since the total depends on where the node is in the tree
+    impl PartialEq for Sum {
+        fn eq(&self, other: &Sum) -> bool {
+            self.value == other.value
+        }
+    }
+
+    impl Eq for Sum {}
+
+    impl PartialOrd for Sum {
+        fn partial_cmp(&self, other: &Sum) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Sum {
+        fn cmp(&self, other: &Sum) -> Ordering {
+            self.value.cmp(&other.value)
+        }
+    }
+
+    impl Sum {
+        fn new(value: usize) -> Self {
+            Sum{ value, total: value }
+        }
+    }
+
+    impl Augmented for Sum {
+        fn update(&mut self, left: Option<&Self>, right: Option<&Self>) {
+            self.total = left.map_or(0, |n| n.total) + self.value + right.map_or(0, |n| n.total);
+        }
+    }
+
+    // checks the red-black invariants, sizes, and augmented data; returns the black height
+    fn check<T: Augmented>(link: &Link<T>, total: &dyn Fn(&T) -> usize) -> usize {
+        match link {
+            None => 1,
+            Some(node) => {
+                assert!(!is_red(&node.right), "right-leaning red link");
+                assert!(!(node.red && is_red(&node.left)), "two red links in a row");
+                assert_eq!(node.size, 1 + super::size(&node.left) + super::size(&node.right));
+                let children = node.left.as_ref().map_or(0, |n| total(&n.value)) + node.right.as_ref().map_or(0, |n| total(&n.value));
+                assert!(total(&node.value) >= children);
+                let left = check(&node.left, total);
+                assert_eq!(left, check(&node.right, total), "unbalanced black height");
+                left + if node.red { 0 } else { 1 }
+            }
+        }
+    }
+
+    fn check_tree(tree: &RBTree<Sum>) {
+        check(&tree.root, &|s: &Sum| s.total);
+        let expected = (0..tree.len()).map(|i| tree.get(i).unwrap().value).sum::<usize>();
+        assert_eq!(tree.root.as_ref().map_or(0, |n| n.value.total), expected);
+    }
+
+    fn values(tree: &RBTree<Sum>) -> Vec<usize> {
+        (0..tree.len()).map(|i| tree.get(i).unwrap().value).collect()
+    }
+
+    #[test]
+    fn insert_rank_and_get() {
+        let mut tree = RBTree::new();
+        let mut expected = Vec::new();
+        for i in 0..200 {
+            let rank = (i * 7) % (expected.len() + 1);
+            tree.insert_rank(rank, Sum::new(i));
+            expected.insert(rank, i);
+            check_tree(&tree);
+        }
+        assert_eq!(values(&tree), expected);
+        assert!(tree.get(200).is_none());
+        tree.insert_rank(500, Sum::new(0));
+        assert_eq!(tree.len(), 200);
+    }
+
+    #[test]
+    fn insert_ord_and_search() {
+        let mut tree = RBTree::new();
+        for i in (0..100).rev() {
+            tree.insert(Sum::new(i * 2));
+        }
+        tree.insert(Sum::new(10));
+        check_tree(&tree);
+        assert_eq!(values(&tree), (0..100).map(|i| i * 2).collect::<Vec<usize>>());
+        assert!(tree.search(Sum::new(42)).is_some());
+        assert!(tree.search(Sum::new(43)).is_none());
+        assert_eq!(tree.search_rank_with(|s| 42.cmp(&s.value)).map(|(rank, _)| rank), Some(21));
+    }
+
+    #[test]
+    fn delete_rank() {
+        let mut tree = RBTree::new();
+        let mut expected = Vec::new();
+        for i in 0..300 {
+            tree.insert_rank(i, Sum::new(i));
+            expected.push(i);
+        }
+        let mut i = 0;
+        while !expected.is_empty() {
+            let rank = (i * 13) % expected.len();
+            assert_eq!(tree.delete_rank(rank).unwrap().value, expected.remove(rank));
+            check_tree(&tree);
+            i += 1;
+        }
+        assert!(tree.root.is_none());
+        assert!(tree.delete_rank(0).is_none());
+    }
+
+    #[test]
+    fn delete_with() {
+        let mut tree = RBTree::new();
+        for i in 0..100 {
+            tree.insert(Sum::new(i));
+        }
+        for i in (0..100).step_by(3) {
+            assert_eq!(tree.delete(Sum::new(i)).unwrap().value, i);
+        }
+        assert!(tree.delete_with(|s| 3.cmp(&s.value)).is_none());
+        check_tree(&tree);
+        assert_eq!(values(&tree), (0..100).filter(|i| i % 3 != 0).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn modify_updates_ancestors() {
+        let mut tree = RBTree::new();
+        for i in 0..50 {
+            tree.insert_rank(i, Sum::new(1));
+        }
+        tree.modify(17, |s| s.value = 100);
+        check_tree(&tree);
+        assert_eq!(tree.root.as_ref().unwrap().value.total, 149);
+        assert!(tree.modify(50, |s| s.value = 0).is_none());
+    }
+
+    #[test]
+    fn new_node_is_red() {
+        assert!(RBNode::new(Sum::new(0)).red);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::ranked_tree::{Augmented, RankedTree};
 use core::borrow::Borrow;
 use core::cmp::{max, Ordering};
 use std::ops::Index;
@@ -9,21 +10,32 @@ use std::ops::Index;
 // making insertion quicker but lookups and deletions slower, and vice versa for a low α. Therefore
 // in practical applications, an α can be chosen depending on how frequently these actions should
 // be performed." ([2])
+// Since the tree is stored in an array, the depth of the tree also decides how much memory it
+// takes up: a tree with depth d needs an array of length 2^(d + 1).
+
+/// A scapegoat tree stored in an array, indexed by rank.
+///
+/// Lookups are O(log n) and insertions are amortized O(log n). Deletions rebuild the subtree
+/// under the deleted element.
 pub struct ScapegoatTree<T> {
+    #[doc(hidden)]
     tree: Vec<Option<T>>,
+    #[doc(hidden)]
+    sizes: Vec<usize>, // size of the subtree rooted at each index (only meaningful for valid indices)
+    #[doc(hidden)]
     alpha_reciprocal: f32,
+    #[doc(hidden)]
     size: usize,
+    #[doc(hidden)]
     max_size: usize
 }
 
-impl<T> ScapegoatTree<T> {
-    const ROOT: usize = 1;
-
-    pub fn new(alpha: f32) -> Self {
-        ScapegoatTree{ tree: Vec::new(), size: 0, max_size: 0, alpha_reciprocal: (1.0 / alpha) }
+impl<T: Augmented> RankedTree<T> for ScapegoatTree<T> {
+    fn len(&self) -> usize {
+        self.size
     }
 
-    pub fn delete<R: Borrow<T>>(&mut self, item: R) -> Option<T>
+    fn delete<R: Borrow<T>>(&mut self, item: R) -> Option<T>
     where
         T: Ord
     {
@@ -31,70 +43,22 @@ impl<T> ScapegoatTree<T> {
         self.delete_with(|tree_el| item.cmp(tree_el))
     }
 
-    pub fn delete_with<F>(&mut self, f: F) -> Option<T>
+    fn delete_with<F>(&mut self, f: F) -> Option<T>
     where
         F: FnMut(&T) -> Ordering
     {
-        if let Some(idx) = self.idx_search_with(f) {
-            let (left_valid, right_valid) = (self.is_valid(left(idx)), self.is_valid(right(idx)));
-            let el = if !left_valid && !right_valid {
-                // has no children, can take directly
-                self.tree[idx].take()
-            } else if left_valid != right_valid {
-                // has one child, swap and then take
-                let child = if left_valid { left(idx) } else { right(idx) };
-                self.tree.swap(idx, child);
-                self.tree[child].take()
-            } else {
-                // has two children, do hibbard deletion
-                // try to do predecessor/successor about half each
-                // not guaranteed, but tree rebalances itself anyway
-                let new_node = if idx % 2 == 0 {
-                    // prececessor
-                    let mut node = left(idx);
-                    let mut right_ = right(node);
-                    while self.is_valid(right_) {
-                        node = right_;
-                        right_ = right(node);
-                    }
-                    let left_ = left(node);
-                    if self.is_valid(left_) {
-                        self.tree.swap(node, left_);
-                        left_
-                    } else {
-                        node
-                    }
-                } else {
-                    // successor
-                    let mut node = right(idx);
-                    let mut left_ = left(node);
-                    while self.is_valid(left_) {
-                        node = left_;
-                        left_ = left(node);
-                    }
-                    let right_ = right(node);
-                    if self.is_valid(right_) {
-                        self.tree.swap(node, right_);
-                        right_
-                    } else {
-                        node
-                    }
-                };
-                self.tree.swap(new_node, idx);
-                self.tree[new_node].take()
-            };
-            self.size -= 1;
-            let alpha = 1.0 / self.alpha_reciprocal;
-            if (self.size as f32) < alpha * (self.max_size as f32) {
-                self.rebuild(Self::ROOT, Some(self.size));
-                self.max_size = self.size;
-            }
-            return el;
+        self.idx_search_with(f).map(|idx| self.remove(idx))
+    }
+
+    fn delete_rank(&mut self, rank: usize) -> Option<T> {
+        if rank >= self.size {
+            return None;
         }
-        None
+        let idx = self.idx_get(rank);
+        Some(self.remove(idx))
     }
 
-    pub fn search<R: Borrow<T>>(&self, item: R) -> Option<&T>
+    fn search<R: Borrow<T>>(&self, item: R) -> Option<&T>
     where
         T: Ord
     {
@@ -102,14 +66,33 @@ impl<T> ScapegoatTree<T> {
         self.search_with(|tree_el| item.cmp(tree_el))
     }
 
-    pub fn search_with<F>(&self, f: F) -> Option<&T>
+    fn search_with<F>(&self, f: F) -> Option<&T>
     where
         F: FnMut(&T) -> Ordering
     {
         self.idx_search_with(f).map(|idx| self.tree.index(idx).as_ref().expect("idx holds node"))
     }
 
-    pub fn search_mut<R: Borrow<T>>(&mut self, item: R) -> Option<&mut T>
+    fn search_rank_with<F>(&self, mut f: F) -> Option<(usize, &T)>
+    where
+        F: FnMut(&T) -> Ordering
+    {
+        let mut node = Self::ROOT;
+        let mut rank = 0;
+        while let Some(el) = self.tree.get(node).and_then(|o| o.as_ref()) {
+            match f(el) {
+                Ordering::Less => node = left(node),
+                Ordering::Greater => {
+                    rank += self.size(left(node)) + 1;
+                    node = right(node);
+                },
+                Ordering::Equal => return Some((rank + self.size(left(node)), el))
+            };
+        }
+        None
+    }
+
+    fn search_mut<R: Borrow<T>>(&mut self, item: R) -> Option<&mut T>
     where
         T: Ord
     {
@@ -117,52 +100,72 @@ impl<T> ScapegoatTree<T> {
         self.search_with_mut(|tree_el| item.cmp(tree_el))
     }
 
-    pub fn search_with_mut<F>(&mut self, f: F) -> Option<&mut T>
+    fn search_with_mut<F>(&mut self, f: F) -> Option<&mut T>
     where
         F: FnMut(&T) -> Ordering
     {
         self.idx_search_with(f).map(|idx| self.tree.get_mut(idx).expect("idx is in bounds").as_mut().expect("idx holds node"))
     }
 
-    pub fn get(&self, mut rank: usize) -> Option<&T> {
+    fn get(&self, rank: usize) -> Option<&T> {
         if rank >= self.size {
             return None;
         }
 
-        let idx = self.get_recursive(Self::ROOT, &mut rank).expect("valid rank should yield valid index");
+        let idx = self.idx_get(rank);
         Some(self.tree.index(idx).as_ref().expect("idx holds node"))
     }
 
-    pub fn get_mut(&mut self, mut rank: usize) -> Option<&mut T> {
+    fn get_mut(&mut self, rank: usize) -> Option<&mut T> {
         if rank >= self.size {
             return None;
         }
 
-        let idx = self.get_recursive(Self::ROOT, &mut rank).expect("valid rank should yield valid index");
+        let idx = self.idx_get(rank);
         Some(self.tree.get_mut(idx).expect("idx is in bounds").as_mut().expect("idx holds node"))
     }
 
-    pub fn insert_rank(&mut self, mut rank: usize, new: T) {
+    fn modify<F, R>(&mut self, rank: usize, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut T) -> R
+    {
+        if rank >= self.size {
+            return None;
+        }
+
+        let idx = self.idx_get(rank);
+        let ret = f(self.tree.get_mut(idx).expect("idx is in bounds").as_mut().expect("idx holds node"));
+        self.fix_ancestors(idx);
+        Some(ret)
+    }
+
+    fn insert_rank(&mut self, mut rank: usize, new: T) {
         if rank > self.size {
             return;
         }
-        let idx = self.insert_rank_recursive(Self::ROOT, &mut rank).expect("valid rank should yield valid index");
-        self.put(idx, new);
-        self.size += 1;
-        self.max_size = max(self.size, self.max_size);
-
-        if log2(idx) >= self.deep_height() {
-            self.scapegoat(idx);
+        let mut node = Self::ROOT;
+        let mut depth = 0;
+        while self.is_valid(node) {
+            let left_size = self.size(left(node));
+            if rank <= left_size {
+                node = left(node);
+            } else {
+                rank -= left_size + 1;
+                node = right(node);
+            }
+            depth += 1;
         }
+        self.put(node, new);
+        self.inserted(node, depth);
     }
 
-    pub fn insert(&mut self, new: T)
+    fn insert(&mut self, new: T)
     where
         T: Ord
     {
         let mut node = Self::ROOT;
         let mut depth = 0;
-        while let Some(el) = self.tree.get(node).map(|o| o.as_ref()).flatten() {
+        while let Some(el) = self.tree.get(node).and_then(|o| o.as_ref()) {
             match new.cmp(el) {
                 Ordering::Less => node = left(node),
                 Ordering::Greater => node = right(node),
@@ -171,54 +174,66 @@ impl<T> ScapegoatTree<T> {
             depth += 1;
         }
         self.put(node, new);
-        self.size += 1;
-        self.max_size = max(self.size, self.max_size);
-
-        if depth >= self.deep_height() {
-            self.scapegoat(node);
-        }
+        self.inserted(node, depth);
     }
+}
 
-    fn get_recursive(&self, idx: usize, rank: &mut usize) -> Option<usize> {
-        if !self.is_valid(idx) {
-            return None;
-        }
-
-        if let ret @ Some(_) = self.get_recursive(left(idx), rank) {
-            return ret;
-        }
-        if *rank == 0 {
-            return Some(idx);
-        }
+impl<T: Augmented> ScapegoatTree<T> {
+    const ROOT: usize = 1;
 
-        *rank -= 1;
+    /// Alpha used by [`Default`]: the tree is stored in an array, so a lower alpha than usual
+    /// keeps it (and its memory use) shallower.
+    pub const DEFAULT_ALPHA: f32 = 0.6;
 
-        self.get_recursive(right(idx), rank)
+    /// Create an empty tree; `alpha` must be between 0.5 and 1 (exclusive).
+    pub fn new(alpha: f32) -> Self {
+        ScapegoatTree{ tree: Vec::new(), sizes: Vec::new(), size: 0, max_size: 0, alpha_reciprocal: (1.0 / alpha) }
     }
 
-    fn insert_rank_recursive(&self, idx: usize, rank: &mut usize) -> Option<usize> {
-        if !self.is_valid(idx) {
-            return None;
-        }
+    fn inserted(&mut self, idx: usize, depth: usize) {
+        self.size += 1;
+        self.max_size = max(self.size, self.max_size);
+        self.fix_ancestors(idx);
 
-        let left_ = left(idx);
-        if let ret @ Some(_) = self.insert_rank_recursive(left_, rank) {
-            return ret;
-        }
-        if *rank == 0 {
-            return Some(left_);
+        if depth > self.deep_height() {
+            self.scapegoat(idx);
         }
+    }
 
-        *rank -= 1;
+    // removing a node from an array-backed tree means moving its descendants, so the whole subtree
+    // is rebuilt without it; this is O(size of subtree), which is O(log n) on average
+    fn remove(&mut self, idx: usize) -> T {
+        let position = self.size(left(idx));
+        let mut sorted_subtree = self.pull_subtree(idx, Some(self.size(idx)));
+        let el = sorted_subtree.remove(position).expect("subtree should only contain valid values");
+        if !sorted_subtree.is_empty() {
+            let hi = sorted_subtree.len() - 1;
+            self.put_subtree(idx, 0, hi, &mut sorted_subtree);
+        }
+        self.size -= 1;
+        self.fix_ancestors(idx / 2);
 
-        let right_ = right(idx);
-        if let ret @ Some(_) = self.insert_rank_recursive(right_, rank) {
-            return ret;
+        let alpha = 1.0 / self.alpha_reciprocal;
+        if (self.size as f32) < alpha * (self.max_size as f32) {
+            self.rebuild(Self::ROOT, Some(self.size));
+            self.max_size = self.size;
         }
-        if *rank == 0 {
-            Some(right_)
-        } else {
-            None
+        el
+    }
+
+    // index of the node with the given rank; the rank must be less than the size of the tree
+    fn idx_get(&self, mut rank: usize) -> usize {
+        let mut node = Self::ROOT;
+        loop {
+            let left_size = self.size(left(node));
+            match rank.cmp(&left_size) {
+                Ordering::Less => node = left(node),
+                Ordering::Equal => return node,
+                Ordering::Greater => {
+                    rank -= left_size + 1;
+                    node = right(node);
+                }
+            }
         }
     }
 
@@ -227,7 +242,7 @@ impl<T> ScapegoatTree<T> {
         F: FnMut(&T) -> Ordering
     {
         let mut node = Self::ROOT;
-        while let Some(el) = self.tree.get(node).map(|o| o.as_ref()).flatten() {
+        while let Some(el) = self.tree.get(node).and_then(|o| o.as_ref()) {
             match f(el) {
                 Ordering::Less => node = left(node),
                 Ordering::Greater => node = right(node),
@@ -239,20 +254,16 @@ impl<T> ScapegoatTree<T> {
 
     fn scapegoat(&mut self, mut node: usize) {
         let mut i = 0; // 0 = current node, i + 1 = parent of i
-        let mut size = 1; // size of current node
-        let mut size_sibling = self.size(sibling(node)); // size of current node's sibling (other child of this node's parent)
-        loop {
+        while node != Self::ROOT {
             node /= 2; // traverse to parent
             i += 1; // increment reverse depth / parent distance
-            size = 1 + size + size_sibling;
-            if i > self.h_alpha(size) {
+            if i > self.h_alpha(self.size(node)) {
                 // always satisfied by root, according to [1]
                 // and using this criteria may result in more balanced trees on average
-                self.rebuild(node, Some(size));
                 break;
             }
-            size_sibling = self.size(sibling(node));
         }
+        self.rebuild(node, Some(self.size(node)));
     }
 
     fn rebuild(&mut self, scapegoat: usize, subtree_size: Option<usize>) {
@@ -262,16 +273,17 @@ impl<T> ScapegoatTree<T> {
         }
     }
 
+    // lo <= hi, both inclusive
     fn put_subtree(&mut self, idx: usize, lo: usize, hi: usize, subtree: &mut Vec<Option<T>>) {
-        if lo > hi {
-            return;
-        }
         let m = median(lo, hi);
         self.put(idx, subtree[m].take().expect("subtree should only contain valid values"));
-        if m > 0 {
+        if m > lo {
             self.put_subtree(left(idx), lo, m - 1, subtree);
         }
-        self.put_subtree(right(idx), m + 1, hi, subtree);
+        if m < hi {
+            self.put_subtree(right(idx), m + 1, hi, subtree);
+        }
+        self.fix(idx);
     }
 
     fn pull_subtree(&mut self, idx: usize, subtree_size: Option<usize>) -> Vec<Option<T>> {
@@ -292,8 +304,27 @@ impl<T> ScapegoatTree<T> {
         if idx >= self.tree.len() {
             self.tree.reserve(idx - self.tree.len() + 1); // may reserve more than necessary to prevent future reallocations
             self.tree.resize_with(idx + 1, || None); // fill new places with None, new len = idx + 1
+            self.sizes.resize(idx + 1, 0);
         }
         self.tree[idx] = Some(value);
+        self.sizes[idx] = 1;
+    }
+
+    // recomputes the size and augmented data of the node at idx from its children
+    fn fix(&mut self, idx: usize) {
+        self.sizes[idx] = 1 + self.size(left(idx)) + self.size(right(idx));
+        // children are always after their parent in the array
+        let (head, tail) = self.tree.split_at_mut(idx + 1);
+        let left_ = tail.get(left(idx) - idx - 1).and_then(|o| o.as_ref());
+        let right_ = tail.get(right(idx) - idx - 1).and_then(|o| o.as_ref());
+        head[idx].as_mut().expect("idx holds node").update(left_, right_);
+    }
+
+    fn fix_ancestors(&mut self, mut idx: usize) {
+        while idx >= Self::ROOT {
+            self.fix(idx);
+            idx /= 2;
+        }
     }
 
     fn is_valid(&self, idx: usize) -> bool {
@@ -301,17 +332,7 @@ impl<T> ScapegoatTree<T> {
     }
 
     fn size(&self, root: usize) -> usize {
-        let mut stack = Vec::new();
-        let mut size = 0;
-        stack.push(root);
-        while let Some(node) = stack.pop() {
-            if self.is_valid(node) {
-                size += 1;
-                stack.push(left(node));
-                stack.push(right(node));
-            }
-        }
-        size
+        if self.is_valid(root) { self.sizes[root] } else { 0 }
     }
 
     fn deep_height(&self) -> usize {
@@ -324,6 +345,12 @@ impl<T> ScapegoatTree<T> {
     }
 }
 
+impl<T: Augmented> Default for ScapegoatTree<T> {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_ALPHA)
+    }
+}
+
 // both inclusive
 const fn median(left: usize, right: usize) -> usize {
     (left + right) >> 1
@@ -337,28 +364,10 @@ const fn right(parent: usize) -> usize {
     2*parent + 1
 }
 
-const fn sibling(child: usize) -> usize {
-    // children are 2n and 2n + 1
-    // so sibling is +1 if even, and -1 if odd
-    if child % 2 == 0 {
-        child + 1
-    } else {
-        child - 1
-    }
-}
-
-const fn log2(mut n: usize) -> usize {
-    let mut count = 0;
-    while n > 0 {
-        n >>= 1;
-        count += 1;
-    }
-    return count;
-}
-
 #[cfg(test)]
 mod tests {
     use super::ScapegoatTree;
+    use crate::ranked_tree::{Augmented, RankedTree};
     use std::cmp::Ordering;
 
     #[derive(Clone, Debug)]
@@ -375,6 +384,8 @@ mod tests {
 
     impl Eq for TestStruct {}
 
+    impl Augmented for TestStruct {}
+
     impl PartialOrd for TestStruct {
         fn partial_cmp(&self, other: &TestStruct) -> Option<Ordering> {
             self.comp.partial_cmp(&other.comp)
@@ -566,4 +577,70 @@ mod tests {
         let search = 123;
         assert!(tree.search_with_mut(|o| search.cmp(&o.non_comp).reverse()).is_none());
     }
+
+    #[test]
+    pub fn test_delete_rank() {
+        let mut tree = setup();
+        for i in (0..100).step_by(2) {
+            let el = tree.delete_rank(i / 2).unwrap();
+            assert_eq!(el.comp, i);
+        }
+        assert_eq!(tree.len(), 50);
+        for i in 0..50 {
+            assert_eq!(tree.get(i).unwrap().comp, 2 * i + 1);
+        }
+        assert!(tree.delete_rank(50).is_none());
+    }
+
+    #[test]
+    pub fn test_delete_keeps_subtrees() {
+        let mut tree = setup();
+        for i in (0..100).rev().step_by(3) {
+            assert!(equals(&tree.delete(TestStruct{ comp: i, non_comp: 0 }).unwrap(), &TestStruct{ comp: i, non_comp: 100 - i }));
+        }
+        let remaining = (0..100).filter(|i| (99 - i) % 3 != 0).collect::<Vec<usize>>();
+        assert_eq!(tree.len(), remaining.len());
+        for (rank, i) in remaining.into_iter().enumerate() {
+            assert_eq!(tree.get(rank).unwrap().comp, i);
+        }
+    }
+
+    #[test]
+    pub fn test_augmented() {
+        struct Sum {
+            value: usize,
+            left_total: usize,
+            total: usize
+        }
+
+        impl Augmented for Sum {
+            fn update(&mut self, left: Option<&Self>, right: Option<&Self>) {
+                self.left_total = left.map_or(0, |n| n.total);
+                self.total = self.left_total + self.value + right.map_or(0, |n| n.total);
+            }
+        }
+
+        let mut tree = ScapegoatTree::new(0.6);
+        for i in 0..200 {
+            tree.insert_rank(i / 2, Sum{ value: i, left_total: 0, total: i });
+        }
+        tree.delete_rank(10);
+        tree.modify(0, |n| n.value += 1000);
+        let values = (0..tree.len()).map(|i| tree.get(i).unwrap().value).collect::<Vec<usize>>();
+        // find the node containing the prefix sum 5000 using only the augmented data
+        let target = 5000;
+        let mut remaining = target;
+        let (rank, _) = tree.search_rank_with(|n| {
+            if remaining < n.left_total {
+                Ordering::Less
+            } else if remaining < n.left_total + n.value {
+                Ordering::Equal
+            } else {
+                remaining -= n.left_total + n.value;
+                Ordering::Greater
+            }
+        }).unwrap();
+        let before = values[..rank].iter().sum::<usize>();
+        assert!(before <= target && target < before + values[rank]);
+    }
 }
@@ -59,6 +59,12 @@ fn gen_impls(name: &Ident) -> TokenStream {
                 #name(t)
             }
         }
+
+        impl ::std::fmt::Display for #name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                self.0.fmt(f)
+            }
+        }
     };
     gen.into()
 }
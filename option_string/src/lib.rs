@@ -12,6 +12,7 @@ fn impl_option_string(ast: &DeriveInput) -> TokenStream {
     let enum_name = &ast.ident;
     if let Data::Enum(enum_data) = &ast.data {
         let mut matches = Vec::new();
+        let mut names = Vec::new();
         for variant in enum_data.variants.iter() {
             if let Fields::Unit = variant.fields {} else {
                 panic!("#[option_string] can only be applied to enums with unit variants");
@@ -20,6 +21,9 @@ fn impl_option_string(ast: &DeriveInput) -> TokenStream {
             matches.push(quote! {
                 stringify!(#ident) => ::std::result::Result::Ok(<#enum_name>::#ident), 
             });
+            names.push(quote! {
+                <#enum_name>::#ident => stringify!(#ident),
+            });
         }
 
         let gen = quote! {
@@ -33,6 +37,14 @@ fn impl_option_string(ast: &DeriveInput) -> TokenStream {
                     }
                 }
             }
+
+            impl ::std::fmt::Display for #enum_name {
+                fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                    f.write_str(match self {
+                        #(#names)*
+                    })
+                }
+            }
         };
 
         gen.into()
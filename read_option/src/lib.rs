@@ -3,7 +3,7 @@ use proc_macro2::TokenStream as TokenStream2;
 use syn::{parse, punctuated::Punctuated, spanned::Spanned, token::Comma, Data, DeriveInput, Field, Fields, Ident};
 use quote::{format_ident, quote, quote_spanned};
 
-static ERROR_MSG: &str = "the ReadOption derive macro only works with structs named Options with named fields that implement FromStr";
+static ERROR_MSG: &str = "the ReadOption derive macro only works with structs named Options with named fields that implement FromStr and Display";
 
 #[proc_macro_derive(ReadOption)]
 pub fn read_option(item: TokenStream) -> TokenStream {
@@ -40,6 +40,11 @@ fn gen_error() -> TokenStream2 {
                 /// The option that the user requested.
                 option: String
             },
+            /// Tried to toggle an option that isn't a boolean option.
+            NotABoolOption{
+                /// The option that the user requested.
+                option: String
+            },
             /// Could not parse the value into the appropriate type.
             ValueParseError{
                 /// The message from the parser.
@@ -56,6 +61,7 @@ fn gen_error() -> TokenStream2 {
                     OptionParseError::NoEquals => write!(f, "did not find `=` in string"),
                     OptionParseError::NoValueAfterEquals => write!(f, "did not find a value after `=` in string"),
                     OptionParseError::NoMatchingOption{ option } => write!(f, "no matching option in `Options` for {}", option),
+                    OptionParseError::NotABoolOption{ option } => write!(f, "{} is not a boolean option", option),
                     OptionParseError::ValueParseError{ msg } => write!(f, "failed to parse value: {}", msg)
                 }
             }
@@ -146,9 +152,12 @@ fn gen_read_option_func(asserts: &Vec<TokenStream2>, matches: &Vec<TokenStream2>
     }
 }
 
-fn gen_set_option_func(asserts: &Vec<TokenStream2>, matches: &Vec<TokenStream2>) -> TokenStream2 {
+fn gen_set_option_func(asserts: &Vec<TokenStream2>, matches: &Vec<TokenStream2>, names: &Vec<String>, gets: &Vec<TokenStream2>, resets: &Vec<TokenStream2>, bools: &Vec<TokenStream2>) -> TokenStream2 {
     quote! {
         impl Options {
+            /// The names of all of the options, in the order they are declared.
+            pub const NAMES: &'static [&'static str] = &[#(#names),*];
+
             /// Read a single option/value pair from the passed string slice, and set the associated
             /// value.
             ///
@@ -161,6 +170,31 @@ fn gen_set_option_func(asserts: &Vec<TokenStream2>, matches: &Vec<TokenStream2>)
                     _ => Err(OptionParseError::NoMatchingOption{ option: opt_name.to_string() })
                 }
             }
+
+            /// Returns the value of the option called `opt_name`, formatted so that it could be
+            /// read back by [`Options::set_option`].
+            pub fn get_option(&self, opt_name: &str) -> Result<String, OptionParseError> {
+                match opt_name {
+                    #(#gets)*
+                    _ => Err(OptionParseError::NoMatchingOption{ option: opt_name.to_string() })
+                }
+            }
+
+            /// Set the option called `opt_name` back to its default value.
+            pub fn reset_option(&mut self, opt_name: &str) -> Result<(), OptionParseError> {
+                match opt_name {
+                    #(#resets)*
+                    _ => Err(OptionParseError::NoMatchingOption{ option: opt_name.to_string() })
+                }
+            }
+
+            /// Returns `true` if the option called `opt_name` is a boolean option.
+            pub fn is_bool_option(&self, opt_name: &str) -> bool {
+                match opt_name {
+                    #(#bools)*
+                    _ => false
+                }
+            }
         }
     }
 }
@@ -169,10 +203,15 @@ fn gen_read_option(fields: &Punctuated<Field, Comma>) -> TokenStream {
     let mut matches_read = Vec::new();
     let mut matches_set = Vec::new();
     let mut asserts = Vec::new();
+    let mut names = Vec::new();
+    let mut gets = Vec::new();
+    let mut resets = Vec::new();
+    let mut bools = Vec::new();
     let mut counter = 0;
     for field in fields.iter() {
         let ident = field.ident.as_ref().expect(ERROR_MSG);
         let ty = &field.ty;
+        names.push(ident.to_string());
 
         let assert_from_str_name = Ident::new(
             format!("_AssertFromStr{}{}", counter, ident.to_string().replace('_', "")).as_str(),
@@ -196,6 +235,22 @@ fn gen_read_option(fields: &Punctuated<Field, Comma>) -> TokenStream {
             }, 
         });
 
+        gets.push(quote! {
+            stringify!(#ident) => Ok(self.#ident.to_string()),
+        });
+
+        resets.push(quote! {
+            stringify!(#ident) => {
+                self.#ident = Options::default().#ident;
+                Ok(())
+            },
+        });
+
+        // boolean options are the ones parsed like a `bool` (see the OptionBool derive macro)
+        bools.push(quote! {
+            stringify!(#ident) => ::std::any::TypeId::of::<<#ty as ::std::str::FromStr>::Err>() == ::std::any::TypeId::of::<::std::str::ParseBoolError>(),
+        });
+
         matches_set.push(quote! {
             stringify!(#ident) => {
                 let val_object = val.parse::<#ty>()?;
@@ -210,7 +265,7 @@ fn gen_read_option(fields: &Punctuated<Field, Comma>) -> TokenStream {
     let error_enum = gen_error();
     let extract_opt_val_func = gen_extract_opt_val_func();
     let read_option_func = gen_read_option_func(&asserts, &matches_read);
-    let set_option_func = gen_set_option_func(&asserts, &matches_set);
+    let set_option_func = gen_set_option_func(&asserts, &matches_set, &names, &gets, &resets, &bools);
 
     let gen = quote! {
         #error_enum
@@ -0,0 +1,171 @@
+//! A module for copying text to (and reading text from) the system clipboard, for the `"+` and
+//! `"*` registers (see [`crate::register`]).
+//!
+//! By default, text is copied with an OSC 52 escape sequence (see [`Osc52`]). Most terminal
+//! emulators copy the text to the system clipboard when they see one, which works over SSH too.
+//! If the `clipboard_command` option is set, text is copied by running it with the text on its
+//! standard input instead (e.g. `xclip -selection clipboard`, `wl-copy` or `pbcopy`).
+//!
+//! Reading the system clipboard needs the `clipboard_paste_command` option (e.g. `xclip -selection
+//! clipboard -o`, `wl-paste -n` or `pbpaste`), since few terminal emulators answer OSC 52 queries.
+//! Without it, the `"+` and `"*` registers hold the text that was last yanked into them.
+//!
+//! Both commands are run with `sh -c`, with the `FIM_CLIPBOARD` environment variable set to
+//! `clipboard` for the `"+` register and `primary` for the `"*` register.
+use crossterm::Command;
+use std::fmt;
+use std::io::{Error, ErrorKind, Write};
+use std::process::{Command as Process, ExitStatus, Stdio};
+
+/// Enum that represents the clipboards the `"+` and `"*` registers are copied to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ClipboardKind {
+    /// The clipboard that is pasted from with Ctrl-V (the `"+` register).
+    Clipboard,
+    /// The X11 primary selection, which is pasted from with a middle click (the `"*` register).
+    Primary,
+}
+
+impl ClipboardKind {
+    /// Returns the clipboard that the register named `name` is copied to, if it is copied to one.
+    pub fn of_register(name: char) -> Option<Self> {
+        match name {
+            '+' => Some(Self::Clipboard),
+            '*' => Some(Self::Primary),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the clipboard, as it is given to clipboard commands in `FIM_CLIPBOARD`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Clipboard => "clipboard",
+            Self::Primary => "primary",
+        }
+    }
+
+    // the clipboard's parameter in OSC 52
+    fn osc52_parameter(&self) -> char {
+        match self {
+            Self::Clipboard => 'c',
+            Self::Primary => 'p',
+        }
+    }
+}
+
+/// Struct that represents the OSC 52 escape sequence, which asks the terminal emulator to copy
+/// text to a clipboard.
+///
+/// This is a crossterm [`Command`], so it can be queued with
+/// [`Terminal::q`](crate::terminal::Terminal::q).
+pub struct Osc52<'a> {
+    /// The clipboard to copy to.
+    pub kind: ClipboardKind,
+    /// The text to copy.
+    pub text: &'a str,
+}
+
+impl Command for Osc52<'_> {
+    fn write_ansi(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        write!(f, "\x1b]52;{};{}\x07", self.kind.osc52_parameter(), base64(self.text.as_bytes()))
+    }
+
+    #[cfg(windows)]
+    fn execute_winapi(&self) -> crossterm::Result<()> {
+        Ok(())
+    }
+}
+
+/// Copies text to a clipboard by running `command`, with the text on its standard input.
+///
+/// Returns an error if the command couldn't be run, or if it failed.
+pub fn copy_with_command(command: &str, kind: ClipboardKind, text: &str) -> Result<(), Error> {
+    // the command's output would be drawn over the editor
+    let mut child = shell(command, kind).stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null()).spawn()?;
+    // closed when dropped, so the command sees the end of the text
+    let written = child.stdin.take().expect("standard input should be piped").write_all(text.as_bytes());
+    // waited for even if the text couldn't be written (e.g. the command exited without reading
+    // it), so that it doesn't stay a zombie; its exit status explains more than a broken pipe
+    succeeded(child.wait()?).and(written)
+}
+
+/// Reads a clipboard by running `command`, returning what it prints.
+///
+/// Returns an error if the command couldn't be run, if it failed, or if it didn't print UTF-8.
+pub fn paste_with_command(command: &str, kind: ClipboardKind) -> Result<String, Error> {
+    let output = shell(command, kind).stdin(Stdio::null()).stderr(Stdio::null()).output()?;
+    succeeded(output.status)?;
+    String::from_utf8(output.stdout).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+fn shell(command: &str, kind: ClipboardKind) -> Process {
+    let mut process = Process::new("sh");
+    process.arg("-c").arg(command).env("FIM_CLIPBOARD", kind.name());
+    process
+}
+
+fn succeeded(status: ExitStatus) -> Result<(), Error> {
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::other(format!("clipboard command failed ({})", status)))
+    }
+}
+
+// standard base64, with padding (OSC 52 has no other way of carrying arbitrary text)
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | (u32::from(*b) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[test]
+fn test_osc52() {
+    assert_eq!(base64(b""), "");
+    assert_eq!(base64(b"f"), "Zg==");
+    assert_eq!(base64(b"fo"), "Zm8=");
+    assert_eq!(base64(b"foo"), "Zm9v");
+    assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    assert_eq!(base64("é\n".as_bytes()), "w6kK");
+
+    let mut written = String::new();
+    Osc52{ kind: ClipboardKind::Clipboard, text: "foo\nbar" }.write_ansi(&mut written).unwrap();
+    assert_eq!(written, "\x1b]52;c;Zm9vCmJhcg==\x07");
+    written.clear();
+    Osc52{ kind: ClipboardKind::Primary, text: "" }.write_ansi(&mut written).unwrap();
+    assert_eq!(written, "\x1b]52;p;\x07");
+}
+
+#[test]
+fn test_clipboard_commands() {
+    let dir = std::env::temp_dir().join(format!("fim-test-clipboard-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // a stub clipboard tool, keeping one file per clipboard
+    let script = dir.join("clip.sh");
+    std::fs::write(&script, format!("#!/bin/sh\nfile=\"{}/$FIM_CLIPBOARD\"\nif [ \"$1\" = -o ]; then cat \"$file\"; else cat > \"$file\"; fi\n", dir.display())).unwrap();
+    let copy = format!("sh {}", script.display());
+    let paste = format!("sh {} -o", script.display());
+
+    copy_with_command(&copy, ClipboardKind::Clipboard, "e\u{301}\nline").unwrap();
+    copy_with_command(&copy, ClipboardKind::Primary, "primary").unwrap();
+    assert_eq!(std::fs::read_to_string(dir.join("clipboard")).unwrap(), "e\u{301}\nline");
+    assert_eq!(paste_with_command(&paste, ClipboardKind::Clipboard).unwrap(), "e\u{301}\nline");
+    assert_eq!(paste_with_command(&paste, ClipboardKind::Primary).unwrap(), "primary");
+    assert!(copy_with_command("exit 1", ClipboardKind::Clipboard, "text").is_err());
+    // more text than a pipe holds can't be written to a command that doesn't read it, which is
+    // still waited for
+    let error = copy_with_command("exit 1", ClipboardKind::Clipboard, &"text".repeat(1 << 16)).unwrap_err();
+    assert_eq!(error.to_string(), "clipboard command failed (exit status: 1)");
+    assert!(paste_with_command("false", ClipboardKind::Clipboard).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,1039 @@
+//! A module that contains fim's ex commands (the commands entered after a ':' in
+//! [`CommandMode`](crate::context::CommandMode)).
+//!
+//! # Command Lines
+//! A command line is an optional line range, a command name, optionally a '!' (which usually means
+//! "do it anyway"), then arguments separated by whitespace. A backslash makes the next character
+//! part of the argument, so `:w my\ file` writes to `my file`.
+//!
+//! A command name can be abbreviated to any prefix that is at least as long as its shortest
+//! abbreviation (e.g. `:w`, `:wr`, ..., `:write`). If a prefix could be more than one command, the
+//! command registered first wins.
+//!
+//! ## Ranges
+//! A range is one line address, or two separated by a ',' (e.g. `:3,10d`). An address is a line
+//! number, `.` (the current line) or `$` (the last line), followed by any number of `+N` or `-N`
+//! offsets (`N` defaults to 1). An address that is only offsets is relative to the current line
+//! (e.g. `:.,+2d` and `:,+2d` are the same). `%` is the whole document (`1,$`). A range with no
+//! command (e.g. `:42`) moves the cursor to the last line of the range.
+//!
+//! # Commands
+//! | Command                | Meaning                                                           |
+//! |------------------------|-------------------------------------------------------------------|
+//! | `:q[uit][!]`           | quit (`!` discards unsaved changes)                               |
+//! | `:w[rite][!] [path]`   | write the document to its file, or to `path`                      |
+//! | `:wq[!] [path]`        | write, then quit                                                  |
+//! | `:x[it][!] [path]`     | like `:wq`, but only write if there are unsaved changes           |
+//! | `:e[dit][!] [path]`    | open `path` (or reopen the current file), discarding the document |
+//! | `:o[pen][!] [path]`    | same as `:edit`                                                   |
+//! | `:sav[eas][!] path`    | write the document to `path`, and edit `path` from now on         |
+//! | `:[range]d[elete] [x]` | delete the lines in the range (the current line by default)       |
+//! | `:[range]y[ank] [x]`   | yank the lines in the range (the current line by default)         |
+//! | `:reg[isters] [names]` | list the registers that aren't empty (only those in `names`)      |
+//! | `:di[splay] [names]`   | same as `:registers`                                              |
+//! | `:u[ndo] [N]`          | undo one change, or go to the state after change number `N`       |
+//! | `:red[o]`              | redo one change                                                   |
+//! | `:ea[rlier] [N]`       | go back `N` changes, or a time (e.g. `30s`, `2m`, `1h`, `1d`)     |
+//! | `:lat[er] [N]`         | go forward `N` changes, or a time                                 |
+//! | `:undotree`            | show the undo tree                                                |
+//! | `:se[t] [args]`        | set, show or reset options (see below)                            |
+//! | `:bind(C) key ...`     | bind a key, like a `bind` line in a config file                   |
+//! | `:bind-layout(C) ...`  | bind a key in the current layout only                             |
+//! | `:unbind(C) key`       | remove a key bind, like an `unbind` line in a config file         |
+//! | `:unbind-layout(C) key`| remove a key bind made with `:bind-layout`                        |
+//! | `:binds [C]`           | list the key binds (only those in context `C`, if given)          |
+//!
+//! `:delete` and `:yank` put the lines into register `x`, if it's given (see
+//! [`crate::register`]).
+//!
+//! ## `:set`
+//! Each argument of `:set` is one of the following (a name is the name of a field of
+//! [`Options`], like in config files):
+//! - `name=value` or `name = value`: set an option
+//! - `name?`: show the value of an option
+//! - `name&`: reset an option to its default value
+//! - `name!` or `invname`: toggle a boolean option
+//! - `name`: set a boolean option to `true`, or show the value of any other option
+//! - `noname`: set a boolean option to `false`
+//!
+//! Without arguments, `:set` shows the options that don't have their default values. Changes
+//! apply to every window right away.
+//!
+//! Pressing Tab in [`CommandMode`](crate::context::CommandMode) completes command names, and the
+//! arguments of commands that say what their arguments are (see [`Completion`]).
+//!
+//! Contexts can add their own commands with
+//! [`Editor::register_command`](crate::editor::Editor::register_command), and config files can
+//! define aliases for command lines (see [`ExCommands::add_alias`]).
+use crate::config::options::{LayoutType, OptionParseError, Options};
+use crate::config::config_error::BindParseError;
+use crate::config::keybinds::{BindInfo, KeyBinds};
+use crate::context::{ListView, RecoveryPrompt, UndoTreeView, CONTEXTS};
+use crate::delta::Differential;
+use crate::editor::{CmdLineFlags, Editor};
+use crate::register::{RegisterError, RegisterKind, Registers};
+use crate::undo::Travel;
+use crossterm::{Result, event::KeyEvent};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::read_dir;
+use std::io::Error;
+use std::iter::once;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+/// The result of running an [`ExCommand`]: a message to show on the command line, if any.
+pub type CommandResult = std::result::Result<Option<String>, CommandError>;
+
+/// How many arguments an [`ExCommand`] takes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Args {
+    /// No arguments.
+    None,
+    /// Zero or one argument.
+    Optional,
+    /// Exactly one argument.
+    One,
+    /// Any number of arguments.
+    Any,
+}
+
+/// What the arguments of an [`ExCommand`] are, for Tab completion.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Completion {
+    /// Nothing to complete.
+    Nothing,
+    /// File paths.
+    File,
+    /// Option names (the fields of [`Options`]).
+    Option,
+    /// Context names (see [`context()`](crate::context::context)).
+    Context,
+}
+
+/// Struct that represents an ex command.
+#[derive(Copy, Clone)]
+pub struct ExCommand {
+    /// The full name of the command (e.g. `write`).
+    pub name: &'static str,
+    /// The length of the shortest abbreviation of the name (e.g. 1, for `w`).
+    pub abbreviation: usize,
+    /// How many arguments the command takes.
+    pub args: Args,
+    /// Whether the command accepts a line range.
+    pub range: bool,
+    /// Whether the command accepts a '!'.
+    pub bang: bool,
+    /// What the arguments are, for Tab completion.
+    pub complete: Completion,
+    /// The function that runs the command.
+    pub run: fn(&mut Editor, &Invocation) -> CommandResult,
+}
+
+impl ExCommand {
+    /// Returns `true` if `name` is this command's name or one of its abbreviations.
+    pub fn matches(&self, name: &str) -> bool {
+        name.len() >= self.abbreviation && self.name.starts_with(name)
+    }
+}
+
+/// The arguments an [`ExCommand`] is run with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Invocation {
+    /// The lines in the range given to the command (zero-based), if there was one.
+    pub range: Option<RangeInclusive<usize>>,
+    /// Whether the command was given a '!'.
+    pub bang: bool,
+    /// The arguments to the command, with escapes removed.
+    pub args: Vec<String>,
+    /// The text after the name and '!', as it was typed (for commands with their own syntax).
+    pub raw: String,
+}
+
+/// A line address in a command line range.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Address {
+    /// A line number (one-based, like the line numbers that are shown).
+    Line(usize, isize),
+    /// The current line.
+    Current(isize),
+    /// The last line.
+    Last(isize),
+}
+
+impl Address {
+    /// Returns the zero-based line this address refers to, if it is in the document.
+    pub fn resolve(&self, current: usize, num_lines: usize) -> Option<usize> {
+        let (line, offset) = match *self {
+            Self::Line(line, offset) => (line.saturating_sub(1), offset),
+            Self::Current(offset) => (current, offset),
+            Self::Last(offset) => (num_lines.saturating_sub(1), offset),
+        };
+        line.checked_add_signed(offset).filter(|l| *l < num_lines)
+    }
+}
+
+/// The first and last lines of a command line range.
+pub type LineRange = (Address, Address);
+
+/// A parsed command line.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CommandLine {
+    /// The range before the command name, if there was one.
+    pub range: Option<LineRange>,
+    /// The name of the command, as typed (it may be abbreviated).
+    pub name: String,
+    /// Whether the name was followed by a '!'.
+    pub bang: bool,
+    /// The arguments to the command, with escapes removed.
+    pub args: Vec<String>,
+    /// The text after the name and '!', as it was typed.
+    pub raw: String,
+}
+
+impl CommandLine {
+    /// Parse a command line (without the leading ':').
+    ///
+    /// A name is letters, possibly in words joined by '-' (e.g. `bind-layout`). It can be followed
+    /// directly by a '(' (for commands like `:bind(NormalMode)`), but not by anything else
+    /// besides a '!' or whitespace.
+    pub fn parse(line: &str) -> std::result::Result<Self, CommandError> {
+        let line = line.trim_start().trim_start_matches(':').trim_start();
+        let (range, line) = parse_range(line)?;
+        let line = line.trim_start();
+        let (name, rest) = line.split_at(name_len(line));
+        let (bang, rest) = match rest.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        if !rest.is_empty() && !rest.starts_with(char::is_whitespace) && !rest.starts_with('(') {
+            return Err(CommandError::TrailingCharacters(String::from(rest)));
+        }
+        Ok(CommandLine{ range, name: String::from(name), bang, args: split_args(rest), raw: String::from(rest) })
+    }
+}
+
+/// Enum for containing errors that might occur in parsing or executing a command.
+#[derive(Debug)]
+pub enum CommandError {
+    /// There is no command with the given name.
+    NotACommand(String),
+    /// Something that isn't a '!' or whitespace came right after the command name.
+    TrailingCharacters(String),
+    /// The command needs a file name, but none was given and the window has no file.
+    NoFileName,
+    /// The command would discard unsaved changes, and wasn't given a '!'.
+    Unsaved,
+    /// The command would overwrite an existing file, and wasn't given a '!'.
+    FileExists(PathBuf),
+    /// The command would write the file of a read-only window, and wasn't given a '!'.
+    ReadOnly,
+    /// The command was given more arguments than it takes.
+    TooManyArguments,
+    /// The command needs an argument, but wasn't given one.
+    ArgumentRequired,
+    /// An argument couldn't be understood.
+    InvalidArgument(String),
+    /// The command was given a range, but doesn't take one.
+    NoRangeAllowed,
+    /// The command was given a '!', but doesn't take one.
+    NoBangAllowed,
+    /// The range is malformed or refers to lines that aren't in the document.
+    InvalidRange,
+    /// An option couldn't be set or shown.
+    OptionError(OptionParseError),
+    /// A key bind couldn't be added or removed.
+    BindError(BindParseError),
+    /// Text couldn't be put into a register.
+    RegisterError(RegisterError),
+    /// There is no custom layout with the given name.
+    NoMatchingLayout(String),
+    /// IO error (e.g. the file couldn't be written).
+    IOError(Error),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotACommand(name) => write!(f, "Not a command: {}", name),
+            Self::TrailingCharacters(rest) => write!(f, "Trailing characters: {}", rest),
+            Self::NoFileName => write!(f, "No file name"),
+            Self::Unsaved => write!(f, "No write since last change (add ! to override)"),
+            Self::FileExists(path) => write!(f, "\"{}\" exists (add ! to override)", path.display()),
+            Self::ReadOnly => write!(f, "File is read-only (add ! to override)"),
+            Self::TooManyArguments => write!(f, "Too many arguments"),
+            Self::ArgumentRequired => write!(f, "Argument required"),
+            Self::InvalidArgument(arg) => write!(f, "Invalid argument: {}", arg),
+            Self::NoRangeAllowed => write!(f, "No range allowed"),
+            Self::NoBangAllowed => write!(f, "No ! allowed"),
+            Self::InvalidRange => write!(f, "Invalid range"),
+            Self::OptionError(e) => write!(f, "Option error: {}", e),
+            Self::BindError(e) => write!(f, "Bind error: {}", e),
+            Self::RegisterError(e) => e.fmt(f),
+            Self::NoMatchingLayout(name) => write!(f, "No layout named {}", name),
+            Self::IOError(e) => e.fmt(f),
+        }
+    }
+}
+
+impl From<Error> for CommandError {
+    fn from(e: Error) -> Self {
+        Self::IOError(e)
+    }
+}
+
+impl From<OptionParseError> for CommandError {
+    fn from(e: OptionParseError) -> Self {
+        Self::OptionError(e)
+    }
+}
+
+impl From<RegisterError> for CommandError {
+    fn from(e: RegisterError) -> Self {
+        Self::RegisterError(e)
+    }
+}
+
+/// Enum for containing errors that might occur in parsing alias lines.
+#[derive(Debug, PartialEq, Eq)]
+pub enum AliasParseError {
+    /// No `alias ` found at the beginning of the line.
+    MalformedAlias,
+    /// The alias name is empty or isn't made of letters.
+    BadName,
+    /// Nothing for the alias to stand for.
+    NoCommand,
+}
+
+impl fmt::Display for AliasParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MalformedAlias => write!(f, "no `alias ` found"),
+            Self::BadName => write!(f, "alias name must be made of letters"),
+            Self::NoCommand => write!(f, "no command line after the alias name"),
+        }
+    }
+}
+
+/// Maps between names and [`ExCommand`]s.
+///
+/// This is to ex commands what [`context()`](crate::context::context) is to contexts: the one
+/// place commands are looked up by name.
+pub struct ExCommands {
+    #[doc(hidden)]
+    commands: Vec<ExCommand>,
+    #[doc(hidden)]
+    aliases: HashMap<String, String>,
+}
+
+impl Default for ExCommands {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExCommands {
+    /// Create a new registry with fim's built-in commands.
+    pub fn new() -> Self {
+        ExCommands{ commands: BUILTIN_COMMANDS.to_vec(), aliases: HashMap::new() }
+    }
+
+    /// Add a command, replacing any command with the same name.
+    ///
+    /// A new command comes after the existing commands when resolving abbreviations.
+    pub fn add(&mut self, command: ExCommand) {
+        match self.commands.iter_mut().find(|c| c.name == command.name) {
+            Some(existing) => *existing = command,
+            None => self.commands.push(command),
+        }
+    }
+
+    /// Add an alias from an alias line in a config file.
+    ///
+    /// An alias line looks like `alias <NAME> <COMMAND LINE>`, e.g. `alias W write!`. Running the
+    /// alias (which must be typed out in full) runs the command line, with any range, '!' and
+    /// arguments given to the alias added on. Aliases take precedence over commands, but aren't
+    /// expanded inside other aliases.
+    pub fn add_alias(&mut self, line: &str) -> std::result::Result<(), AliasParseError> {
+        let line = line.strip_prefix("alias ").ok_or(AliasParseError::MalformedAlias)?.trim_start();
+        let (name, command) = line.split_once(char::is_whitespace).ok_or(AliasParseError::NoCommand)?;
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(AliasParseError::BadName);
+        }
+        let command = command.trim();
+        if command.is_empty() {
+            return Err(AliasParseError::NoCommand);
+        }
+        self.aliases.insert(String::from(name), String::from(command));
+        Ok(())
+    }
+
+    /// Returns the command that `name` (which may be abbreviated) refers to.
+    pub fn get(&self, name: &str) -> Option<&ExCommand> {
+        self.commands.iter().find(|c| c.name == name).or_else(|| self.commands.iter().find(|c| c.matches(name)))
+    }
+
+    /// Returns the command line an alias stands for.
+    pub fn alias(&self, name: &str) -> Option<&str> {
+        self.aliases.get(name).map(String::as_str)
+    }
+
+    /// Returns an iterator over the full names of the commands and aliases.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.commands.iter().map(|c| c.name).chain(self.aliases.keys().map(String::as_str))
+    }
+
+    /// Returns the candidates for completing the word that ends at the end of `line` (a command
+    /// line without the leading ':'), and the byte offset in `line` where that word starts.
+    ///
+    /// The word is a command name if it is the first word, and otherwise an argument that is
+    /// completed according to the command's [`Completion`]. Candidates are sorted, and have
+    /// whitespace and backslashes escaped.
+    pub fn complete(&self, line: &str) -> (usize, Vec<String>) {
+        let trimmed = line.trim_start().trim_start_matches(':').trim_start();
+        let after_range = match parse_range(trimmed) {
+            Ok((_, rest)) => rest.trim_start(),
+            Err(_) => return (line.len(), Vec::new()),
+        };
+        let offset = line.len() - after_range.len();
+        let name_len = name_len(after_range);
+        // a trailing '-' may be the start of the next word of a name like `bind-layout`
+        if name_len == after_range.len() || after_range[name_len..] == *"-" {
+            let mut names: Vec<String> = self.names().filter(|n| n.starts_with(after_range)).map(String::from).collect();
+            names.sort();
+            names.dedup();
+            return (offset, names);
+        }
+        let completion = match CommandLine::parse(after_range) {
+            Ok(cmd) => self.alias(&cmd.name).and_then(|a| CommandLine::parse(a).ok()).map_or(cmd.name, |a| a.name),
+            Err(_) => return (line.len(), Vec::new()),
+        };
+        let completion = self.get(&completion).map_or(Completion::Nothing, |c| c.complete);
+        // the word starts after the last whitespace that isn't escaped
+        let name_end = offset + name_len;
+        let mut start = name_end;
+        let mut escaped = false;
+        for (i, c) in line[name_end..].char_indices() {
+            if escaped { escaped = false; } else if c == '\\' { escaped = true; } else if c.is_whitespace() { start = name_end + i + c.len_utf8(); }
+        }
+        // the context in `bind(Context)` starts after the '('
+        if completion == Completion::Context && line[start..].starts_with('(') {
+            start += 1;
+        }
+        let word = split_args(&line[start..]).pop().unwrap_or_default();
+        let mut candidates = match completion {
+            Completion::Nothing => Vec::new(),
+            Completion::File => complete_path(&word),
+            Completion::Option => Options::NAMES.iter().filter(|n| n.starts_with(&word)).map(|n| String::from(*n)).collect(),
+            Completion::Context => CONTEXTS.iter().filter(|n| n.starts_with(&word)).map(|n| String::from(*n)).collect(),
+        };
+        candidates.sort();
+        (start, candidates.iter().map(|c| escape(c)).collect())
+    }
+
+    /// Parse a command line, expanding an alias, and find its command.
+    ///
+    /// The command is `None` for a command line with only a range (e.g. `:42`), or nothing at all.
+    pub fn resolve(&self, line: &str) -> std::result::Result<(CommandLine, Option<ExCommand>), CommandError> {
+        let mut cmd = CommandLine::parse(line)?;
+        if let Some(expansion) = self.alias(&cmd.name) {
+            let expanded = CommandLine::parse(expansion)?;
+            cmd = CommandLine{
+                range: cmd.range.or(expanded.range),
+                name: expanded.name,
+                bang: cmd.bang || expanded.bang,
+                args: expanded.args.into_iter().chain(cmd.args).collect(),
+                raw: expanded.raw + &cmd.raw,
+            };
+        }
+        if cmd.name.is_empty() {
+            return if cmd.bang || !cmd.args.is_empty() { Err(CommandError::NotACommand(String::from(line.trim()))) } else { Ok((cmd, None)) };
+        }
+        match self.get(&cmd.name) {
+            Some(command) => Ok((cmd, Some(*command))),
+            None => Err(CommandError::NotACommand(cmd.name)),
+        }
+    }
+}
+
+/// Execute a command line (without the leading ':').
+///
+/// Any message from the command (including errors in the command itself, like a misspelled name
+/// or a file that couldn't be written) is drawn on the command line. Only errors in drawing it are
+/// returned.
+pub fn execute(ed: &mut Editor, line: &str) -> Result<()> {
+    match run(ed, line) {
+        Ok(Some(message)) => ed.q_draw_cmd_line([&message], CmdLineFlags::all()),
+        Ok(None) => Ok(()),
+        Err(e) => ed.q_draw_cmd_line([&e.to_string()], CmdLineFlags::all()),
+    }
+}
+
+// parses, checks and runs a command line
+fn run(ed: &mut Editor, line: &str) -> CommandResult {
+    let (cmd, command) = ed.config().commands.resolve(line)?;
+    let range = match cmd.range {
+        Some((start, end)) => {
+            let (current, num_lines) = ed.on_current_window(|w, _| Ok((w.cursor().y, w.num_lines().unwrap_or(1))))?;
+            let start = start.resolve(current, num_lines).ok_or(CommandError::InvalidRange)?;
+            let end = end.resolve(current, num_lines).ok_or(CommandError::InvalidRange)?;
+            Some(if start <= end { start..=end } else { end..=start })
+        },
+        None => None,
+    };
+    let command = match command {
+        Some(command) => command,
+        None => {
+            if let Some(range) = range {
+                ed.on_current_window(|w, t| w.goto_line(*range.end(), t))?;
+            }
+            return Ok(None);
+        },
+    };
+    if range.is_some() && !command.range {
+        return Err(CommandError::NoRangeAllowed);
+    }
+    if cmd.bang && !command.bang {
+        return Err(CommandError::NoBangAllowed);
+    }
+    match (command.args, cmd.args.len()) {
+        (Args::None, 1..) | (Args::Optional | Args::One, 2..) => return Err(CommandError::TooManyArguments),
+        (Args::One, 0) => return Err(CommandError::ArgumentRequired),
+        _ => (),
+    }
+    (command.run)(ed, &Invocation{ range, bang: cmd.bang, args: cmd.args, raw: cmd.raw })
+}
+
+const BUILTIN_COMMANDS: &[ExCommand] = &[
+    ExCommand{ name: "quit", abbreviation: 1, args: Args::None, range: false, bang: true, complete: Completion::Nothing, run: quit },
+    ExCommand{ name: "write", abbreviation: 1, args: Args::Optional, range: false, bang: true, complete: Completion::File, run: write },
+    ExCommand{ name: "wq", abbreviation: 2, args: Args::Optional, range: false, bang: true, complete: Completion::File, run: write_quit },
+    ExCommand{ name: "xit", abbreviation: 1, args: Args::Optional, range: false, bang: true, complete: Completion::File, run: exit },
+    ExCommand{ name: "edit", abbreviation: 1, args: Args::Optional, range: false, bang: true, complete: Completion::File, run: edit },
+    ExCommand{ name: "open", abbreviation: 1, args: Args::Optional, range: false, bang: true, complete: Completion::File, run: edit },
+    ExCommand{ name: "saveas", abbreviation: 3, args: Args::One, range: false, bang: true, complete: Completion::File, run: save_as },
+    ExCommand{ name: "delete", abbreviation: 1, args: Args::Optional, range: true, bang: false, complete: Completion::Nothing, run: delete },
+    ExCommand{ name: "yank", abbreviation: 1, args: Args::Optional, range: true, bang: false, complete: Completion::Nothing, run: yank },
+    ExCommand{ name: "registers", abbreviation: 3, args: Args::Optional, range: false, bang: false, complete: Completion::Nothing, run: registers },
+    ExCommand{ name: "display", abbreviation: 2, args: Args::Optional, range: false, bang: false, complete: Completion::Nothing, run: registers },
+    ExCommand{ name: "undo", abbreviation: 1, args: Args::Optional, range: false, bang: false, complete: Completion::Nothing, run: undo },
+    ExCommand{ name: "redo", abbreviation: 3, args: Args::None, range: false, bang: false, complete: Completion::Nothing, run: redo },
+    ExCommand{ name: "earlier", abbreviation: 2, args: Args::Optional, range: false, bang: false, complete: Completion::Nothing, run: earlier },
+    ExCommand{ name: "later", abbreviation: 3, args: Args::Optional, range: false, bang: false, complete: Completion::Nothing, run: later },
+    ExCommand{ name: "undotree", abbreviation: 8, args: Args::None, range: false, bang: false, complete: Completion::Nothing, run: undo_tree },
+    ExCommand{ name: "set", abbreviation: 2, args: Args::Any, range: false, bang: false, complete: Completion::Option, run: set },
+    ExCommand{ name: "bind", abbreviation: 4, args: Args::Any, range: false, bang: false, complete: Completion::Context, run: bind },
+    ExCommand{ name: "bind-layout", abbreviation: 11, args: Args::Any, range: false, bang: false, complete: Completion::Context, run: bind_layout },
+    ExCommand{ name: "unbind", abbreviation: 6, args: Args::Any, range: false, bang: false, complete: Completion::Context, run: unbind },
+    ExCommand{ name: "unbind-layout", abbreviation: 13, args: Args::Any, range: false, bang: false, complete: Completion::Context, run: unbind_layout },
+    ExCommand{ name: "binds", abbreviation: 5, args: Args::Optional, range: false, bang: false, complete: Completion::Context, run: binds },
+];
+
+fn quit(ed: &mut Editor, inv: &Invocation) -> CommandResult {
+    if !inv.bang && ed.on_current_window(|w, _| Ok(w.modified()))? {
+        return Err(CommandError::Unsaved);
+    }
+    ed.quit();
+    Ok(None)
+}
+
+fn write(ed: &mut Editor, inv: &Invocation) -> CommandResult {
+    write_file(ed, inv.args.first().map(Path::new), inv.bang).map(Some)
+}
+
+fn write_quit(ed: &mut Editor, inv: &Invocation) -> CommandResult {
+    write_file(ed, inv.args.first().map(Path::new), inv.bang)?;
+    ed.quit();
+    Ok(None)
+}
+
+// like `:wq`, but doesn't write the document to its own file if it has no unsaved changes
+fn exit(ed: &mut Editor, inv: &Invocation) -> CommandResult {
+    let path = inv.args.first().map(Path::new);
+    if path.is_some() || ed.on_current_window(|w, _| Ok(w.modified()))? {
+        write_file(ed, path, inv.bang)?;
+    }
+    ed.quit();
+    Ok(None)
+}
+
+fn edit(ed: &mut Editor, inv: &Invocation) -> CommandResult {
+    if !inv.bang && ed.on_current_window(|w, _| Ok(w.modified()))? {
+        return Err(CommandError::Unsaved);
+    }
+    let path = match inv.args.first() {
+        Some(path) => PathBuf::from(path),
+        None => ed.on_current_window(|w, _| Ok(w.file().map(Path::to_path_buf)))?.ok_or(CommandError::NoFileName)?,
+    };
+    ed.on_current_window(|w, t| w.open(&path, t))?;
+    let backup = Differential::backup_path(&path)?;
+    if backup.exists() {
+        ed.push_context(RecoveryPrompt::new(backup));
+        return Ok(None);
+    }
+    let (lines, bytes) = ed.on_current_window(|w, _| Ok((w.num_lines().unwrap_or_default(), path.metadata()?.len())))?;
+    Ok(Some(format!("\"{}\" {}L, {}B", path.display(), lines, bytes)))
+}
+
+fn save_as(ed: &mut Editor, inv: &Invocation) -> CommandResult {
+    let path = Path::new(&inv.args[0]);
+    if !inv.bang && path.exists() {
+        return Err(CommandError::FileExists(path.to_path_buf()));
+    }
+    let (lines, bytes) = ed.on_current_window(|w, _| Ok((w.num_lines().ok_or_else(|| Error::other("no document"))?, w.save_as(path)?)))?;
+    Ok(Some(written(path, lines, bytes)))
+}
+
+fn delete(ed: &mut Editor, inv: &Invocation) -> CommandResult {
+    let register = register_arg(inv)?;
+    let text = ed.on_current_window(|w, t| {
+        let current = w.cursor().y;
+        let lines = inv.range.clone().unwrap_or(current..=current);
+        let text = if w.read_only() { None } else { w.yank_lines(lines.clone()) };
+        w.delete_lines(lines, t)?;
+        Ok(text)
+    })?;
+    if let Some(text) = text {
+        ed.store_register(register, text, true)?;
+    }
+    Ok(None)
+}
+
+fn yank(ed: &mut Editor, inv: &Invocation) -> CommandResult {
+    let register = register_arg(inv)?;
+    let text = ed.on_current_window(|w, _| {
+        let current = w.cursor().y;
+        Ok(w.yank_lines(inv.range.clone().unwrap_or(current..=current)))
+    })?;
+    if let Some(text) = text {
+        ed.store_register(register, text, false)?;
+    }
+    Ok(None)
+}
+
+fn registers(ed: &mut Editor, inv: &Invocation) -> CommandResult {
+    let names = inv.args.first();
+    let rows: Vec<String> = ed.registers().list().into_iter()
+        .filter(|(name, _)| names.is_none_or(|n| n.contains(*name)))
+        .map(|(name, text)| {
+            let kind = match text.kind {
+                RegisterKind::Char => 'c',
+                RegisterKind::Line => 'l',
+                RegisterKind::Block => 'b',
+            };
+            // like vim, newlines and tabs are shown as ^J and ^I
+            let text = text.text().replace('\n', "^J").replace('\t', "^I");
+            format!("  {}  \"{}   {}", kind, name, text)
+        }).collect();
+    if rows.is_empty() {
+        return Ok(Some(String::from("No registers")));
+    }
+    let lines = once(String::from("Type Name Content")).chain(rows).collect();
+    ed.push_context(ListView::new(String::from("-- REGISTERS --"), lines));
+    Ok(None)
+}
+
+fn undo(ed: &mut Editor, inv: &Invocation) -> CommandResult {
+    match inv.args.first() {
+        None => {
+            ed.action("undo")?;
+        },
+        Some(step) => {
+            let step = step.parse().map_err(|_| CommandError::InvalidArgument(step.clone()))?;
+            if !ed.on_current_window(|w, t| w.undo_to(step, t))? {
+                return Ok(Some(format!("Undo number {} not found", step)));
+            }
+        },
+    }
+    Ok(None)
+}
+
+fn redo(ed: &mut Editor, _inv: &Invocation) -> CommandResult {
+    ed.action("redo")?;
+    Ok(None)
+}
+
+fn earlier(ed: &mut Editor, inv: &Invocation) -> CommandResult {
+    travel(ed, inv, true)
+}
+
+fn later(ed: &mut Editor, inv: &Invocation) -> CommandResult {
+    travel(ed, inv, false)
+}
+
+fn travel(ed: &mut Editor, inv: &Invocation, earlier: bool) -> CommandResult {
+    let travel = match inv.args.first() {
+        None => Travel::Steps(1),
+        Some(arg) => arg.parse().map_err(|_| CommandError::InvalidArgument(arg.clone()))?,
+    };
+    ed.on_current_window(|w, t| w.travel(travel, earlier, t))?;
+    Ok(None)
+}
+
+fn undo_tree(ed: &mut Editor, _inv: &Invocation) -> CommandResult {
+    ed.push_context(UndoTreeView::new());
+    Ok(None)
+}
+
+fn set(ed: &mut Editor, inv: &Invocation) -> CommandResult {
+    let mut opt = ed.options().clone();
+    let mut shown = Vec::new();
+    if inv.args.is_empty() {
+        let default = Options::default();
+        for name in Options::NAMES {
+            let value = opt.get_option(name)?;
+            if value != default.get_option(name)? { shown.push(format!("{}={}", name, value)); }
+        }
+        return Ok(Some(if shown.is_empty() { String::from("All options have their default values") } else { shown.join("  ") }));
+    }
+    let changed = apply_set_args(&mut opt, &inv.args, &mut shown)?;
+    if let LayoutType::Custom{ name } = &opt.layout {
+        if !ed.config().layouts.contains_key(name) {
+            return Err(CommandError::NoMatchingLayout(name.clone()));
+        }
+    }
+    if changed {
+        ed.set_options(opt)?;
+    }
+    Ok(if shown.is_empty() { None } else { Some(shown.join("  ")) })
+}
+
+fn bind(ed: &mut Editor, inv: &Invocation) -> CommandResult {
+    change_binds(ed, &format!("bind{}", inv.raw), true)
+}
+
+fn bind_layout(ed: &mut Editor, inv: &Invocation) -> CommandResult {
+    change_binds(ed, &format!("bind-layout{}", inv.raw), true)
+}
+
+fn unbind(ed: &mut Editor, inv: &Invocation) -> CommandResult {
+    change_binds(ed, &format!("unbind{}", inv.raw), false)
+}
+
+fn unbind_layout(ed: &mut Editor, inv: &Invocation) -> CommandResult {
+    change_binds(ed, &format!("unbind-layout{}", inv.raw), false)
+}
+
+// adds or removes a key bind, like a `bind` or `unbind` line in a config file
+fn change_binds(ed: &mut Editor, line: &str, add: bool) -> CommandResult {
+    let config = ed.config_mut();
+    let layout = config.opt.layout.clone();
+    let result = if add { config.key_binds.add(line, layout, &config.layouts) } else { config.key_binds.remove(line, layout, &config.layouts) };
+    result.map(|_| None).map_err(CommandError::BindError)
+}
+
+fn binds(ed: &mut Editor, inv: &Invocation) -> CommandResult {
+    let config = ed.config();
+    let context = inv.args.first().map(String::as_str);
+    let binds: Vec<BindInfo> = config.key_binds.binds().into_iter().filter(|b| context.is_none_or(|c| c == b.context)).collect();
+    if binds.is_empty() {
+        return Ok(Some(String::from("No key binds")));
+    }
+    let rows: Vec<[String; 4]> = binds.iter().map(|b| {
+        let keys: Vec<KeyEvent> = b.keys.iter().map(|k| config.to_current_layout_event(*k)).collect();
+        let key = KeyBinds::key_sequence_string(&keys);
+        let layout = b.layout.map_or_else(|| String::from("any layout"), |l| format!("{} only", l));
+        [String::from(b.context), key, String::from(b.target), layout]
+    }).collect();
+    let widths: Vec<usize> = (0..3).map(|i| rows.iter().map(|r| r[i].chars().count()).max().unwrap_or(0)).collect();
+    let lines = rows.iter().map(|[context, key, target, layout]| {
+        format!("{:<w0$}  {:<w1$}  {:<w2$}  ({})", context, key, target, layout, w0 = widths[0], w1 = widths[1], w2 = widths[2])
+    }).collect();
+    let title = format!("-- KEY BINDS (keys as typed in {}) --", config.opt.layout);
+    ed.push_context(ListView::new(title, lines));
+    Ok(None)
+}
+
+// the register named by the argument of a command like `:delete`, if it has one
+fn register_arg(inv: &Invocation) -> std::result::Result<Option<char>, CommandError> {
+    let arg = match inv.args.first() {
+        Some(arg) => arg,
+        None => return Ok(None),
+    };
+    let mut chars = arg.chars();
+    match (chars.next(), chars.next()) {
+        (Some(name), None) => {
+            Registers::check_writable(name)?;
+            Ok(Some(name))
+        },
+        _ => Err(CommandError::InvalidArgument(arg.clone())),
+    }
+}
+
+// applies the arguments of `:set` to `opt`, adding the values to show to `shown`; returns `true`
+// if any option was changed
+fn apply_set_args(opt: &mut Options, args: &[String], shown: &mut Vec<String>) -> std::result::Result<bool, CommandError> {
+    // `name = value` is split into three arguments, so put it back together
+    let mut items: Vec<String> = Vec::new();
+    for arg in args {
+        match items.last_mut() {
+            Some(last) if last.ends_with('=') || arg.starts_with('=') => last.push_str(arg),
+            _ => items.push(arg.clone()),
+        }
+    }
+    let mut changed = false;
+    for item in &items {
+        if let Some((name, value)) = item.split_once('=') {
+            opt.set_option(&format!("set {} = {}", name, value))?;
+            changed = true;
+        } else if let Some(name) = item.strip_suffix('?') {
+            shown.push(format!("{}={}", name, opt.get_option(name)?));
+        } else if let Some(name) = item.strip_suffix('&') {
+            opt.reset_option(name)?;
+            changed = true;
+        } else if let Some(name) = item.strip_suffix('!').or_else(|| item.strip_prefix("inv").filter(|n| opt.is_bool_option(n))) {
+            opt.toggle_option(name)?;
+            changed = true;
+        } else if opt.is_bool_option(item) {
+            opt.set_option(&format!("set {} = true", item))?;
+            changed = true;
+        } else if let Some(name) = item.strip_prefix("no").filter(|n| opt.is_bool_option(n)) {
+            opt.set_option(&format!("set {} = false", name))?;
+            changed = true;
+        } else {
+            shown.push(format!("{}={}", item, opt.get_option(item)?));
+        }
+    }
+    Ok(changed)
+}
+
+// writes the current window's document to `path` (or its own file), returning the message to show
+fn write_file(ed: &mut Editor, path: Option<&Path>, bang: bool) -> std::result::Result<String, CommandError> {
+    let (file, read_only) = ed.on_current_window(|w, _| Ok((w.file().map(Path::to_path_buf), w.read_only())))?;
+    let path = path.map(Path::to_path_buf).or(file.clone()).ok_or(CommandError::NoFileName)?;
+    let own_file = file.is_some_and(|f| path.canonicalize().is_ok_and(|p| p == f));
+    if !bang {
+        if own_file && read_only {
+            return Err(CommandError::ReadOnly);
+        } else if !own_file && path.exists() {
+            return Err(CommandError::FileExists(path));
+        }
+    }
+    let (lines, bytes) = ed.on_current_window(|w, _| Ok((w.num_lines().ok_or(Error::other("no document"))?, w.write(Some(&path))?)))?;
+    Ok(written(&path, lines, bytes))
+}
+
+fn written(path: &Path, lines: usize, bytes: u64) -> String {
+    format!("\"{}\" {}L, {}B written", path.display(), lines, bytes)
+}
+
+// returns the paths that start with `prefix`; directories end with a '/', and hidden files are
+// only included if the file name part of `prefix` starts with a '.'
+fn complete_path(prefix: &str) -> Vec<String> {
+    let (dir, file) = match prefix.rfind('/') {
+        Some(i) => prefix.split_at(i + 1),
+        None => ("", prefix),
+    };
+    let entries = match read_dir(if dir.is_empty() { Path::new(".") } else { Path::new(dir) }) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries.filter_map(|entry| {
+        let entry = entry.ok()?;
+        let name = entry.file_name().into_string().ok()?;
+        if !name.starts_with(file) || (name.starts_with('.') && !file.starts_with('.')) { return None; }
+        let slash = if entry.path().is_dir() { "/" } else { "" };
+        Some(format!("{}{}{}", dir, name, slash))
+    }).collect()
+}
+
+// the length of the command name at the start of `line`
+fn name_len(line: &str) -> usize {
+    let letters = |s: &str| s.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(s.len());
+    let mut len = letters(line);
+    while len > 0 && line[len..].starts_with('-') && line[len + 1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+        len += 1 + letters(&line[len + 1..]);
+    }
+    len
+}
+
+// escapes whitespace and backslashes, so `split_args` gives back `arg`
+fn escape(arg: &str) -> String {
+    arg.chars().fold(String::new(), |mut escaped, c| {
+        if c == '\\' || c.is_whitespace() { escaped.push('\\'); }
+        escaped.push(c);
+        escaped
+    })
+}
+
+// parses the range at the start of a command line, returning it and the rest of the line
+fn parse_range(line: &str) -> std::result::Result<(Option<LineRange>, &str), CommandError> {
+    if let Some(rest) = line.strip_prefix('%') {
+        return Ok((Some((Address::Line(1, 0), Address::Last(0))), rest));
+    }
+    let (start, rest) = parse_address(line)?;
+    match rest.strip_prefix(',') {
+        Some(rest) => {
+            let (end, rest) = parse_address(rest)?;
+            let current = Address::Current(0);
+            Ok((Some((start.unwrap_or(current), end.unwrap_or(current))), rest))
+        },
+        None => Ok((start.map(|a| (a, a)), rest)),
+    }
+}
+
+// parses a line address, returning it (if there was one) and the rest of the line
+fn parse_address(line: &str) -> std::result::Result<(Option<Address>, &str), CommandError> {
+    let digits = line.find(|c: char| !c.is_ascii_digit()).unwrap_or(line.len());
+    if digits > 0 {
+        let line_no = line[..digits].parse::<usize>().map_err(|_| CommandError::InvalidRange)?;
+        let (offset, rest) = parse_offsets(&line[digits..])?;
+        return Ok((Some(Address::Line(line_no, offset)), rest));
+    }
+    let (offset, rest) = parse_offsets(line.strip_prefix(['.', '$']).unwrap_or(line))?;
+    let address = match line.chars().next() {
+        Some('.') => Address::Current(offset),
+        Some('$') => Address::Last(offset),
+        _ if rest.len() == line.len() => return Ok((None, rest)),
+        _ => Address::Current(offset),
+    };
+    Ok((Some(address), rest))
+}
+
+// parses any number of `+N`/`-N` offsets, returning their sum and the rest of the line
+fn parse_offsets(mut line: &str) -> std::result::Result<(isize, &str), CommandError> {
+    let mut offset: isize = 0;
+    while let Some(sign) = line.chars().next().filter(|c| *c == '+' || *c == '-') {
+        line = &line[1..];
+        let digits = line.find(|c: char| !c.is_ascii_digit()).unwrap_or(line.len());
+        let n = if digits == 0 { 1 } else { line[..digits].parse::<isize>().map_err(|_| CommandError::InvalidRange)? };
+        line = &line[digits..];
+        offset = if sign == '+' { offset.checked_add(n) } else { offset.checked_sub(n) }.ok_or(CommandError::InvalidRange)?;
+    }
+    Ok((offset, line))
+}
+
+// splits arguments on whitespace, where a backslash escapes the character after it
+fn split_args(args: &str) -> Vec<String> {
+    let mut split = Vec::new();
+    let mut arg: Option<String> = None;
+    let mut chars = args.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => arg.get_or_insert_with(String::new).push(chars.next().unwrap_or('\\')),
+            c if c.is_whitespace() => split.extend(arg.take()),
+            c => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    split.extend(arg);
+    split
+}
+
+#[test]
+fn test_command_line_parse() {
+    let parse = |line| CommandLine{ raw: String::new(), ..CommandLine::parse(line).unwrap() };
+    let cmd = |range, name: &str, bang, args: &[&str]| CommandLine{ range, name: String::from(name), bang, args: args.iter().map(|a| String::from(*a)).collect(), raw: String::new() };
+    assert_eq!(parse("w"), cmd(None, "w", false, &[]));
+    assert_eq!(parse(":q!"), cmd(None, "q", true, &[]));
+    assert_eq!(parse("  e!  a.txt "), cmd(None, "e", true, &["a.txt"]));
+    assert_eq!(parse("sav my\\ file\\\\ b"), cmd(None, "sav", false, &["my file\\", "b"]));
+    assert_eq!(parse("ea 30s"), cmd(None, "ea", false, &["30s"]));
+    assert_eq!(parse(""), cmd(None, "", false, &[]));
+    assert!(matches!(CommandLine::parse("w2"), Err(CommandError::TrailingCharacters(_))));
+    assert_eq!(parse("bind-layout(NormalMode) <C-s> Action undo"), cmd(None, "bind-layout", false, &["(NormalMode)", "<C-s>", "Action", "undo"]));
+    assert_eq!(CommandLine::parse("bind(NormalMode) u  Action undo").unwrap().raw, "(NormalMode) u  Action undo");
+
+    let (line, current, last) = (Address::Line, Address::Current, Address::Last);
+    assert_eq!(parse("3,10d"), cmd(Some((line(3, 0), line(10, 0))), "d", false, &[]));
+    assert_eq!(parse("%d"), cmd(Some((line(1, 0), last(0))), "d", false, &[]));
+    assert_eq!(parse(".,$delete"), cmd(Some((current(0), last(0))), "delete", false, &[]));
+    assert_eq!(parse(",+2d"), cmd(Some((current(0), current(2))), "d", false, &[]));
+    assert_eq!(parse("42"), cmd(Some((line(42, 0), line(42, 0))), "", false, &[]));
+    assert_eq!(parse("$-3+1,-"), cmd(Some((last(-2), current(-1))), "", false, &[]));
+}
+
+#[test]
+fn test_address_resolve() {
+    assert_eq!(Address::Line(1, 0).resolve(5, 10), Some(0));
+    assert_eq!(Address::Line(0, 0).resolve(5, 10), Some(0));
+    assert_eq!(Address::Line(11, 0).resolve(5, 10), None);
+    assert_eq!(Address::Current(-2).resolve(5, 10), Some(3));
+    assert_eq!(Address::Current(-6).resolve(5, 10), None);
+    assert_eq!(Address::Last(0).resolve(5, 10), Some(9));
+    assert_eq!(Address::Last(1).resolve(5, 10), None);
+}
+
+#[test]
+fn test_ex_commands() {
+    let mut commands = ExCommands::new();
+    let name = |commands: &ExCommands, name| commands.get(name).map(|c| c.name);
+    assert_eq!(name(&commands, "w"), Some("write"));
+    assert_eq!(name(&commands, "wq"), Some("wq"));
+    assert_eq!(name(&commands, "x"), Some("xit"));
+    assert_eq!(name(&commands, "e"), Some("edit"));
+    assert_eq!(name(&commands, "ea"), Some("earlier"));
+    assert_eq!(name(&commands, "un"), Some("undo"));
+    assert_eq!(name(&commands, "undotree"), Some("undotree"));
+    assert_eq!(name(&commands, "re"), None);
+    assert_eq!(name(&commands, "writes"), None);
+
+    assert_eq!(commands.add_alias("alias W write!"), Ok(()));
+    assert_eq!(commands.add_alias("alias W2 write"), Err(AliasParseError::BadName));
+    assert_eq!(commands.add_alias("alias W "), Err(AliasParseError::NoCommand));
+    assert_eq!(commands.add_alias("unalias W"), Err(AliasParseError::MalformedAlias));
+    let (cmd, command) = commands.resolve("W out.txt").unwrap();
+    assert_eq!((cmd.name.as_str(), cmd.bang, &cmd.args[..], command.map(|c| c.name)), ("write", true, &[String::from("out.txt")][..], Some("write")));
+    assert!(commands.resolve("42").unwrap().1.is_none());
+    assert!(matches!(commands.resolve("nope"), Err(CommandError::NotACommand(_))));
+
+    commands.add(ExCommand{ name: "wall", abbreviation: 2, args: Args::None, range: false, bang: true, complete: Completion::Nothing, run: quit });
+    assert_eq!(name(&commands, "wa"), Some("wall"));
+    assert_eq!(name(&commands, "w"), Some("write"));
+    assert!(commands.names().any(|n| n == "W"));
+}
+
+#[test]
+fn test_complete() {
+    let mut commands = ExCommands::new();
+    commands.add_alias("alias Edit edit!").unwrap();
+    assert_eq!(commands.complete("und"), (0, vec![String::from("undo"), String::from("undotree")]));
+    assert_eq!(commands.complete("unbind-"), (0, vec![String::from("unbind-layout")]));
+    assert_eq!(commands.complete("bind(Ins"), (5, vec![String::from("InsertMode")]));
+    assert_eq!(commands.complete(":3,5de"), (4, vec![String::from("delete")]));
+    assert_eq!(commands.complete("E"), (0, vec![String::from("Edit")]));
+    assert_eq!(commands.complete("undo 1"), (5, vec![]));
+
+    let dir = std::env::temp_dir().join(format!("fim-test-complete-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("sub dir")).unwrap();
+    std::fs::write(dir.join("some file.txt"), "").unwrap();
+    std::fs::write(dir.join("other.txt"), "").unwrap();
+    std::fs::write(dir.join(".hidden"), "").unwrap();
+    let d = dir.to_str().unwrap();
+    let (start, candidates) = commands.complete(&format!("w {}/s", d));
+    assert_eq!(start, 2);
+    assert_eq!(candidates, vec![format!("{}/some\\ file.txt", d), format!("{}/sub\\ dir/", d)]);
+    assert_eq!(commands.complete(&format!("Edit {}/sub\\ d", d)), (5, vec![format!("{}/sub\\ dir/", d)]));
+    assert_eq!(commands.complete(&format!("e {}/.", d)).1, vec![format!("{}/.hidden", d)]);
+    assert_eq!(commands.complete(&format!("e {}/", d)).1.len(), 3);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(commands.complete("se undo_").1, vec![String::from("undo_dir"), String::from("undo_file")]);
+    commands.add(ExCommand{ name: "binding", abbreviation: 3, args: Args::Any, range: false, bang: false, complete: Completion::Context, run: quit });
+    assert_eq!(commands.complete("bin N").1, vec![String::from("NormalMode")]);
+}
+
+#[test]
+fn test_set_args() {
+    let set = |opt: &mut Options, line: &str| {
+        let mut shown = Vec::new();
+        let args = CommandLine::parse(line).unwrap().args;
+        apply_set_args(opt, &args, &mut shown).map(|changed| (changed, shown.join("  ")))
+    };
+    let mut opt = Options::default();
+    assert_eq!(set(&mut opt, "set tab_width? line_numbering").unwrap(), (false, String::from("tab_width=4  line_numbering=Relative")));
+    assert_eq!(set(&mut opt, "set tab_width=8 line_numbering = On").unwrap(), (true, String::new()));
+    assert_eq!((i32::from(opt.tab_width), opt.get_option("line_numbering").unwrap().as_str()), (8, "On"));
+    assert!(set(&mut opt, "set tab_width& undo_file").unwrap().0);
+    assert_eq!((i32::from(opt.tab_width), bool::from(opt.undo_file)), (4, true));
+    set(&mut opt, "set undo_file!").unwrap();
+    assert!(!bool::from(opt.undo_file));
+    set(&mut opt, "set invundo_file").unwrap();
+    assert!(bool::from(opt.undo_file));
+    set(&mut opt, "set noundo_file").unwrap();
+    assert!(!bool::from(opt.undo_file));
+    set(&mut opt, "set undo_dir=/tmp/my\\ undo").unwrap();
+    assert_eq!(opt.get_option("undo_dir").unwrap(), "/tmp/my undo");
+    assert!(matches!(set(&mut opt, "set tab_width!"), Err(CommandError::OptionError(OptionParseError::NotABoolOption{ .. }))));
+    assert!(matches!(set(&mut opt, "set nope"), Err(CommandError::OptionError(OptionParseError::NoMatchingOption{ .. }))));
+    assert!(matches!(set(&mut opt, "set tab_width=0"), Err(CommandError::OptionError(OptionParseError::ValueParseError{ .. }))));
+}
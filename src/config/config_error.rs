@@ -1,6 +1,7 @@
 //! A module that provides the error types for configuration parsing.
 
 pub use super::options::OptionParseError;
+pub use crate::command::AliasParseError;
 use std::fmt;
 
 /// Enum for containing errors that might occur in parsing bind lines.
@@ -21,6 +22,11 @@ pub enum BindParseError {
     MalformedKeyEventTerm,
     /// Unexpected unicode character in the key event term.
     UnicodeBoundaryErrorInKeyEvent,
+    /// There is no key bind to remove for the key in an `unbind` line.
+    NoMatchingBind{
+        /// The key event term the user provided.
+        key: String
+    },
 }
 
 impl fmt::Display for BindParseError {
@@ -32,6 +38,7 @@ impl fmt::Display for BindParseError {
             Self::UnicodeBoundaryErrorInBind => write!(f, "unexpected unicode character in bind term"),
             Self::MalformedKeyEventTerm => write!(f, "incorrect syntax in key event term"),
             Self::UnicodeBoundaryErrorInKeyEvent => write!(f, "unexpected unicode character in key event term"),
+            Self::NoMatchingBind{ key } => write!(f, "no key bind for {} found", key),
         }
     }
 }
@@ -156,6 +163,13 @@ pub enum ConfigParseError {
         /// The offending line in the config file.
         line: usize
     },
+    /// See [`AliasParseError`](crate::command::AliasParseError).
+    AliasParseError{
+        /// The wrapped `AliasParseError`.
+        error: AliasParseError,
+        /// The offending line in the config file.
+        line: usize
+    },
     /// Could not determine the statement type of the line.
     NotAStatement{
         /// The offending line in the config file.
@@ -193,6 +207,11 @@ impl ConfigParseError {
     pub fn include(error: IncludeParseError, line: usize) -> Self {
         Self::IncludeParseError{ error, line }
     }
+
+    /// Create a `ConfigParseError::AliasParseError` from the inner `AliasParseError`.
+    pub fn alias(error: AliasParseError, line: usize) -> Self {
+        Self::AliasParseError{ error, line }
+    }
 }
 
 impl fmt::Display for ConfigParseError {
@@ -202,6 +221,7 @@ impl fmt::Display for ConfigParseError {
             Self::OptionParseError{ error, line } => write!(f, "error parsing option statement on line {}: {}", line, error),
             Self::LayoutParseError{ error, line } => write!(f, "error parsing layout spec (included on line {}): {}", line, error),
             Self::IncludeParseError{ error, line } => write!(f, "error parsing include statement on line {}: {}", line, error),
+            Self::AliasParseError{ error, line } => write!(f, "error parsing alias statement on line {}: {}", line, error),
             Self::NotAStatement{ line } => write!(f, "could not determine statement type of line {}", line),
             Self::NoMatchingLayout{ line } => write!(f, "could not find an included custom layout matching line {}", line),
             Self::IOError{ error } => error.fmt(f),
@@ -7,6 +7,13 @@ use crate::layout::CustomLayout;
 use std::collections::HashMap;
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 
+// the names of keys that are written between angle brackets (the first name of a key is the one
+// that `KeyBinds::key_event_string` uses)
+const UNPRINTABLE_NAMES: [(&str, KeyCode); 16] = [("BS", KeyCode::Backspace), ("CR", KeyCode::Enter), ("Enter", KeyCode::Enter), ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right), ("Up", KeyCode::Up), ("Down", KeyCode::Down), ("Home", KeyCode::Home),
+    ("End", KeyCode::End), ("PageUp", KeyCode::PageUp), ("PageDown", KeyCode::PageDown), ("Tab", KeyCode::Tab),
+    ("Del", KeyCode::Delete), ("Ins", KeyCode::Insert), ("Space", KeyCode::Char(' ')), ("Esc", KeyCode::Esc)];
+
 struct KeyBindMap {
     unprintable: HashMap<String, KeyEvent>,
 }
@@ -14,11 +21,7 @@ struct KeyBindMap {
 impl KeyBindMap {
     fn new() -> Self {
         let mut unprintable = HashMap::new();
-        let pairs = [("BS", KeyCode::Backspace), ("CR", KeyCode::Enter), ("Enter", KeyCode::Enter), ("Left", KeyCode::Left),
-                     ("Right", KeyCode::Right), ("Up", KeyCode::Up), ("Down", KeyCode::Down), ("Home", KeyCode::Home),
-                     ("End", KeyCode::End), ("PageUp", KeyCode::PageUp), ("PageDown", KeyCode::PageDown), ("Tab", KeyCode::Tab),
-                     ("Del", KeyCode::Delete), ("Ins", KeyCode::Insert), ("Space", KeyCode::Char(' ')), ("Esc", KeyCode::Esc)];
-        for (rep, code) in pairs {
+        for (rep, code) in UNPRINTABLE_NAMES {
             unprintable.insert(rep.to_string(), KeyEvent::new(code, KeyModifiers::NONE));
         }
         for i in 1u8..=12 {
@@ -28,28 +31,27 @@ impl KeyBindMap {
     }
 
     pub fn query(&self, rep: &str) -> Option<KeyEvent> {
-        if rep.len() == 0 {
+        if rep.is_empty() {
             return None;
         }
         let head = rep.chars().next();
         let middle = rep.get(1..rep.len() - 1);
-        if rep.len() == 1 && head.is_some() && head.unwrap().is_ascii_graphic() {
-            Some(KeyEvent::new(KeyCode::Char(head.unwrap().to_ascii_uppercase()), KeyModifiers::NONE))
-        } else if rep.len() > 2 && rep.get(0..1) == Some("<") && rep.get(rep.len() - 1..) == Some(">")
-            && middle.is_some() && self.unprintable.contains_key(middle.unwrap()) {
-            Some(self.unprintable[middle.unwrap()])
+        if let Some(head) = head.filter(|h| rep.len() == 1 && h.is_ascii_graphic()) {
+            Some(KeyEvent::new(KeyCode::Char(head.to_ascii_uppercase()), KeyModifiers::NONE))
         } else {
-            None
+            middle.filter(|m| rep.len() > 2 && rep.get(0..1) == Some("<") && rep.get(rep.len() - 1..) == Some(">")
+                && self.unprintable.contains_key(*m))
+                .map(|m| self.unprintable[m])
         }
     }
 
     pub fn query_code(&self, rep: &str) -> Option<KeyCode> {
-        if rep.len() == 0 {
+        if rep.is_empty() {
             return None;
         }
         let head = rep.chars().next();
-        if rep.len() == 1 && head.is_some() && head.unwrap().is_ascii_graphic() {
-            Some(KeyCode::Char(head.unwrap().to_ascii_uppercase()))
+        if let Some(head) = head.filter(|h| rep.len() == 1 && h.is_ascii_graphic()) {
+            Some(KeyCode::Char(head.to_ascii_uppercase()))
         } else if self.unprintable.contains_key(rep) {
             Some(self.unprintable[rep].code)
         } else {
@@ -62,10 +64,90 @@ lazy_static! {
     static ref MAP: KeyBindMap = KeyBindMap::new();
 }
 
+// what a key is bound to: the rest of the `bind` line after the key (e.g. `Action undo`), and
+// the factory it was parsed into
+struct Binding {
+    target: String,
+    factory: Factory,
+}
+
+// a node in the trie of key sequences bound in a context: the bind for the sequence of keys
+// leading to this node (if any), and the nodes for the sequences one key longer
+#[derive(Default)]
+struct BindNode {
+    binding: Option<Binding>,
+    children: HashMap<KeyEvent, BindNode>,
+}
+
+impl BindNode {
+    fn get(&self, keys: &[KeyEvent]) -> Option<&BindNode> {
+        keys.iter().try_fold(self, |node, key| node.children.get(key))
+    }
+
+    // removes the bind for `keys`, pruning the nodes that no longer lead to a bind
+    fn remove(&mut self, keys: &[KeyEvent]) -> Option<Binding> {
+        match keys.split_first() {
+            None => self.binding.take(),
+            Some((key, rest)) => {
+                let child = self.children.get_mut(key)?;
+                let binding = child.remove(rest);
+                if child.binding.is_none() && child.children.is_empty() {
+                    self.children.remove(key);
+                }
+                binding
+            },
+        }
+    }
+
+    fn collect<'a>(&'a self, keys: &mut Vec<KeyEvent>, binds: &mut Vec<(Vec<KeyEvent>, &'a Binding)>) {
+        if let Some(binding) = self.binding.as_ref() {
+            binds.push((keys.clone(), binding));
+        }
+        for (key, child) in self.children.iter() {
+            keys.push(*key);
+            child.collect(keys, binds);
+            keys.pop();
+        }
+    }
+}
+
 /// Struct that represents key press to context mapping.
+///
+/// Keys are bound as sequences of one or more key presses (e.g. `dd` or `<C-w>v`), kept in a
+/// trie per layout and per context.
 pub struct KeyBinds {
     #[doc(hidden)]
-    map: HashMap<Option<LayoutType>, HashMap<String, HashMap<KeyEvent, Factory>>>
+    map: HashMap<Option<LayoutType>, HashMap<String, BindNode>>
+}
+
+/// A key bind, as listed by [`KeyBinds::binds`].
+pub struct BindInfo<'a> {
+    /// The layout the bind is specific to, or `None` for a layout-agnostic bind.
+    pub layout: Option<&'a LayoutType>,
+    /// The context the bind applies in.
+    pub context: &'a str,
+    /// The key presses, in QWERTY.
+    pub keys: Vec<KeyEvent>,
+    /// What the keys are bound to (the new context and its arguments, e.g. `Action undo`).
+    pub target: &'a str,
+}
+
+/// The result of looking up a sequence of key presses with [`KeyBinds::query`].
+pub enum BindMatch<'a> {
+    /// No key bind starts with the key presses.
+    Nothing,
+    /// The key presses are the start of longer key binds, but aren't bound themselves.
+    Prefix,
+    /// The key presses are bound, but they are also the start of longer key binds.
+    Ambiguous(&'a Factory),
+    /// The key presses are bound, and aren't the start of any longer key bind.
+    Complete(&'a Factory),
+}
+
+impl Default for KeyBinds {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl KeyBinds {
@@ -78,26 +160,152 @@ impl KeyBinds {
     ///
     /// Replaces previous key binds.
     pub fn add(&mut self, line: &str, layout: LayoutType, layout_map: &HashMap<String, CustomLayout>) -> Result<(), BindParseError> {
-        let (current_layout_only, context, keypress, factory) = Self::parse_line(line)?;
-        let keypress = Config::to_qwerty_event(keypress, &layout, layout_map); // translate to QWERTY
+        let (current_layout_only, context, keys, factory) = Self::parse_line(line)?;
+        let keys: Vec<KeyEvent> = keys.into_iter().map(|k| Self::bound_key(Config::to_qwerty_event(k, &layout, layout_map))).collect(); // translate to QWERTY
         let layout_option = if current_layout_only { Some(layout) } else { None };
-        self.map.entry(layout_option).or_insert(HashMap::new())
-                .entry(context).or_insert(HashMap::new())
-                .insert(keypress, factory);
+        let target = line.splitn(3, ' ').nth(2).unwrap_or_default().trim().to_string();
+        let root = self.map.entry(layout_option).or_default().entry(context).or_default();
+        let node = keys.into_iter().fold(root, |node, key| node.children.entry(key).or_default());
+        node.binding = Some(Binding{ target, factory });
         Ok(())
     }
 
-    /// Query the key binds for an associated context [`Factory`].
+    /// Parses the passed line as an `unbind` line and removes the key bind it refers to.
+    ///
+    /// An `unbind` line is like a `bind` line without the new context term: `unbind(<context>)` (or
+    /// `unbind-layout(<context>)`, for a layout-specific key bind) and the key event term. Returns
+    /// an error if there is no such key bind.
+    pub fn remove(&mut self, line: &str, layout: LayoutType, layout_map: &HashMap<String, CustomLayout>) -> Result<(), BindParseError> {
+        let mut iter = line.split(' ').filter(|t| !t.is_empty());
+        let (unbind, key_term) = match (iter.next(), iter.next()) {
+            (Some(unbind), Some(key_term)) => (unbind, key_term),
+            _ => return Err(BindParseError::NotEnoughTerms),
+        };
+        let (current_layout_only, context) = if let Some(context) = unbind.strip_prefix("unbind(").and_then(|c| c.strip_suffix(')')) {
+            (false, context)
+        } else if let Some(context) = unbind.strip_prefix("unbind-layout(").and_then(|c| c.strip_suffix(')')) {
+            (true, context)
+        } else {
+            return Err(BindParseError::MalformedBindTerm);
+        };
+        let keys: Vec<KeyEvent> = Self::parse_key_sequence(key_term)?.into_iter().map(|k| Self::bound_key(Config::to_qwerty_event(k, &layout, layout_map))).collect();
+        let layout_option = if current_layout_only { Some(layout) } else { None };
+        self.map.get_mut(&layout_option)
+                .and_then(|m| m.get_mut(context))
+                .and_then(|root| root.remove(&keys))
+                .map(|_| ())
+                .ok_or_else(|| BindParseError::NoMatchingBind{ key: key_term.to_string() })
+    }
+
+    /// Returns every key bind, sorted by context, then layout-agnostic binds before
+    /// layout-specific ones, then keys.
+    pub fn binds(&self) -> Vec<BindInfo<'_>> {
+        let mut binds = Vec::new();
+        for (layout, contexts) in self.map.iter() {
+            for (context, root) in contexts.iter() {
+                let mut sequences = Vec::new();
+                root.collect(&mut Vec::new(), &mut sequences);
+                binds.extend(sequences.into_iter().map(|(keys, binding)| {
+                    BindInfo{ layout: layout.as_ref(), context, keys, target: &binding.target }
+                }));
+            }
+        }
+        binds.sort_by_cached_key(|b| (b.context.to_string(), b.layout.map(|l| l.to_string()), Self::key_sequence_string(&b.keys)));
+        binds
+    }
+
+    /// Query the key binds for the sequence of key presses `keys`.
     /// 
     /// Layout-specific key binds are always returned in favor over layout-agnostic key binds, if
-    /// possible.
-    pub fn query(&self, context: &str, key: KeyEvent, layout: LayoutType, layout_map: &HashMap<String, CustomLayout>) -> Option<&Factory> {
-        let key = Config::to_qwerty_event(key, &layout, layout_map); // translate to QWERTY
-        let code = if let KeyCode::Char(c) = key.code { KeyCode::Char(c.to_ascii_uppercase()) } else { key.code };
-        let key = KeyEvent::new(code, key.modifiers);
-        self.map.get(&Some(layout)).map(|m| m.get(context).map(|m| m.get(&key))).flatten().flatten().or_else(||
-            self.map.get(&None).map(|m| m.get(context).map(|m| m.get(&key))).flatten().flatten()
-        )
+    /// possible. The key presses are the start of a longer key bind if they are the start of a
+    /// longer key bind of either kind.
+    pub fn query(&self, context: &str, keys: &[KeyEvent], layout: LayoutType, layout_map: &HashMap<String, CustomLayout>) -> BindMatch<'_> {
+        let keys: Vec<KeyEvent> = keys.iter().map(|key| {
+            let key = Config::to_qwerty_event(*key, &layout, layout_map); // translate to QWERTY
+            let shifted = key.modifiers.contains(KeyModifiers::SHIFT) || matches!(key.code, KeyCode::Char(c) if c.is_ascii_uppercase());
+            Self::trie_key(key, shifted)
+        }).collect();
+        let node = |layout| self.map.get(&layout).and_then(|m| m.get(context)).and_then(|root| root.get(&keys));
+        let (specific, agnostic) = (node(Some(layout)), node(None));
+        let binding = specific.and_then(|n| n.binding.as_ref()).or_else(|| agnostic.and_then(|n| n.binding.as_ref()));
+        let longer = [specific, agnostic].iter().flatten().any(|n| !n.children.is_empty());
+        match (binding, longer) {
+            (None, false) => BindMatch::Nothing,
+            (None, true) => BindMatch::Prefix,
+            (Some(binding), true) => BindMatch::Ambiguous(&binding.factory),
+            (Some(binding), false) => BindMatch::Complete(&binding.factory),
+        }
+    }
+
+    // a parsed key as it's kept in the tries (parsed letters are uppercase, shifted or not)
+    fn bound_key(key: KeyEvent) -> KeyEvent {
+        Self::trie_key(key, key.modifiers.contains(KeyModifiers::SHIFT))
+    }
+
+    // letters are kept lowercase unless they're shifted, since crossterm compares an uppercase
+    // letter as if Shift was held (which would make e.g. `v` and `<S-v>` the same key)
+    fn trie_key(key: KeyEvent, shifted: bool) -> KeyEvent {
+        match key.code {
+            KeyCode::Char(c) if c.is_ascii_alphabetic() && shifted => KeyEvent::new(KeyCode::Char(c.to_ascii_uppercase()), key.modifiers | KeyModifiers::SHIFT),
+            KeyCode::Char(c) if c.is_ascii_alphabetic() => KeyEvent::new(KeyCode::Char(c.to_ascii_lowercase()), key.modifiers),
+            _ => key,
+        }
+    }
+
+    /// Format a key event the way it would be written in a `bind` line (e.g. `<C-S>` or `u`).
+    ///
+    /// Letters are written in lower case, with `S-` if they are shifted (e.g. `<S-g>`, which can
+    /// also be written `G`).
+    pub fn key_event_string(key: KeyEvent) -> String {
+        let name = match key.code {
+            KeyCode::Char(' ') => String::from("Space"),
+            KeyCode::Char(c) => c.to_ascii_lowercase().to_string(),
+            KeyCode::F(n) => format!("F{}", n),
+            code => String::from(UNPRINTABLE_NAMES.iter().find(|(_, c)| *c == code).map_or("?", |(name, _)| *name)),
+        };
+        let mut modifiers = String::new();
+        for (modifier, prefix) in [(KeyModifiers::CONTROL, "C-"), (KeyModifiers::SHIFT, "S-"), (KeyModifiers::ALT, "A-")] {
+            if key.modifiers.contains(modifier) { modifiers.push_str(prefix); }
+        }
+        if modifiers.is_empty() && name.chars().count() == 1 {
+            name
+        } else {
+            format!("<{}{}>", modifiers, name)
+        }
+    }
+
+    /// Format a sequence of key events the way it would be written in a `bind` line (e.g. `dd` or
+    /// `<C-w>v`).
+    pub fn key_sequence_string(keys: &[KeyEvent]) -> String {
+        keys.iter().map(|k| Self::key_event_string(*k)).collect()
+    }
+
+    /// Parse a key event term that is a sequence of key events (e.g. `gg` or `<C-w><Left>`).
+    ///
+    /// A `<` starts a special character or modifier string if it parses as one up to one of the
+    /// following `>`s, and is the `<` key otherwise. An uppercase letter is the letter with Shift
+    /// (e.g. `G` is `<S-g>`).
+    pub fn parse_key_sequence(term: &str) -> Result<Vec<KeyEvent>, BindParseError> {
+        if term.is_empty() {
+            return Err(BindParseError::MalformedKeyEventTerm);
+        }
+        let mut keys = Vec::new();
+        let mut rest = term;
+        while let Some(c) = rest.chars().next() {
+            let bracketed = if c == '<' {
+                rest.match_indices('>').find_map(|(i, _)| Self::parse_key_event(&rest[..=i]).ok().map(|k| (k, i + 1)))
+            } else {
+                None
+            };
+            let (key, len) = match bracketed {
+                Some(key) => key,
+                None if c.is_ascii_uppercase() => (KeyEvent::new(KeyCode::Char(c), KeyModifiers::SHIFT), 1),
+                None => (Self::parse_key_event(&rest[..c.len_utf8()])?, c.len_utf8()),
+            };
+            keys.push(key);
+            rest = &rest[len..];
+        }
+        Ok(keys)
     }
 
     #[doc(hidden)]
@@ -176,7 +384,7 @@ impl KeyBinds {
     }
 
     #[doc(hidden)]
-    pub fn parse_line(line: &str) -> Result<(bool, String, KeyEvent, Factory), BindParseError> {
+    pub fn parse_line(line: &str) -> Result<(bool, String, Vec<KeyEvent>, Factory), BindParseError> {
         let mut current_layout_only = false;
         let mut iter = line.split(' ');
         let bind = iter.next();
@@ -195,8 +403,9 @@ impl KeyBinds {
                 current_layout_only = true;
             }
         }
-        if let Some(old_context) = bind.get(5..bind.len() - 1) {
-            let key_event = Self::parse_key_event(key_event)?;
+        let context_start = if current_layout_only { 12 } else { 5 };
+        if let Some(old_context) = bind.get(context_start..bind.len() - 1) {
+            let key_event = Self::parse_key_sequence(key_event)?;
             let args = String::from(iter.fold(String::new(), |acc, x| acc + " " + x).trim());
            
             if let Some(factory) = context(new_context, args) {
@@ -212,18 +421,18 @@ impl KeyBinds {
 
 #[test]
 fn test_parse_line_key_event() {
-    assert_eq!(Config::parse_key_event("", 1).err(), Some(BindParseError::MalformedKeyEventTerm));
-    assert_eq!(Config::parse_key_event("a", 1).unwrap(), KeyEvent::new(KeyCode::Char('A'), KeyModifiers::NONE));
-    assert_eq!(Config::parse_key_event("B", 1).unwrap(), KeyEvent::new(KeyCode::Char('B'), KeyModifiers::NONE));
-    assert_eq!(Config::parse_key_event("<Tab>", 1).unwrap(), KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE));
-    assert_eq!(Config::parse_key_event("<tab>", 1).err(), Some(BindParseError::MalformedKeyEventTerm));
-    assert_eq!(Config::parse_key_event("<C-C>", 1).unwrap(), KeyEvent::new(KeyCode::Char('C'), KeyModifiers::CONTROL));
-    assert_eq!(Config::parse_key_event("<C-S-A>", 1).unwrap(), KeyEvent::new(KeyCode::Char('A'), KeyModifiers::CONTROL.union(KeyModifiers::SHIFT)));
-    assert_eq!(Config::parse_key_event("<C-A-->", 1).unwrap(), KeyEvent::new(KeyCode::Char('-'), KeyModifiers::CONTROL.union(KeyModifiers::ALT)));
-    assert_eq!(Config::parse_key_event("<A-Enter>", 1).unwrap(), KeyEvent::new(KeyCode::Enter, KeyModifiers::ALT));
-    assert_eq!(Config::parse_key_event("<S-V>", 1).unwrap(), KeyEvent::new(KeyCode::Char('V'), KeyModifiers::SHIFT));
-    assert_eq!(Config::parse_key_event("<S-A-C>", 1).unwrap(), KeyEvent::new(KeyCode::Char('C'), KeyModifiers::SHIFT.union(KeyModifiers::ALT)));
-    assert_eq!(Config::parse_key_event("<C-S-A-Del>", 1).unwrap(), KeyEvent::new(KeyCode::Delete, KeyModifiers::CONTROL.union(KeyModifiers::SHIFT.union(KeyModifiers::ALT))));
+    assert_eq!(KeyBinds::parse_key_event("").err(), Some(BindParseError::MalformedKeyEventTerm));
+    assert_eq!(KeyBinds::parse_key_event("a").unwrap(), KeyEvent::new(KeyCode::Char('A'), KeyModifiers::NONE));
+    assert_eq!(KeyBinds::parse_key_event("B").unwrap(), KeyEvent::new(KeyCode::Char('B'), KeyModifiers::NONE));
+    assert_eq!(KeyBinds::parse_key_event("<Tab>").unwrap(), KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE));
+    assert_eq!(KeyBinds::parse_key_event("<tab>").err(), Some(BindParseError::MalformedKeyEventTerm));
+    assert_eq!(KeyBinds::parse_key_event("<C-C>").unwrap(), KeyEvent::new(KeyCode::Char('C'), KeyModifiers::CONTROL));
+    assert_eq!(KeyBinds::parse_key_event("<C-S-A>").unwrap(), KeyEvent::new(KeyCode::Char('A'), KeyModifiers::CONTROL.union(KeyModifiers::SHIFT)));
+    assert_eq!(KeyBinds::parse_key_event("<C-A-->").unwrap(), KeyEvent::new(KeyCode::Char('-'), KeyModifiers::CONTROL.union(KeyModifiers::ALT)));
+    assert_eq!(KeyBinds::parse_key_event("<A-Enter>").unwrap(), KeyEvent::new(KeyCode::Enter, KeyModifiers::ALT));
+    assert_eq!(KeyBinds::parse_key_event("<S-V>").unwrap(), KeyEvent::new(KeyCode::Char('V'), KeyModifiers::SHIFT));
+    assert_eq!(KeyBinds::parse_key_event("<S-A-C>").unwrap(), KeyEvent::new(KeyCode::Char('C'), KeyModifiers::SHIFT.union(KeyModifiers::ALT)));
+    assert_eq!(KeyBinds::parse_key_event("<C-S-A-Del>").unwrap(), KeyEvent::new(KeyCode::Delete, KeyModifiers::CONTROL.union(KeyModifiers::SHIFT.union(KeyModifiers::ALT))));
 }
 
 #[test]
@@ -290,3 +499,106 @@ fn test_config_map() {
     assert_eq!(MAP.query("<Esc>"), Some(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)));
     assert_eq!(MAP.query_code("Esc"), Some(KeyCode::Esc));
 }
+
+#[test]
+fn test_key_event_string() {
+    for key in ["u", "<C-r>", "<Tab>", "<C-S-A-Del>", "<A-CR>", "<F5>", "<Space>", ":", "<S-v>", "<C-A-->"] {
+        let event = KeyBinds::parse_key_event(key).unwrap();
+        assert_eq!(KeyBinds::key_event_string(event), key);
+        assert_eq!(KeyBinds::parse_key_event(&KeyBinds::key_event_string(event)), Ok(event));
+    }
+    assert_eq!(KeyBinds::key_event_string(KeyBinds::parse_key_event("<Enter>").unwrap()), "<CR>");
+}
+
+#[test]
+fn test_add_remove_binds() {
+    let layouts = HashMap::new();
+    let mut binds = KeyBinds::new();
+    assert_eq!(binds.add("bind(NormalMode) u Action undo", LayoutType::Qwerty, &layouts), Ok(()));
+    assert_eq!(binds.add("bind-layout(NormalMode) <C-r> Action redo", LayoutType::Dvorak, &layouts), Ok(()));
+    assert_eq!(binds.add("bind(InsertMode) <Left> Action move_left", LayoutType::Qwerty, &layouts), Ok(()));
+    let u = KeyEvent::new(KeyCode::Char('u'), KeyModifiers::NONE);
+    assert!(matches!(binds.query("NormalMode", &[u], LayoutType::Qwerty, &layouts), BindMatch::Complete(_)));
+    let listed: Vec<(Option<String>, &str, String, &str)> = binds.binds().iter().map(|b| (b.layout.map(|l| l.to_string()), b.context, KeyBinds::key_sequence_string(&b.keys), b.target)).collect();
+    assert_eq!(listed, vec![
+        (None, "InsertMode", String::from("<Left>"), "Action move_left"),
+        (None, "NormalMode", String::from("u"), "Action undo"),
+        (Some(String::from("Dvorak")), "NormalMode", String::from("<C-o>"), "Action redo"),
+    ]);
+
+    assert_eq!(binds.remove("unbind(NormalMode) u", LayoutType::Qwerty, &layouts), Ok(()));
+    assert!(matches!(binds.query("NormalMode", &[u], LayoutType::Qwerty, &layouts), BindMatch::Nothing));
+    assert_eq!(binds.remove("unbind(NormalMode) u", LayoutType::Qwerty, &layouts), Err(BindParseError::NoMatchingBind{ key: String::from("u") }));
+    assert_eq!(binds.remove("unbind(NormalMode) <C-r>", LayoutType::Dvorak, &layouts), Err(BindParseError::NoMatchingBind{ key: String::from("<C-r>") }));
+    assert_eq!(binds.remove("unbind-layout(NormalMode) <C-r>", LayoutType::Dvorak, &layouts), Ok(()));
+    assert_eq!(binds.remove("unbind(NormalMode)", LayoutType::Qwerty, &layouts), Err(BindParseError::NotEnoughTerms));
+    assert_eq!(binds.remove("unbound(NormalMode) u", LayoutType::Qwerty, &layouts), Err(BindParseError::MalformedBindTerm));
+    assert_eq!(binds.binds().len(), 1);
+}
+
+#[test]
+fn test_parse_key_sequence() {
+    let key = |k| KeyBinds::parse_key_event(k).unwrap();
+    assert_eq!(KeyBinds::parse_key_sequence("dd"), Ok(vec![key("d"), key("d")]));
+    assert_eq!(KeyBinds::parse_key_sequence("<C-w>v"), Ok(vec![key("<C-w>"), key("v")]));
+    assert_eq!(KeyBinds::parse_key_sequence("g<Left><CR>"), Ok(vec![key("g"), key("<Left>"), key("<CR>")]));
+    assert_eq!(KeyBinds::parse_key_sequence("<C-A-->"), Ok(vec![key("<C-A-->")]));
+    assert_eq!(KeyBinds::parse_key_sequence("<S->>"), Ok(vec![key("<S->>")]));
+    assert_eq!(KeyBinds::parse_key_sequence("<>"), Ok(vec![key("<"), key(">")]));
+    assert_eq!(KeyBinds::parse_key_sequence("<x"), Ok(vec![key("<"), key("x")]));
+    assert_eq!(KeyBinds::parse_key_sequence("gG"), Ok(vec![key("g"), key("<S-g>")]));
+    assert_eq!(KeyBinds::parse_key_sequence(""), Err(BindParseError::MalformedKeyEventTerm));
+    assert_eq!(KeyBinds::parse_key_sequence("gé"), Err(BindParseError::MalformedKeyEventTerm));
+    let keys = KeyBinds::parse_key_sequence("<C-w><Left>g").unwrap();
+    assert_eq!(KeyBinds::key_sequence_string(&keys), "<C-w><Left>g");
+}
+
+#[test]
+fn test_query_key_sequences() {
+    use crate::layout::{Dvorak, Layout};
+    let layouts = HashMap::new();
+    let mut binds = KeyBinds::new();
+    binds.add("bind(NormalMode) g Action earlier", LayoutType::Qwerty, &layouts).unwrap();
+    binds.add("bind(NormalMode) gg Action undo", LayoutType::Qwerty, &layouts).unwrap();
+    binds.add("bind(NormalMode) <C-w>v Action redo", LayoutType::Qwerty, &layouts).unwrap();
+    binds.add("bind-layout(NormalMode) <C-w>h Action move_left", LayoutType::Dvorak, &layouts).unwrap();
+    // the keys as a terminal sends them (parsed letters are uppercase, shifted or not)
+    let typed = |keys: &str| -> Vec<KeyEvent> { KeyBinds::parse_key_sequence(keys).unwrap().into_iter().map(|k| match k.code {
+        KeyCode::Char(c) if !k.modifiers.contains(KeyModifiers::SHIFT) => KeyEvent::new(KeyCode::Char(c.to_ascii_lowercase()), k.modifiers),
+        _ => k,
+    }).collect() };
+    let query = |binds: &KeyBinds, keys: &str, layout| match binds.query("NormalMode", &typed(keys), layout, &layouts) {
+        BindMatch::Nothing => "nothing",
+        BindMatch::Prefix => "prefix",
+        BindMatch::Ambiguous(_) => "ambiguous",
+        BindMatch::Complete(_) => "complete",
+    };
+    assert_eq!(query(&binds, "g", LayoutType::Qwerty), "ambiguous");
+    assert_eq!(query(&binds, "gg", LayoutType::Qwerty), "complete");
+    assert_eq!(query(&binds, "gx", LayoutType::Qwerty), "nothing");
+    assert_eq!(query(&binds, "<C-w>", LayoutType::Qwerty), "prefix");
+    assert_eq!(query(&binds, "<C-w>v", LayoutType::Qwerty), "complete");
+    assert_eq!(query(&binds, "<C-w>h", LayoutType::Qwerty), "nothing");
+    assert_eq!(query(&binds, "<S-g>", LayoutType::Qwerty), "nothing");
+    assert!(matches!(binds.query("NormalMode", &[KeyEvent::new(KeyCode::Char('G'), KeyModifiers::NONE)], LayoutType::Qwerty, &layouts), BindMatch::Nothing));
+    // an uppercase letter is bound as the shifted letter, apart from the lowercase one
+    binds.add("bind(NormalMode) G Action later", LayoutType::Qwerty, &layouts).unwrap();
+    assert_eq!(query(&binds, "<S-g>", LayoutType::Qwerty), "complete");
+    assert!(matches!(binds.query("NormalMode", &[KeyEvent::new(KeyCode::Char('G'), KeyModifiers::NONE)], LayoutType::Qwerty, &layouts), BindMatch::Complete(_)));
+    assert_eq!(query(&binds, "g", LayoutType::Qwerty), "ambiguous");
+    assert_eq!(binds.remove("unbind(NormalMode) <S-g>", LayoutType::Qwerty, &layouts), Ok(()));
+    // the same key positions in Dvorak
+    let dvorak: Vec<KeyEvent> = typed("<C-w>v").into_iter().map(|k| KeyEvent::new(Dvorak.from_qwerty_keycode(k.code), k.modifiers)).collect();
+    assert!(matches!(binds.query("NormalMode", &dvorak, LayoutType::Dvorak, &layouts), BindMatch::Complete(_)));
+    assert!(matches!(binds.query("NormalMode", &dvorak[..1], LayoutType::Dvorak, &layouts), BindMatch::Prefix));
+
+    assert_eq!(binds.remove("unbind(NormalMode) gg", LayoutType::Qwerty, &layouts), Ok(()));
+    assert_eq!(query(&binds, "g", LayoutType::Qwerty), "complete");
+    assert_eq!(binds.remove("unbind(NormalMode) <C-w>", LayoutType::Qwerty, &layouts), Err(BindParseError::NoMatchingBind{ key: String::from("<C-w>") }));
+    assert_eq!(binds.remove("unbind(NormalMode) <C-w>v", LayoutType::Qwerty, &layouts), Ok(()));
+    assert_eq!(query(&binds, "<C-w>", LayoutType::Qwerty), "nothing");
+    let listed: Vec<(String, Option<&LayoutType>)> = binds.binds().iter().map(|b| (KeyBinds::key_sequence_string(&b.keys), b.layout)).collect();
+    assert_eq!(listed.len(), 2);
+    assert!(listed[0] == (String::from("g"), None));
+    assert!(listed[1].1 == Some(&LayoutType::Dvorak));
+}
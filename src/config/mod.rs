@@ -24,34 +24,36 @@
 //! 
 //! ### Examples
 //! - `set line_numbering = Relative\r\n`: sets the `line_numbering` field in an
-//! [`Options`](self::options::Options) object
-//! to be [`LineNumbers::Relative`](self::options::LineNumbers::Relative).
+//!   [`Options`](self::options::Options) object
+//!   to be [`LineNumbers::Relative`](self::options::LineNumbers::Relative).
 //! - `set layout=Dvorak`: sets the `layout` field in an [`Options`](self::options::Options) object to be
-//! [`LayoutType::Dvorak`](self::options::LayoutType::Dvorak).
+//!   [`LayoutType::Dvorak`](self::options::LayoutType::Dvorak).
 //!
 //! ## `bind` Lines
 //! A `bind` line consists of four parts: the bind term, the key event term, the new
 //! context term, and the rest of the line (which represents optional arguments).
 //! - the `bind` term is formed like this: `bind(<context>)`, where `<context>` represents the name
-//! of the context to which this binding applies. For example, if you want to bind a key to perform
-//! an action in normal mode, the bind term would be `bind(NormalMode)`.
+//!   of the context to which this binding applies. For example, if you want to bind a key to perform
+//!   an action in normal mode, the bind term would be `bind(NormalMode)`.
 //!     - this type of `bind` term creates a layout-agnostic key bind. That is, if the current
-//!     layout is QWERTY, and you bind `S` to start `CommandMode`, no matter what keyboard layout
-//!     fim is currently in, you can press the key location where `S` is in QWERTY (e.g. `O` in Dvorak) to activate
-//!     `CommandMode`. This feature allows one to type in a different layout, while retaining
-//!     fim/vim QWERTY muscle memory.
+//!       layout is QWERTY, and you bind `s` to start `CommandMode`, no matter what keyboard layout
+//!       fim is currently in, you can press the key location where `s` is in QWERTY (e.g. `o` in Dvorak) to activate
+//!       `CommandMode`. This feature allows one to type in a different layout, while retaining
+//!       fim/vim QWERTY muscle memory.
 //!     - there is also a layout-specific bind term: `bind-layout(<context>)`. This only binds the
-//!     key in the current layout. For example: the line `set layout = Dvorak` followed by
-//!     `bind-layout(NormalMode) O CommandMode` would bind an `O` key press to start the
-//!     CommandMode context only when the current layout is Dvorak.
-//! - the key event term represents the key press that you are binding. See below.
+//!       key in the current layout. For example: the line `set layout = Dvorak` followed by
+//!       `bind-layout(NormalMode) o CommandMode` would bind an `o` key press to start the
+//!       CommandMode context only when the current layout is Dvorak.
+//! - the key event term represents the key press (or sequence of key presses) that you are
+//!   binding. See below.
 //! - the new context term is the name of the context that you want to change to. For example, if
-//! you wanted to enter command mode, the new context term would be `CommandMode`.
+//!   you wanted to enter command mode, the new context term would be `CommandMode`.
 //! - the optional arguments: no required form overall, specific to each context.
 //!
 //! ### Key Event Format
-//! A key event is either a literal key character (e.g. `A`, `6`, or `/`), one of the following
-//! representations of special characters, or a modifier string.
+//! A key event is either a literal key character (e.g. `a`, `6`, or `/`), one of the following
+//! representations of special characters, or a modifier string. An uppercase letter is the letter
+//! with Shift pressed (e.g. `G` is the same as `<S-g>`).
 //!
 //! ### Special Characters
 //! - `<Tab>`: the tab key
@@ -90,22 +92,46 @@
 //! - `<C-S-A-Left>`: Control + Shift + Alt + left arrow key
 //! - `<C-A-Del>`: Control + Alt + Delete (this will probably be intercepted by your OS)
 //!
+//! ### Key Sequences
+//! A key event term can also be several key events written one after another, e.g. `dd`, `g-` or
+//! `<C-w><Left>`. The key bind is used once all of them are pressed in order. While only the start
+//! of a key sequence has been pressed, fim waits for the next key press (showing the keys pressed
+//! so far at the end of the command line, if the `show_cmd` option is set). If the keys pressed
+//! so far are bound themselves (e.g. `g` when `gg` is also bound), they are used after
+//! `timeout_len` milliseconds without a key press, or when the next key press doesn't continue
+//! any key sequence.
+//!
+//! ## `unbind` Lines
+//! An `unbind` line removes a key bind made by an earlier `bind` line. It has the bind term (with
+//! `unbind` instead of `bind`) and the key event term of the `bind` line, e.g.
+//! `unbind(NormalMode) x` or `unbind-layout(NormalMode) O`.
+//!
+//! ## `alias` Lines
+//! An alias line defines a new ex command name that stands for a command line: `alias`, a space,
+//! the name of the alias (letters only), whitespace, then the command line. Any range, `!` and
+//! arguments given to the alias are added to the command line. See
+//! [`ExCommands::add_alias`](crate::command::ExCommands::add_alias).
+//!
+//! ### Examples
+//! - `alias W write!`: `:W` forces a write, and `:W out.txt` forces a write to `out.txt`.
+//! - `alias Top 1`: `:Top` moves the cursor to the first line.
+//!
 //! ## Comments
 //! Line comments begin with a `"`. Note that currently all comments must be on their own line.
 //!
 //! ### Examples
 //! - `" this is a comment`
 //! - `set line_numbering = Dvorak " I really like Dvorak`: DOES NOT WORK! Place the comment above
-//! the option statement.
+//!   the option statement.
 
 pub mod config_error;
 pub mod keybinds;
 pub mod options;
 
-use crate::context::Factory;
+use crate::command::ExCommands;
 use crate::layout::{ Colemak, CustomLayout, Dvorak, Layout, Qwerty };
 use self::config_error::{ ConfigParseError, IncludeParseError };
-use self::keybinds::KeyBinds;
+use self::keybinds::{BindMatch, KeyBinds};
 use self::options::{ LayoutType, Options };
 use crossterm::event::KeyEvent;
 use std::collections::HashMap;
@@ -113,13 +139,16 @@ use std::path::PathBuf;
 use std::fs::read_to_string;
 
 /// Struct to hold configuration details of fim.
+#[derive(Default)]
 pub struct Config {
     /// `Options` object.
     pub opt: Options,
     /// `KeyBinds` object.
     pub key_binds: KeyBinds,
     /// Map between layout name and the `CustomLayout` object.
-    pub layouts: HashMap<String, CustomLayout>
+    pub layouts: HashMap<String, CustomLayout>,
+    /// `ExCommands` object.
+    pub commands: ExCommands,
 }
 
 impl Config {
@@ -128,16 +157,17 @@ impl Config {
         let mut opt = Options::default();
         let mut key_binds = KeyBinds::new();
         let mut layouts = HashMap::new();
+        let mut commands = ExCommands::new();
         let string = read_to_string(file)?;
         for (line, line_no) in string.lines().zip(1usize..) {
-            Self::parse_line(line, line_no, &mut opt, &mut key_binds, &mut layouts)?;
+            Self::parse_line(line, line_no, &mut opt, &mut key_binds, &mut layouts, &mut commands)?;
         }
-        Ok(Config{ opt, key_binds, layouts })
+        Ok(Config{ opt, key_binds, layouts, commands })
     }
 
     /// Shortcut to calling `self.key_binds.query` with the appropriate arguments.
-    pub fn query_binds(&self, context: &str, key: KeyEvent) -> Option<&Factory> {
-        self.key_binds.query(context, key, self.opt.layout.clone(), &self.layouts)
+    pub fn query_binds(&self, context: &str, keys: &[KeyEvent]) -> BindMatch<'_> {
+        self.key_binds.query(context, keys, self.opt.layout.clone(), &self.layouts)
     }
 
     /// Return the trait object reference of the current layout, as according to `opt`.
@@ -149,7 +179,7 @@ impl Config {
     ///
     /// # Panics
     /// Panics if `layout` is a `LayoutType::Custom` and its `name` field is not a key in `map`.
-    pub fn layout<'a, 'b>(layout: &'a LayoutType, map: &'b HashMap<String, CustomLayout>) -> &'b dyn Layout {
+    pub fn layout<'b>(layout: &LayoutType, map: &'b HashMap<String, CustomLayout>) -> &'b dyn Layout {
         match layout {
             LayoutType::Dvorak => &Dvorak as &dyn Layout,
             LayoutType::Colemak => &Colemak as &dyn Layout,
@@ -173,24 +203,30 @@ impl Config {
         KeyEvent::new(key_code, e.modifiers)
     }
 
-    fn parse_line(line: &str, line_no: usize, opt: &mut Options, key_binds: &mut KeyBinds, layouts: &mut HashMap<String, CustomLayout>) -> Result<(), ConfigParseError> {
+    fn parse_line(line: &str, line_no: usize, opt: &mut Options, key_binds: &mut KeyBinds, layouts: &mut HashMap<String, CustomLayout>, commands: &mut ExCommands) -> Result<(), ConfigParseError> {
         // TODO: end of line comments
-        if line.trim().len() == 0 || line.starts_with('"') { return Ok(()); }
+        if line.trim().is_empty() || line.starts_with('"') { return Ok(()); }
         if line.starts_with("bind") {
-            let result = key_binds.add(line, opt.layout.clone(), layouts);
-            if result.is_err() {
-                return Err(ConfigParseError::bind(result.unwrap_err(), line_no));
+            if let Err(e) = key_binds.add(line, opt.layout.clone(), layouts) {
+                return Err(ConfigParseError::bind(e, line_no));
             }
         } else if line.starts_with("set") {
-            let result = opt.set_option(line);
-            if result.is_err() {
-                return Err(ConfigParseError::option(result.unwrap_err(), line_no));
+            if let Err(e) = opt.set_option(line) {
+                return Err(ConfigParseError::option(e, line_no));
             }
             if let LayoutType::Custom{ name } = &opt.layout {
                 if !layouts.contains_key(name.as_str()) {
                     return Err(ConfigParseError::NoMatchingLayout{ line: line_no });
                 }
             }
+        } else if line.starts_with("unbind") {
+            if let Err(e) = key_binds.remove(line, opt.layout.clone(), layouts) {
+                return Err(ConfigParseError::bind(e, line_no));
+            }
+        } else if line.starts_with("alias") {
+            if let Err(e) = commands.add_alias(line) {
+                return Err(ConfigParseError::alias(e, line_no));
+            }
         } else if line.starts_with("include") {
             Self::parse_include(line, line_no, opt, key_binds, layouts)?;
         } else {
@@ -199,16 +235,16 @@ impl Config {
         Ok(())
     }
 
-    fn parse_include(line: &str, line_no: usize, opt: &mut Options, key_binds: &mut KeyBinds, layouts: &mut HashMap<String, CustomLayout>) -> Result<(), ConfigParseError> {
+    fn parse_include(line: &str, line_no: usize, _opt: &mut Options, _key_binds: &mut KeyBinds, layouts: &mut HashMap<String, CustomLayout>) -> Result<(), ConfigParseError> {
         if let Some(line) = line.strip_prefix("include ") {
             if line.starts_with('\'') {
                 // TODO: support regular includes
                 Ok(())
             } else if let Some(line) = line.strip_prefix("layout ") {
-                if let Some((filename, rest)) = line.strip_prefix('\'').map(|l| l.split_once('\'')).flatten() {
+                if let Some((filename, rest)) = line.strip_prefix('\'').and_then(|l| l.split_once('\'')) {
                     let result = CustomLayout::new(filename.parse::<PathBuf>().unwrap());
                     if let Ok(layout) = result {
-                        let name = if rest.len() != 0 {
+                        let name = if !rest.is_empty() {
                             if let Some(name) = rest.strip_prefix(" as ").map(|l| l.trim()) {
                                 name
                             } else { return Err(ConfigParseError::include(IncludeParseError::MalformedAsClause, line_no)); }
@@ -223,9 +259,3 @@ impl Config {
         } else { Err(ConfigParseError::include(IncludeParseError::MalformedInclude, line_no)) }
     }
 }
-
-impl Default for Config {
-    fn default() -> Self {
-        Config{ opt: Options::default(), key_binds: KeyBinds::new(), layouts: HashMap::new() }
-    }
-}
@@ -1,13 +1,15 @@
 //! A module for handling internal configuration options.
 //!
 //! There are three different types of options: boolean, numeric, and string. These can be set by
-//! the user through configuration files or in-fim commands (eventually, not right now).
+//! the user through configuration files or the `:set` command (see [`crate::command`]).
 
 use read_option::ReadOption;
+use option_bool::OptionBool;
 use option_factory::OptionFactory;
 use option_number::OptionNumber;
 use option_string::OptionString;
 use std::num::ParseIntError;
+use std::path::PathBuf;
 
 /// Struct that represent the collection of internal configuration options.
 #[derive(Clone, OptionFactory, ReadOption)]
@@ -22,12 +24,51 @@ pub struct Options {
     pub tab_spaces: TabSpaces,
     /// Width of tab character (only when using tab for tabs).
     pub tab_width: TabWidth,
+    /// Milliseconds without a key press before unsaved changes are written to the differential
+    /// file; numeric option.
+    pub update_time: UpdateTime,
+    /// Number of key presses before unsaved changes are written to the differential file; numeric
+    /// option.
+    pub update_count: UpdateCount,
+    /// Whether to keep undo history in an undo file, so it lasts between editing sessions; boolean
+    /// option.
+    pub undo_file: UndoFile,
+    /// Directory to keep undo files in; string option.
+    pub undo_dir: UndoDir,
+    /// Milliseconds to wait for the next key press of a key bind that is a sequence of key
+    /// presses; numeric option.
+    pub timeout_len: TimeoutLen,
+    /// Whether to show the key presses of a key bind that is still being typed on the command
+    /// line; boolean option.
+    pub show_cmd: ShowCmd,
+    /// Shell command to copy the `"+` and `"*` registers to the system clipboard with; string
+    /// option.
+    pub clipboard_command: ClipboardCommand,
+    /// Shell command to read the `"+` and `"*` registers from the system clipboard with; string
+    /// option.
+    pub clipboard_paste_command: ClipboardCommand,
 }
 
-/// The defaults are relative line numbering and the QWERTY layout.
+/// The defaults are relative line numbering and the QWERTY layout, writing the differential file
+/// after 4 seconds of idle time or 200 key presses, no undo files, and waiting 1 second for the
+/// rest of a key bind (showing what has been typed of it). The system clipboard is copied to with
+/// OSC 52, and isn't read.
 impl Default for Options {
     fn default() -> Self {
-        Options{ line_numbering: LineNumbers::Relative, layout: LayoutType::Qwerty, tab_type: TabType::Spaces, tab_spaces: 4.into(), tab_width: 4.into() }
+        Options{ line_numbering: LineNumbers::Relative, layout: LayoutType::Qwerty, tab_type: TabType::Spaces, tab_spaces: 4.into(), tab_width: 4.into(), update_time: 4000.into(), update_count: 200.into(), undo_file: false.into(), undo_dir: UndoDir::default(), timeout_len: 1000.into(), show_cmd: true.into(), clipboard_command: ClipboardCommand::default(), clipboard_paste_command: ClipboardCommand::default() }
+    }
+}
+
+impl Options {
+    /// Flip the value of the boolean option called `opt_name`.
+    pub fn toggle_option(&mut self, opt_name: &str) -> Result<(), OptionParseError> {
+        if !self.is_bool_option(opt_name) {
+            // still an error about a missing option, if there is no such option
+            self.get_option(opt_name)?;
+            return Err(OptionParseError::NotABoolOption{ option: opt_name.to_string() });
+        }
+        let value: bool = self.get_option(opt_name)?.parse()?;
+        self.set_option(&format!("set {} = {}", opt_name, !value))
     }
 }
 
@@ -101,6 +142,17 @@ pub enum LayoutType {
     }
 }
 
+impl std::fmt::Display for LayoutType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Qwerty => write!(f, "Qwerty"),
+            Self::Dvorak => write!(f, "Dvorak"),
+            Self::Colemak => write!(f, "Colemak"),
+            Self::Custom{ name } => write!(f, "{}", name),
+        }
+    }
+}
+
 impl std::str::FromStr for LayoutType {
     type Err = std::convert::Infallible;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -128,14 +180,14 @@ pub enum TabType {
 ///
 /// This only applies when `tab_type` is `Tab`.
 /// For example, you could have tab characters be 4 characters long:
-/// ```no_run
+/// ```text
 /// fn main() {
 ///     println!("Hello, world!");
 /// }
 /// ```
 /// 
 /// Or 8 characters long:
-/// ```no_run
+/// ```text
 /// fn main() {
 ///         println!("Hello, world!");
 /// }
@@ -160,3 +212,198 @@ impl Verifiable for TabSpaces {
         if x > 0 { Ok(()) } else { Err("number must be positive (i.e. not negative or zero)".to_owned()) }
     }
 }
+
+/// Struct that represents how long (in milliseconds) fim waits for a key press before writing
+/// unsaved changes to the differential file.
+///
+/// This is analogous to vim's `updatetime`. A value of zero means the differential file is never
+/// written because of idle time.
+#[derive(Copy, Clone, OptionNumber)]
+pub struct UpdateTime(i32);
+
+impl Verifiable for UpdateTime {
+    fn verify(x: i32) -> Result<(), String> {
+        if x >= 0 { Ok(()) } else { Err("number must not be negative".to_owned()) }
+    }
+}
+
+/// Struct that represents how many key presses fim waits for before writing unsaved changes to
+/// the differential file.
+///
+/// This is analogous to vim's `updatecount`. A value of zero means the differential file is never
+/// written because of key presses.
+#[derive(Copy, Clone, OptionNumber)]
+pub struct UpdateCount(i32);
+
+impl Verifiable for UpdateCount {
+    fn verify(x: i32) -> Result<(), String> {
+        if x >= 0 { Ok(()) } else { Err("number must not be negative".to_owned()) }
+    }
+}
+
+/// Struct that represents whether fim keeps undo history in undo files.
+///
+/// This is analogous to vim's `undofile`. When it is `true`, the undo history of a file is written
+/// to an undo file when fim quits, and read when the file is opened again (as long as the file
+/// hasn't changed in the meantime).
+#[derive(Copy, Clone, OptionBool)]
+pub struct UndoFile(bool);
+
+/// Struct that represents the directory undo files are kept in.
+///
+/// This is analogous to vim's `undodir`. If it is empty (the default), the undo file of a file is
+/// kept next to it. Otherwise, undo files are kept in this directory, named after the full path of
+/// their file. A leading `~` is replaced by the home directory.
+#[derive(Clone, Default)]
+pub struct UndoDir(String);
+
+impl UndoDir {
+    /// Returns the directory, or `None` if undo files should be kept next to their files.
+    pub fn path(&self) -> Option<PathBuf> {
+        if self.0.is_empty() {
+            return None;
+        }
+        match (self.0.strip_prefix('~'), std::env::var_os("HOME")) {
+            (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+                Some(PathBuf::from(home).join(rest.trim_start_matches('/')))
+            },
+            _ => Some(PathBuf::from(&self.0)),
+        }
+    }
+}
+
+impl std::fmt::Display for UndoDir {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::str::FromStr for UndoDir {
+    type Err = std::convert::Infallible;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(UndoDir(s.to_string()))
+    }
+}
+
+/// Struct that represents how long (in milliseconds) fim waits for the next key press of a key
+/// bind that is a sequence of key presses.
+///
+/// This is analogous to vim's `timeoutlen`. When the key presses typed so far are bound, but are
+/// also the start of a longer key bind (e.g. `g` and `gg`), the shorter key bind is used once this
+/// much time passes without a key press. A value of zero means fim waits indefinitely.
+#[derive(Copy, Clone, OptionNumber)]
+pub struct TimeoutLen(i32);
+
+impl Verifiable for TimeoutLen {
+    fn verify(x: i32) -> Result<(), String> {
+        if x >= 0 { Ok(()) } else { Err("number must not be negative".to_owned()) }
+    }
+}
+
+/// Struct that represents whether fim shows the key presses of a key bind that is still being
+/// typed.
+///
+/// This is analogous to vim's `showcmd`. The key presses are shown at the right end of the
+/// command line.
+#[derive(Copy, Clone, OptionBool)]
+pub struct ShowCmd(bool);
+
+/// Struct that represents a shell command that copies to or reads from the system clipboard.
+///
+/// The command is run with `sh -c`; see [`crate::clipboard`]. If it is empty (the default), the
+/// system clipboard is copied to with OSC 52 (for `clipboard_command`), or isn't read at all (for
+/// `clipboard_paste_command`).
+#[derive(Clone, Default)]
+pub struct ClipboardCommand(String);
+
+impl ClipboardCommand {
+    /// Returns the command, or `None` if it is empty.
+    pub fn command(&self) -> Option<&str> {
+        if self.0.is_empty() { None } else { Some(&self.0) }
+    }
+}
+
+impl std::fmt::Display for ClipboardCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::str::FromStr for ClipboardCommand {
+    type Err = std::convert::Infallible;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(ClipboardCommand(s.to_string()))
+    }
+}
+
+#[test]
+fn test_update_options() {
+    let mut opt = Options::default();
+    assert!(opt.set_option("set update_time = 0").is_ok());
+    assert_eq!(i32::from(opt.update_time), 0);
+    assert!(opt.set_option("set update_count=50").is_ok());
+    assert_eq!(i32::from(opt.update_count), 50);
+    assert!(opt.set_option("set update_count = -1").is_err());
+}
+
+#[test]
+fn test_undo_options() {
+    let mut opt = Options::default();
+    assert!(!bool::from(opt.undo_file));
+    assert_eq!(opt.undo_dir.path(), None);
+    assert!(opt.set_option("set undo_file = true").is_ok());
+    assert!(bool::from(opt.undo_file));
+    assert!(opt.set_option("set undo_dir = /tmp/fim undo").is_ok());
+    assert_eq!(opt.undo_dir.path(), Some(PathBuf::from("/tmp/fim undo")));
+    if let Some(home) = std::env::var_os("HOME") {
+        assert!(opt.set_option("set undo_dir = ~/.fim/undo").is_ok());
+        assert_eq!(opt.undo_dir.path(), Some(PathBuf::from(home).join(".fim/undo")));
+    }
+}
+
+#[test]
+fn test_clipboard_options() {
+    let mut opt = Options::default();
+    assert_eq!(opt.clipboard_command.command(), None);
+    assert!(opt.set_option("set clipboard_command = xclip -selection clipboard").is_ok());
+    assert_eq!(opt.clipboard_command.command(), Some("xclip -selection clipboard"));
+    assert!(opt.set_option("set clipboard_paste_command = wl-paste -n").is_ok());
+    assert_eq!(opt.clipboard_paste_command.command(), Some("wl-paste -n"));
+    assert_eq!(opt.reset_option("clipboard_command"), Ok(()));
+    assert_eq!(opt.clipboard_command.command(), None);
+}
+
+#[test]
+fn test_get_reset_toggle_options() {
+    let mut opt = Options::default();
+    assert_eq!(opt.get_option("line_numbering"), Ok(String::from("Relative")));
+    assert_eq!(opt.get_option("layout"), Ok(String::from("Qwerty")));
+    assert_eq!(opt.get_option("tab_width"), Ok(String::from("4")));
+    assert_eq!(opt.get_option("undo_file"), Ok(String::from("false")));
+    assert_eq!(opt.get_option("undo_dir"), Ok(String::new()));
+    assert_eq!(opt.get_option("nope"), Err(OptionParseError::NoMatchingOption{ option: String::from("nope") }));
+    for name in Options::NAMES {
+        let value = opt.get_option(name).unwrap();
+        if !value.is_empty() {
+            assert_eq!(opt.set_option(&format!("set {} = {}", name, value)), Ok(()));
+        }
+    }
+
+    assert!(opt.set_option("set tab_width = 8").is_ok());
+    assert!(opt.set_option("set line_numbering = Off").is_ok());
+    assert_eq!(opt.reset_option("tab_width"), Ok(()));
+    assert_eq!(opt.get_option("tab_width"), Ok(String::from("4")));
+    assert_eq!(opt.get_option("line_numbering"), Ok(String::from("Off")));
+
+    assert!(opt.is_bool_option("undo_file"));
+    assert!(!opt.is_bool_option("tab_width"));
+    assert_eq!(opt.toggle_option("undo_file"), Ok(()));
+    assert!(bool::from(opt.undo_file));
+    assert_eq!(opt.toggle_option("tab_width"), Err(OptionParseError::NotABoolOption{ option: String::from("tab_width") }));
+    assert_eq!(opt.toggle_option("nope"), Err(OptionParseError::NoMatchingOption{ option: String::from("nope") }));
+    // a string option isn't a boolean option just because its value looks like one
+    assert!(opt.set_option("set undo_dir = true").is_ok());
+    assert!(!opt.is_bool_option("undo_dir"));
+    assert_eq!(opt.toggle_option("undo_dir"), Err(OptionParseError::NotABoolOption{ option: String::from("undo_dir") }));
+    assert_eq!(Options::NAMES.iter().filter(|name| opt.is_bool_option(name)).count(), 2);
+}
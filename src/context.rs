@@ -11,11 +11,32 @@
 //! beneath it on the context stack (the [`Context`] that becomes the active context after this one
 //! is popped). [`Context`]s also have a 'setup' function that is called once, at the time that
 //! [`Context`] becomes the active [`Context`].
+//!
+//! A key bind in `NormalMode` can be typed with a count before it (e.g. `5j`). The [`Context`] the
+//! key bind creates is handed the count before it is set up (see [`Context::count`]), and likewise
+//! the register typed before it (e.g. the `a` in `"ayy`, see [`Context::register`]).
+//! Contexts that change the text (see [`Context::is_change`]) let the editor know where a change
+//! begins and ends, so that it can be repeated with `.`.
+//!
+//! A [`Context`] can also return from its setup function. This is how [`Motion`]s and
+//! [`TextObject`]s work: they return the range of text they describe as soon as they are pushed,
+//! to `NormalMode` (which moves the cursor) or to an [`OperatorPending`] (which acts on the text).
+use crate::command;
+use crate::delta::{Differential, RecoverError};
 use crate::editor::{CmdLineFlags, Editor};
+use crate::motion::{MotionKind, ObjectKind, Operator, TextRange};
+use crate::register::{Register, Registers};
+use crate::window::SelectionKind;
 use std::cmp::min;
+use std::fs::remove_file;
+use std::path::PathBuf;
+use std::time::SystemTime;
 use crossterm::{
     Result,
-    event::{KeyCode, KeyEvent},
+    cursor::{Hide, Show},
+    event::{KeyCode, KeyEvent, KeyModifiers},
+    style::{Print, Stylize},
+    terminal::{Clear, ClearType},
 };
 
 /// Enum for return values of [`Context`]s.
@@ -32,6 +53,8 @@ pub enum ContextMessage {
     Float(f32),
     /// A boolean return value.
     Bool(bool),
+    /// A range of text in the current window's document, returned by motions and text objects.
+    Range(TextRange),
 }
 
 /// Trait to represent contexts.
@@ -41,9 +64,40 @@ pub trait Context {
     /// Function to setup the Context when it becomes the active context.
     ///
     /// Can cause the Context to 'return' and be popped off the context stack, if this function
-    /// returns Ok(true). If this function returns Ok(false), the Context has not 'returned'.
-    fn setup(&mut self, _ed: &mut Editor) -> Result<bool> {
-        Ok(false)
+    /// returns `Ok(Some(c))`, where `c` is a [`ContextMessage`]. If this function returns
+    /// `Ok(None)`, the Context has not 'returned'.
+    fn setup(&mut self, _ed: &mut Editor) -> Result<Option<ContextMessage>> {
+        Ok(None)
+    }
+
+    /// Receives the count typed before the key bind that created this Context (e.g. the `5` in
+    /// `5j`).
+    ///
+    /// Called once, before `setup`, and only if a count was typed. By default, the count is
+    /// ignored.
+    fn count(&mut self, _count: usize) {}
+
+    /// Receives the register typed before the key bind that created this Context (e.g. the `a` in
+    /// `"ayy`).
+    ///
+    /// Called once, before `setup`, and only if a register was typed. By default, the register is
+    /// ignored.
+    fn register(&mut self, _name: char) {}
+
+    /// Receives the operator waiting for the range this Context returns (e.g. the `c` in `cw`).
+    ///
+    /// Called once, before `setup`, and only for key binds typed after an operator (see
+    /// [`OperatorPending`]). By default, the operator is ignored.
+    fn operator(&mut self, _operator: Operator) {}
+
+    /// Returns `true` if the Context changes the text (e.g. an operator that deletes text, or
+    /// `InsertMode`).
+    ///
+    /// A key bind in `NormalMode` that pushes a Context that makes changes begins a change, which
+    /// ends when that Context returns. The key presses in between can be replayed with the `repeat`
+    /// action (see [`Editor::repeat_change`]). By default, a Context doesn't make changes.
+    fn is_change(&self) -> bool {
+        false
     }
 
     /// Accepts forwarded key presses.
@@ -88,19 +142,35 @@ impl Factory {
     }
 }
 
+/// The names of the contexts that [`context()`] knows about.
+pub const CONTEXTS: &[&str] = &["NormalMode", "CommandMode", "Action", "InsertMode", "UndoTreeView", "OperatorPending", "Motion", "TextObject", "VisualMode", "VisualLineMode", "VisualBlockMode", "Put", "Record", "Execute"];
+
 /// Maps between Strings and Contexts.
 ///
 /// # Arguments
 /// - `name`: the name of the Context
 /// - `args`: any additional arguments that need to be passed to the Context
 /// # Return
-/// Returns `None` if there is no context with the name `name`. Returns a [`Factory`] otherwise.
+/// Returns `None` if there is no context with the name `name` (or if `args` aren't valid arguments
+/// for it). Returns a [`Factory`] otherwise.
 pub fn context(name: &str, args: String) -> Option<Factory> {
     match name {
         "NormalMode" => Some(Factory::new(|| NormalMode)),
-        "CommandMode" => Some(Factory::new(|| CommandMode::new())),
+        "CommandMode" => Some(Factory::new(CommandMode::new)),
         "Action" => Some(Factory::new(move || Action::new(String::from(&args)))),
         "InsertMode" => Some(Factory::new(|| InsertMode)),
+        "UndoTreeView" => Some(Factory::new(UndoTreeView::new)),
+        "OperatorPending" => Operator::from_name(&args).map(|op| Factory::new(move || OperatorPending::new(op))),
+        "Motion" => MotionKind::from_name(&args).map(|kind| Factory::new(move || Motion::new(kind))),
+        "TextObject" => ObjectKind::from_name(&args).map(|kind| Factory::new(move || TextObject::new(kind))),
+        "VisualMode" if args == "reselect" => Some(Factory::new(VisualMode::reselect)),
+        "VisualMode" => Some(Factory::new(|| VisualMode::new(SelectionKind::Char))),
+        "VisualLineMode" => Some(Factory::new(|| VisualMode::new(SelectionKind::Line))),
+        "VisualBlockMode" => Some(Factory::new(|| VisualMode::new(SelectionKind::Block))),
+        "Put" if args == "before" => Some(Factory::new(|| Put::new(true))),
+        "Put" => Some(Factory::new(|| Put::new(false))),
+        "Record" => Some(Factory::new(|| Record)),
+        "Execute" => Some(Factory::new(Execute::new)),
         _ => None
     }
 }
@@ -108,25 +178,47 @@ pub fn context(name: &str, args: String) -> Option<Factory> {
 /// Struct that represents a "one-and-done" action context.
 ///
 /// This is the context that does one action in its `setup` method, and then returns. This is not
-/// for state-like contexts (e.g. `NormalMode` or `CommandMode`).
-///
-///
+/// for state-like contexts (e.g. `NormalMode` or `CommandMode`). With a count, the action is done
+/// that many times, except for `repeat`, which makes the change it repeats with that count instead.
 pub struct Action {
     #[doc(hidden)]
-    action: String
+    action: String,
+    #[doc(hidden)]
+    count: Option<usize>,
 }
 
 impl Action {
     /// Create a new `Action` corresponding to the passed string.
     pub fn new(action: String) -> Self {
-        Action{ action }
+        Action{ action, count: None }
     }
 }
 
 impl Context for Action {
-    fn setup(&mut self, ed: &mut Editor) -> Result<bool> {
-        ed.action(self.action.as_str())?;
-        Ok(true)
+    fn setup(&mut self, ed: &mut Editor) -> Result<Option<ContextMessage>> {
+        if self.action == "repeat" {
+            ed.repeat_change(self.count)?;
+        } else {
+            for i in 0..self.count.unwrap_or(1) {
+                // so that a count larger than the document doesn't keep doing nothing
+                if !ed.action(self.action.as_str())? {
+                    // like vim, an action that can't be done at all stops a macro
+                    if i == 0 {
+                        ed.abort_macros();
+                    }
+                    break;
+                }
+            }
+        }
+        Ok(Some(ContextMessage::Unit))
+    }
+
+    fn count(&mut self, count: usize) {
+        self.count = Some(count);
+    }
+
+    fn is_change(&self) -> bool {
+        matches!(self.action.as_str(), "delete" | "backspace")
     }
 }
 
@@ -134,22 +226,331 @@ impl Context for Action {
 ///
 /// Analogous to vim's normal mode. This context always starts as the active context, and there is
 /// always one instance of this struct at the bottom of the context stack.
+///
+/// Digits typed before a key bind are its count (see [`Editor::count_digit`]), and a `"` and a
+/// register name typed before it select its register (see [`Editor::register_prefix`]). The cursor
+/// is moved to the end of ranges returned to it (i.e. by [`Motion`]s).
 pub struct NormalMode;
 impl Context for NormalMode {
     fn forward(&mut self, ed: &mut Editor, event: KeyEvent) -> Result<Option<ContextMessage>> {
-        if let Some(factory) = ed.config().query_binds("NormalMode", event) {
-            let context = factory.create();
-            ed.push_boxed_context(context);
+        if !ed.register_prefix(event)? && !ed.count_digit(event)? {
+            ed.dispatch_key("NormalMode", event)?;
+        }
+        Ok(None)
+    }
+
+    fn receive(&mut self, ed: &mut Editor, arg: ContextMessage) -> Result<Option<ContextMessage>> {
+        if let ContextMessage::Range(range) = arg {
+            ed.on_current_window(|w, t| w.move_to_offset(range.cursor, t))?;
+        }
+        Ok(None)
+    }
+}
+
+/// Struct that represents a motion (e.g. vim's `w`).
+///
+/// Returns the range of text from the cursor to where the motion goes (see
+/// [`crate::motion::motion`]), or [`ContextMessage::Unit`] if the motion can't be made. Motions
+/// like `f` wait for the next key press, and are cancelled by anything other than a character.
+pub struct Motion {
+    #[doc(hidden)]
+    kind: MotionKind,
+    #[doc(hidden)]
+    count: Option<usize>,
+    #[doc(hidden)]
+    operator: Option<Operator>,
+}
+
+impl Motion {
+    /// Create a new `Motion` of the given kind.
+    pub fn new(kind: MotionKind) -> Self {
+        Motion{ kind, count: None, operator: None }
+    }
+
+    fn range(&self, ed: &mut Editor, c: Option<char>) -> Result<ContextMessage> {
+        let range = ed.on_current_window(|w, _| Ok(match self.kind {
+            // like vim, `cw` on a word changes to the end of it, like `ce`
+            MotionKind::WordForward | MotionKind::BigWordForward if self.operator == Some(Operator::Change) => {
+                let big = self.kind == MotionKind::BigWordForward;
+                w.change_word(big, self.count).or_else(|| w.motion(self.kind, self.count, c))
+            },
+            _ => w.motion(self.kind, self.count, c),
+        }))?;
+        if range.is_none() {
+            ed.abort_macros();
+        }
+        Ok(range.map_or(ContextMessage::Unit, ContextMessage::Range))
+    }
+}
+
+impl Context for Motion {
+    fn setup(&mut self, ed: &mut Editor) -> Result<Option<ContextMessage>> {
+        if self.kind.needs_char() {
+            return Ok(None);
+        }
+        self.range(ed, None).map(Some)
+    }
+
+    fn count(&mut self, count: usize) {
+        self.count = Some(count);
+    }
+
+    fn operator(&mut self, operator: Operator) {
+        self.operator = Some(operator);
+    }
+
+    fn forward(&mut self, ed: &mut Editor, event: KeyEvent) -> Result<Option<ContextMessage>> {
+        match event.code {
+            KeyCode::Char(c) => self.range(ed, Some(c)).map(Some),
+            _ => {
+                ed.abort_macros();
+                Ok(Some(ContextMessage::Unit))
+            },
+        }
+    }
+}
+
+/// Struct that represents a text object (e.g. vim's `iw`).
+///
+/// Returns the range of the text object around the cursor (see [`crate::motion::text_object`]),
+/// or [`ContextMessage::Unit`] if there isn't one.
+pub struct TextObject {
+    #[doc(hidden)]
+    kind: ObjectKind,
+    #[doc(hidden)]
+    count: usize,
+}
+
+impl TextObject {
+    /// Create a new `TextObject` of the given kind.
+    pub fn new(kind: ObjectKind) -> Self {
+        TextObject{ kind, count: 1 }
+    }
+}
+
+impl Context for TextObject {
+    fn setup(&mut self, ed: &mut Editor) -> Result<Option<ContextMessage>> {
+        let range = ed.on_current_window(|w, _| Ok(w.text_object(self.kind, self.count)))?;
+        if range.is_none() {
+            ed.abort_macros();
+        }
+        Ok(Some(range.map_or(ContextMessage::Unit, ContextMessage::Range)))
+    }
+
+    fn count(&mut self, count: usize) {
+        self.count = count;
+    }
+}
+
+/// Struct that represents an operator waiting for a motion or text object (e.g. vim's `d`,
+/// before the `w` in `dw`).
+///
+/// Key presses are looked up in the `OperatorPending` key binds, which should bind
+/// [`Motion`]s and [`TextObject`]s; the operator is applied to the range they return. Any key
+/// press that isn't bound cancels the operator. A count typed before the operator multiplies the
+/// count typed before the motion (e.g. `2d3w` deletes 6 words). The operator is handed to the motion
+/// too (see [`Context::operator`]), so that, like in vim, `cw` changes to the end of the word.
+///
+/// If there is a selection (i.e. in [`VisualMode`]), the operator is applied to it straight away,
+/// without waiting for a motion.
+///
+/// Yanked, deleted and changed text goes into the register typed before the operator (e.g. `"ayw`),
+/// or the registers that text goes into without one (see [`crate::register`]).
+pub struct OperatorPending {
+    #[doc(hidden)]
+    operator: Operator,
+    #[doc(hidden)]
+    count: usize,
+    #[doc(hidden)]
+    register: Option<char>,
+}
+
+impl OperatorPending {
+    /// Create a new `OperatorPending` for the given operator.
+    pub fn new(operator: Operator) -> Self {
+        OperatorPending{ operator, count: 1, register: None }
+    }
+
+    // puts the text that the operator yanked or deleted into the register
+    fn store(&self, ed: &mut Editor, text: Option<Register>) -> Result<()> {
+        let text = match text {
+            Some(text) => text,
+            None => return Ok(()),
+        };
+        match ed.store_register(self.register, text, self.operator != Operator::Yank) {
+            Ok(()) => Ok(()),
+            Err(e) => ed.q_draw_cmd_line([&e.to_string()], CmdLineFlags::all()),
+        }
+    }
+}
+
+impl Context for OperatorPending {
+    fn setup(&mut self, ed: &mut Editor) -> Result<Option<ContextMessage>> {
+        if let Some(Err(e)) = self.register.map(Registers::check_writable) {
+            ed.abort_macros();
+            ed.q_draw_cmd_line([&e.to_string()], CmdLineFlags::all())?;
+            return Ok(Some(ContextMessage::Unit));
+        }
+        if ed.on_current_window(|w, _| Ok(w.selection().is_some()))? {
+            let text = ed.on_current_window(|w, t| w.operate_on_selection(self.operator, t))?;
+            self.store(ed, text)?;
+            if self.operator == Operator::Change {
+                ed.push_context(InsertMode);
+            }
+            return Ok(Some(ContextMessage::Unit));
+        }
+        ed.multiply_count(self.count);
+        ed.pending_operator(self.operator);
+        Ok(None)
+    }
+
+    fn count(&mut self, count: usize) {
+        self.count = count;
+    }
+
+    fn register(&mut self, name: char) {
+        self.register = Some(name);
+    }
+
+    fn is_change(&self) -> bool {
+        self.operator != Operator::Yank
+    }
+
+    fn forward(&mut self, ed: &mut Editor, event: KeyEvent) -> Result<Option<ContextMessage>> {
+        if ed.count_digit(event)? || ed.dispatch_key("OperatorPending", event)? {
+            Ok(None)
+        } else {
+            ed.abort_macros();
+            Ok(Some(ContextMessage::Unit))
+        }
+    }
+
+    fn receive(&mut self, ed: &mut Editor, arg: ContextMessage) -> Result<Option<ContextMessage>> {
+        if let ContextMessage::Range(range) = arg {
+            let text = ed.on_current_window(|w, t| w.operate(self.operator, &range, t))?;
+            self.store(ed, text)?;
+            if self.operator == Operator::Change {
+                ed.push_context(InsertMode);
+            }
+        }
+        Ok(Some(ContextMessage::Unit))
+    }
+}
+
+/// Struct that represents putting the text in a register into the document (vim's `p` and `P`).
+///
+/// The text comes from the register typed before the key bind (e.g. `"ap`), or the unnamed
+/// register, and is put `count` times (see [`crate::window::Window::put`]).
+pub struct Put {
+    #[doc(hidden)]
+    before: bool,
+    #[doc(hidden)]
+    count: usize,
+    #[doc(hidden)]
+    register: Option<char>,
+}
+
+impl Put {
+    /// Create a new `Put` that puts text after the cursor, or before it if `before` is `true`.
+    pub fn new(before: bool) -> Self {
+        Put{ before, count: 1, register: None }
+    }
+}
+
+impl Context for Put {
+    fn setup(&mut self, ed: &mut Editor) -> Result<Option<ContextMessage>> {
+        match ed.read_register(self.register.unwrap_or('"')) {
+            Ok(text) => ed.on_current_window(|w, t| w.put(&text, self.before, self.count, t))?,
+            Err(e) => {
+                ed.abort_macros();
+                ed.q_draw_cmd_line([&e.to_string()], CmdLineFlags::all())?
+            },
+        }
+        Ok(Some(ContextMessage::Unit))
+    }
+
+    fn count(&mut self, count: usize) {
+        self.count = count;
+    }
+
+    fn register(&mut self, name: char) {
+        self.register = Some(name);
+    }
+
+    fn is_change(&self) -> bool {
+        true
+    }
+}
+
+/// Struct that represents recording a macro (e.g. vim's `qa`), or stopping the recording (vim's
+/// `q` while recording).
+///
+/// Waits for the name of the register to record into (see [`Registers::is_macro_register`]), and
+/// is cancelled by anything else. See [`Editor::start_recording`].
+pub struct Record;
+
+impl Context for Record {
+    fn setup(&mut self, ed: &mut Editor) -> Result<Option<ContextMessage>> {
+        if ed.recording().is_some() {
+            ed.stop_recording()?;
+            return Ok(Some(ContextMessage::Unit));
+        }
+        Ok(None)
+    }
+
+    fn forward(&mut self, ed: &mut Editor, event: KeyEvent) -> Result<Option<ContextMessage>> {
+        match event.code {
+            KeyCode::Char(c) if Registers::is_macro_register(c) => ed.start_recording(c)?,
+            _ => (),
         }
+        Ok(Some(ContextMessage::Unit))
+    }
+}
+
+/// Struct that represents executing a register as a macro (e.g. vim's `@a`, or `@@` for the last
+/// register executed).
+///
+/// Waits for the name of the register, and replays it as many times as its count (see
+/// [`Editor::execute_register`]).
+pub struct Execute {
+    #[doc(hidden)]
+    count: usize,
+}
+
+impl Default for Execute {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Execute {
+    /// Create a new `Execute`, which replays a register once.
+    pub fn new() -> Self {
+        Execute{ count: 1 }
+    }
+}
+
+impl Context for Execute {
+    fn setup(&mut self, _ed: &mut Editor) -> Result<Option<ContextMessage>> {
         Ok(None)
     }
+
+    fn count(&mut self, count: usize) {
+        self.count = count;
+    }
+
+    fn forward(&mut self, ed: &mut Editor, event: KeyEvent) -> Result<Option<ContextMessage>> {
+        if let KeyCode::Char(c) = event.code {
+            ed.execute_register(c, self.count)?;
+        }
+        Ok(Some(ContextMessage::Unit))
+    }
 }
 
 /// Struct that represents fim's CommandMode context.
 ///
-/// This is the context that (in vim) would allow you to enter ed commands or ':q'. I am not yet
-/// decided whether fim will use ed commands or something different, but this is still the context
-/// where you enter commands after a ':' at the bottom of the screen.
+/// This is the context where you enter commands after a ':' at the bottom of the screen (like
+/// vim's ex commands, e.g. ':w' or ':q'). See the [`command`] module for the commands.
 pub struct CommandMode {
     #[doc(hidden)]
     str: String,
@@ -161,12 +562,28 @@ pub struct CommandMode {
     rev_cmd_idx: Option<usize>,
     #[doc(hidden)]
     saved_str: Option<String>,
+    #[doc(hidden)]
+    wildmenu: Option<Wildmenu>,
+}
+
+// the completions being cycled through with Tab
+struct Wildmenu {
+    start: usize,          // where the completed word starts in the command line
+    typed: String,         // the word as it was typed
+    candidates: Vec<String>,
+    selected: Option<usize>, // `None` when showing the word as it was typed
+}
+
+impl Default for CommandMode {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CommandMode {
     /// Create a new CommandMode instance.
     pub fn new() -> CommandMode {
-        CommandMode{ str: String::new(), begin: 0, cursor_pos: 0, rev_cmd_idx: None, saved_str: None }
+        CommandMode{ str: String::new(), begin: 0, cursor_pos: 0, rev_cmd_idx: None, saved_str: None, wildmenu: None }
     }
 
     fn terminal_x(&self) -> u16 {
@@ -195,14 +612,101 @@ impl CommandMode {
     }
 
     fn delete(&mut self, ed: &mut Editor) -> Result<()> {
-        self.str.remove(self.cursor_pos.into()); 
+        self.str.remove(self.cursor_pos); 
         // TODO: adjust begin after delete?
         self.q_draw(ed)?;
         self.q_move(ed)?;
         ed.terminal().flush()
     }
 
-    fn get_command<'a>(&self, stack: &'a Vec<String>) -> Option<&'a String> {
+    // replaces the text from `start` to the cursor with `word`, moving the cursor to after it
+    fn replace_word(&mut self, start: usize, word: &str, width: u16) {
+        self.str.replace_range(start..self.cursor_pos, word);
+        self.cursor_pos = start + word.len();
+        if self.cursor_pos < self.begin {
+            self.begin = self.cursor_pos;
+        } else if self.cursor_pos + 2 > self.begin + usize::from(width) {
+            self.begin = self.cursor_pos + 2 - usize::from(width);
+        }
+    }
+
+    // completes the word before the cursor, or selects the next (or previous) completion
+    fn complete(&mut self, ed: &mut Editor, forward: bool) -> Result<()> {
+        let width = ed.terminal().size().width;
+        if self.wildmenu.is_none() {
+            let (start, candidates) = ed.config().commands.complete(&self.str[..self.cursor_pos]);
+            match candidates.len() {
+                0 => return Ok(()),
+                1 => self.replace_word(start, &candidates[0], width),
+                _ => {
+                    let typed = String::from(&self.str[start..self.cursor_pos]);
+                    self.wildmenu = Some(Wildmenu{ start, typed, candidates, selected: None });
+                },
+            }
+        }
+        if let Some(menu) = self.wildmenu.as_mut() {
+            // the word as it was typed comes after the last completion and before the first
+            let last = menu.candidates.len() - 1;
+            menu.selected = match (menu.selected, forward) {
+                (None, true) => Some(0),
+                (None, false) => Some(last),
+                (Some(i), true) => if i < last { Some(i + 1) } else { None },
+                (Some(i), false) => i.checked_sub(1),
+            };
+            let word = String::from(menu.selected.map_or(&menu.typed, |i| &menu.candidates[i]));
+            let start = menu.start;
+            self.replace_word(start, &word, width);
+            self.q_draw_wildmenu(ed)?;
+        }
+        self.q_draw(ed)?;
+        self.q_move(ed)?;
+        ed.terminal().flush()
+    }
+
+    // draws the completions on the line above the command line, a page at a time
+    fn q_draw_wildmenu(&self, ed: &mut Editor) -> Result<()> {
+        let menu = match self.wildmenu.as_ref() {
+            Some(menu) => menu,
+            None => return Ok(()),
+        };
+        let size = ed.terminal().size();
+        if size.height < 2 { return Ok(()); }
+        let width = usize::from(size.width).saturating_sub(4).max(1);
+        let items: Vec<String> = menu.candidates.iter().map(|c| c.chars().take(width).collect()).collect();
+        let mut pages = Vec::new();
+        pages.push(0..0);
+        for (i, item) in items.iter().enumerate() {
+            let page = pages.last_mut().unwrap();
+            let used: usize = items[page.clone()].iter().map(|i| i.chars().count() + 2).sum();
+            if page.end > page.start && used + item.chars().count() > width {
+                pages.push(i..i + 1);
+            } else {
+                page.end = i + 1;
+            }
+        }
+        let page = pages.iter().position(|p| menu.selected.is_some_and(|s| p.contains(&s))).unwrap_or(0);
+        let term = ed.terminal();
+        term.q(Hide)?.cursor_to(0, size.height - 2).q_move_cursor()?.q(Clear(ClearType::CurrentLine))?;
+        if page > 0 { term.q(Print("< "))?; }
+        for i in pages[page].clone() {
+            if menu.selected == Some(i) { term.q(Print(items[i].as_str().reverse()))?; } else { term.q(Print(&items[i]))?; }
+            if i + 1 < pages[page].end { term.q(Print("  "))?; }
+        }
+        if page + 1 < pages.len() { term.q(Print(" >"))?; }
+        term.q(Show)?;
+        Ok(())
+    }
+
+    // hides the completions, drawing the window over them again
+    fn close_wildmenu(&mut self, ed: &mut Editor) -> Result<()> {
+        if self.wildmenu.take().is_none() { return Ok(()); }
+        ed.on_current_window(|w, t| w.render(t))?;
+        self.q_draw(ed)?;
+        self.q_move(ed)?;
+        ed.terminal().flush()
+    }
+
+    fn get_command<'a>(&self, stack: &'a [String]) -> Option<&'a String> {
         if let Some(idx) = self.rev_cmd_idx {
             if stack.len() < idx + 1 { None } else { stack.get(stack.len() - idx - 1) }
         } else {
@@ -212,22 +716,29 @@ impl CommandMode {
 }
 
 impl Context for CommandMode {
-    fn setup(&mut self, ed: &mut Editor) -> Result<bool> {
-        ed.q_draw_cmd_line([":"], CmdLineFlags::FLUSH | CmdLineFlags::SAVECURSOR)?;
-        Ok(false)
+    fn setup(&mut self, ed: &mut Editor) -> Result<Option<ContextMessage>> {
+        ed.q_draw_cmd_line([":", &self.str], CmdLineFlags::FLUSH | CmdLineFlags::SAVECURSOR)?;
+        Ok(None)
+    }
+
+    // like vim, a count starts the command line with a range of that many lines
+    fn count(&mut self, count: usize) {
+        self.str = if count > 1 { format!(".,.+{}", count - 1) } else { String::from(".") };
+        self.cursor_pos = self.str.len();
     }
 
     fn forward(&mut self, ed: &mut Editor, event: KeyEvent) -> Result<Option<ContextMessage>> {
         let KeyEvent{ code: c, modifiers: _ } = event;
         let size = ed.terminal().size();
+        if !matches!(c, KeyCode::Tab | KeyCode::BackTab) {
+            self.close_wildmenu(ed)?;
+        }
         match c {
+            KeyCode::Tab => self.complete(ed, true)?,
+            KeyCode::BackTab => self.complete(ed, false)?,
             KeyCode::Enter => {
-                // TODO: implement actual logic
-                match self.str.as_str() {
-                    "q" => ed.quit(),
-                    _ => (),
-                }
                 ed.q_draw_cmd_line([], CmdLineFlags::FLUSH | CmdLineFlags::RESTORECURSOR)?;
+                command::execute(ed, &self.str)?;
                 ed.push_command(String::from(&self.str));
                 return Ok(Some(ContextMessage::Unit))
             },
@@ -275,40 +786,32 @@ impl Context for CommandMode {
                     }
                 }
             },
-            KeyCode::Left => {
-                if self.cursor_pos > 0 {
-                    if self.terminal_x() == 1 && self.begin != 0 {
-                        self.begin -= 1;
-                        self.q_draw(ed)?;
-                    } else {
-                        self.cursor_pos -= 1;
-                        self.q_move(ed)?;
-                    }
-                    ed.terminal().flush()?;
+            KeyCode::Left if self.cursor_pos > 0 => {
+                if self.terminal_x() == 1 && self.begin != 0 {
+                    self.begin -= 1;
+                    self.q_draw(ed)?;
+                } else {
+                    self.cursor_pos -= 1;
+                    self.q_move(ed)?;
                 }
+                ed.terminal().flush()?;
             },
-            KeyCode::Right => {
-                if self.cursor_pos < self.str.len() {
-                    if self.terminal_x() + 1 == size.width {
-                        self.begin += 1;
-                        self.q_draw(ed)?;
-                    } else {
-                        self.cursor_pos += 1;
-                        self.q_move(ed)?;
-                    }
-                    ed.terminal().flush()?;
+            KeyCode::Right if self.cursor_pos < self.str.len() => {
+                if self.terminal_x() + 1 == size.width {
+                    self.begin += 1;
+                    self.q_draw(ed)?;
+                } else {
+                    self.cursor_pos += 1;
+                    self.q_move(ed)?;
                 }
+                ed.terminal().flush()?;
             },
-            KeyCode::Backspace => {
-                if self.cursor_pos > 0 {
-                    self.cursor_pos -= 1;
-                    self.delete(ed)?;
-                }
+            KeyCode::Backspace if self.cursor_pos > 0 => {
+                self.cursor_pos -= 1;
+                self.delete(ed)?;
             },
-            KeyCode::Delete => {
-                if self.cursor_pos < self.str.len() {
-                    self.delete(ed)?;
-                }
+            KeyCode::Delete if self.cursor_pos < self.str.len() => {
+                self.delete(ed)?;
             },
             KeyCode::Char(character) => {
                 if (self.begin == 0 && self.cursor_pos + 2 == size.width.into()) || (self.begin != 0 && self.cursor_pos == self.str.len()) {
@@ -336,33 +839,416 @@ impl Context for CommandMode {
 pub struct InsertMode;
 
 impl Context for InsertMode {
-    fn setup(&mut self, ed: &mut Editor) -> Result<bool> {
-        ed.q_draw_cmd_line(["-- INSERT --"], CmdLineFlags::all())?;
-        Ok(false)
+    fn setup(&mut self, ed: &mut Editor) -> Result<Option<ContextMessage>> {
+        // everything typed until Esc is undone at once
+        ed.on_current_window(|w, _| {
+            w.begin_undo_group();
+            Ok(())
+        })?;
+        ed.q_draw_mode("-- INSERT --")?;
+        Ok(None)
+    }
+
+    fn is_change(&self) -> bool {
+        true
     }
 
     fn forward(&mut self, ed: &mut Editor, key: KeyEvent) -> Result<Option<ContextMessage>> {
-        // matches built-in binds first, then checks for user binds, and then checks for chars
-        let code = key.code;
-        match code {
-            KeyCode::Enter => (),
-            KeyCode::Tab => (),
+        // checks for user binds first (so that a key bind being typed can be finished by any key),
+        // then matches built-in binds, and then checks for chars
+        if ed.dispatch_key("InsertMode", key)? {
+            return Ok(None);
+        }
+        match key.code {
+            KeyCode::Enter => ed.on_current_window(|w, t| w.enter(t))?,
+            KeyCode::Tab => ed.on_current_window(|w, t| w.tab(t))?,
             KeyCode::Esc => {
+                ed.on_current_window(|w, t| {
+                    w.end_undo_group();
+                    w.leave_line_end(t)
+                })?;
                 ed.q_draw_cmd_line([], CmdLineFlags::all())?;
                 return Ok(Some(ContextMessage::Unit));
             },
-            _ => {
-                if let Some(factory) = ed.config().query_binds("InsertMode", key) {
-                    let context = factory.create();
-                    ed.push_boxed_context(context);
-                } else if let KeyCode::Char(c) = code {
-                    ed.on_current_window(|w, t| {
-                        w.insert(c, t)?;
-                        w.move_right(t)
-                    })?;
+            KeyCode::Char(c) => {
+                ed.on_current_window(|w, t| w.insert(c, t).map(|_| ()))?;
+            },
+            _ => (),
+        }
+        Ok(None)
+    }
+}
+
+/// Struct that represents fim's visual modes, where text is selected.
+///
+/// Analogous to vim's visual, visual line and visual block modes, which are the `VisualMode`,
+/// `VisualLineMode` and `VisualBlockMode` contexts. They all use the `VisualMode` key binds:
+/// [`Motion`]s and actions move the cursor (the other end of the selection stays where it was),
+/// and [`OperatorPending`]s act on the selection. Esc stops selecting, `o` moves the cursor to the
+/// other end of the selection, and `v`, `V` and `<C-v>` switch between the visual modes (or stop
+/// selecting, in the mode they start).
+pub struct VisualMode {
+    #[doc(hidden)]
+    kind: SelectionKind,
+    #[doc(hidden)]
+    reselect: bool,
+}
+
+impl VisualMode {
+    /// Create a new `VisualMode` that starts selecting from the cursor.
+    pub fn new(kind: SelectionKind) -> Self {
+        VisualMode{ kind, reselect: false }
+    }
+
+    /// Create a new `VisualMode` that selects the last selection again (like vim's `gv`).
+    pub fn reselect() -> Self {
+        VisualMode{ kind: SelectionKind::Char, reselect: true }
+    }
+
+    fn draw_mode(&self, ed: &mut Editor) -> Result<()> {
+        let mode = match self.kind {
+            SelectionKind::Char => "-- VISUAL --",
+            SelectionKind::Line => "-- VISUAL LINE --",
+            SelectionKind::Block => "-- VISUAL BLOCK --",
+        };
+        ed.q_draw_mode(mode)
+    }
+
+    fn close(&mut self, ed: &mut Editor) -> Result<Option<ContextMessage>> {
+        ed.on_current_window(|w, t| w.end_selection(t))?;
+        ed.q_draw_cmd_line([], CmdLineFlags::all())?;
+        Ok(Some(ContextMessage::Unit))
+    }
+}
+
+impl Context for VisualMode {
+    fn setup(&mut self, ed: &mut Editor) -> Result<Option<ContextMessage>> {
+        let kind = self.kind;
+        let selected = if self.reselect {
+            ed.on_current_window(|w, t| w.reselect(t))?
+        } else {
+            ed.on_current_window(|w, t| w.start_selection(kind, t))?.then_some(kind)
+        };
+        match selected {
+            Some(kind) => {
+                self.kind = kind;
+                self.draw_mode(ed)?;
+                Ok(None)
+            },
+            None => Ok(Some(ContextMessage::Unit)),
+        }
+    }
+
+    fn forward(&mut self, ed: &mut Editor, event: KeyEvent) -> Result<Option<ContextMessage>> {
+        if ed.register_prefix(event)? || ed.count_digit(event)? || ed.dispatch_key("VisualMode", event)? {
+            return Ok(None);
+        }
+        let kind = match (event.code, event.modifiers) {
+            (KeyCode::Esc, _) => return self.close(ed),
+            (KeyCode::Char('o'), KeyModifiers::NONE) => {
+                ed.on_current_window(|w, t| w.swap_selection_ends(t))?;
+                return Ok(None);
+            },
+            (KeyCode::Char('v'), KeyModifiers::NONE) => SelectionKind::Char,
+            (KeyCode::Char('V'), _) => SelectionKind::Line,
+            (KeyCode::Char('v'), KeyModifiers::CONTROL) => SelectionKind::Block,
+            _ => return Ok(None),
+        };
+        if kind == self.kind {
+            return self.close(ed);
+        }
+        self.kind = kind;
+        ed.on_current_window(|w, t| w.set_selection_kind(kind, t))?;
+        self.draw_mode(ed)?;
+        Ok(None)
+    }
+
+    fn receive(&mut self, ed: &mut Editor, arg: ContextMessage) -> Result<Option<ContextMessage>> {
+        if let ContextMessage::Range(range) = arg {
+            ed.on_current_window(|w, t| w.move_to_offset(range.cursor, t))?;
+        }
+        // an operator stops selecting
+        if ed.on_current_window(|w, _| Ok(w.selection().is_none()))? {
+            ed.q_draw_cmd_line([], CmdLineFlags::all())?;
+            return Ok(Some(ContextMessage::Unit));
+        }
+        ed.on_current_window(|w, t| w.update_selection(t))?;
+        Ok(None)
+    }
+}
+
+/// Struct that represents the prompt shown when a file has a differential file left over (e.g.
+/// because fim crashed while it was being edited).
+///
+/// The user can recover the changes in the differential file, discard them (deleting the
+/// differential file), or open the file read-only and decide later. Changes can't be recovered if
+/// the file was changed on disk after the differential file was written.
+pub struct RecoveryPrompt {
+    #[doc(hidden)]
+    backup: PathBuf,
+    #[doc(hidden)]
+    diff: Option<Differential>, // `None` if it can't be read or recovered
+}
+
+impl RecoveryPrompt {
+    /// Create a new RecoveryPrompt for the differential file `backup`.
+    pub fn new(backup: PathBuf) -> Self {
+        RecoveryPrompt{ backup, diff: None }
+    }
+
+    fn backup_name(&self) -> String {
+        self.backup.file_name().map_or_else(|| self.backup.display().to_string(), |n| n.to_string_lossy().into_owned())
+    }
+
+    fn q_draw_choices(&self, ed: &mut Editor, message: &str, flags: CmdLineFlags) -> Result<()> {
+        let choices = if self.diff.is_some() { "[r]ecover, [d]iscard, [o]pen read-only" } else { "[d]iscard, [o]pen read-only" };
+        ed.q_draw_cmd_line([message, ": ", choices], flags)
+    }
+}
+
+impl Context for RecoveryPrompt {
+    fn setup(&mut self, ed: &mut Editor) -> Result<Option<ContextMessage>> {
+        let message = match Differential::from_backup(&self.backup) {
+            Ok(diff) => {
+                let damaged = if diff.damaged() { " (damaged, some changes may be lost)" } else { "" };
+                self.diff = Some(diff);
+                format!("Found {}{}", self.backup_name(), damaged)
+            },
+            Err(e) => format!("Could not read {} ({})", self.backup_name(), e),
+        };
+        self.q_draw_choices(ed, &message, CmdLineFlags::FLUSH | CmdLineFlags::SAVECURSOR)?;
+        Ok(None)
+    }
+
+    fn forward(&mut self, ed: &mut Editor, event: KeyEvent) -> Result<Option<ContextMessage>> {
+        let message = match event.code {
+            KeyCode::Char('r') if self.diff.is_some() => {
+                let diff = self.diff.take().unwrap();
+                match diff.recover() {
+                    Ok(doc) => {
+                        let changes = diff.deltas().len();
+                        ed.on_current_window(|w, t| w.recover(doc, diff, t))?;
+                        format!("Recovered {} change(s) from {}", changes, self.backup_name())
+                    },
+                    Err(e) => {
+                        // the changes stay in the differential file (unless it's discarded), so
+                        // they can still be looked at by hand
+                        let message = match e {
+                            RecoverError::FileChanged => format!("Not recovering, {}", e),
+                            _ => format!("Could not recover ({})", e),
+                        };
+                        self.q_draw_choices(ed, &message, CmdLineFlags::FLUSH)?;
+                        return Ok(None);
+                    },
                 }
+            },
+            KeyCode::Char('d') => {
+                if let Err(e) = remove_file(&self.backup) {
+                    // stay in the prompt so the file can still be recovered or opened read-only
+                    let message = format!("Could not delete {} ({})", self.backup_name(), e);
+                    self.q_draw_choices(ed, &message, CmdLineFlags::FLUSH)?;
+                    return Ok(None);
+                }
+                format!("Deleted {}", self.backup_name())
+            },
+            KeyCode::Char('o') => {
+                ed.on_current_window(|w, _| {
+                    w.set_read_only(true);
+                    Ok(())
+                })?;
+                "-- READ ONLY --".to_string()
+            },
+            _ => return Ok(None),
+        };
+        ed.q_draw_cmd_line([&message], CmdLineFlags::FLUSH | CmdLineFlags::RESTORECURSOR)?;
+        Ok(Some(ContextMessage::Unit))
+    }
+}
+
+/// Struct that represents a view of the undo tree of the current window's document.
+///
+/// The tree is drawn over the window, newest undo steps first, with branches off of a branch
+/// indented under it. `j`/`k` (or the arrow keys) select an undo step, `Enter` makes the document
+/// as it was after the selected step, and `Esc` (or `q`) closes the view.
+pub struct UndoTreeView {
+    #[doc(hidden)]
+    tree: Vec<(usize, usize)>, // step number and branch depth of each line
+    #[doc(hidden)]
+    selected: usize, // line of the tree
+    #[doc(hidden)]
+    first_line: usize,
+}
+
+impl Default for UndoTreeView {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UndoTreeView {
+    /// Create a new UndoTreeView instance.
+    pub fn new() -> Self {
+        UndoTreeView{ tree: Vec::new(), selected: 0, first_line: 0 }
+    }
+
+    fn draw(&mut self, ed: &mut Editor) -> Result<()> {
+        let size = ed.terminal().size();
+        let height = (size.height - 1) as usize; // the command line stays
+        if self.selected < self.first_line {
+            self.first_line = self.selected;
+        } else if self.selected >= self.first_line + height {
+            self.first_line = self.selected + 1 - height;
+        }
+        let lines: Vec<String> = ed.on_current_window(|w, _| {
+            let history = match w.history() {
+                Some(history) => history,
+                None => return Ok(Vec::new()),
+            };
+            let now = SystemTime::now();
+            Ok(self.tree.iter().skip(self.first_line).take(height).map(|(step, depth)| {
+                let marker = if *step == history.current() { '>' } else { '*' };
+                let time = history.step(*step).map_or(String::new(), |s| time_ago(now, s.time()));
+                format!("{}{} {:<6} {}", "| ".repeat(*depth), marker, step, time)
+            }).collect())
+        })?;
+        let term = ed.terminal();
+        term.q(Hide)?;
+        for row in 0..height {
+            term.cursor_to(0, row as u16).q_move_cursor()?.q(Clear(ClearType::CurrentLine))?;
+            if let Some(line) = lines.get(row) {
+                let line: String = line.chars().take(size.width.into()).collect();
+                if self.first_line + row == self.selected {
+                    term.q(Print(line.reverse()))?;
+                } else {
+                    term.q(Print(line))?;
+                }
+            } else {
+                term.q(Print("~".blue()))?;
             }
         }
+        term.q(Show)?;
+        ed.q_draw_cmd_line(["-- UNDO TREE --"], CmdLineFlags::FLUSH)
+    }
+
+    fn close(&mut self, ed: &mut Editor) -> Result<Option<ContextMessage>> {
+        ed.q_draw_cmd_line([], CmdLineFlags::RESTORECURSOR)?;
+        ed.on_current_window(|w, t| w.render(t))?;
+        Ok(Some(ContextMessage::Unit))
+    }
+}
+
+impl Context for UndoTreeView {
+    fn setup(&mut self, ed: &mut Editor) -> Result<Option<ContextMessage>> {
+        let (tree, current) = ed.on_current_window(|w, _| Ok(w.history().map(|h| (h.tree(), h.current()))))?
+            .unwrap_or_default();
+        if tree.is_empty() {
+            return Ok(Some(ContextMessage::Unit));
+        }
+        self.selected = tree.iter().position(|(step, _)| *step == current).unwrap_or(0);
+        self.tree = tree;
+        ed.terminal().save_cursor();
+        self.draw(ed)?;
+        Ok(None)
+    }
+
+    fn forward(&mut self, ed: &mut Editor, event: KeyEvent) -> Result<Option<ContextMessage>> {
+        match event.code {
+            KeyCode::Char('j') | KeyCode::Down if self.selected + 1 < self.tree.len() => {
+                self.selected += 1;
+                self.draw(ed)?;
+            },
+            KeyCode::Char('k') | KeyCode::Up if self.selected > 0 => {
+                self.selected -= 1;
+                self.draw(ed)?;
+            },
+            KeyCode::Enter => {
+                let (step, _) = self.tree[self.selected];
+                let message = self.close(ed)?;
+                ed.on_current_window(|w, t| w.undo_to(step, t))?;
+                return Ok(message);
+            },
+            KeyCode::Esc | KeyCode::Char('q') => return self.close(ed),
+            _ => (),
+        }
         Ok(None)
     }
 }
+
+/// Struct that represents a read-only list of lines drawn over the window (e.g. the list of key
+/// binds).
+///
+/// `j`/`k` (or the arrow keys) scroll the list, and `Esc`, `q` or `Enter` closes it.
+pub struct ListView {
+    #[doc(hidden)]
+    title: String,
+    #[doc(hidden)]
+    lines: Vec<String>,
+    #[doc(hidden)]
+    first_line: usize,
+}
+
+impl ListView {
+    /// Create a new ListView showing `lines`, with `title` on the command line.
+    pub fn new(title: String, lines: Vec<String>) -> Self {
+        ListView{ title, lines, first_line: 0 }
+    }
+
+    fn draw(&mut self, ed: &mut Editor) -> Result<()> {
+        let size = ed.terminal().size();
+        let height = (size.height - 1) as usize; // the command line stays
+        let term = ed.terminal();
+        term.q(Hide)?;
+        for row in 0..height {
+            term.cursor_to(0, row as u16).q_move_cursor()?.q(Clear(ClearType::CurrentLine))?;
+            match self.lines.get(self.first_line + row) {
+                Some(line) => term.q(Print(line.chars().take(size.width.into()).collect::<String>()))?,
+                None => term.q(Print("~".blue()))?,
+            };
+        }
+        term.q(Show)?;
+        ed.q_draw_cmd_line([&self.title], CmdLineFlags::FLUSH)
+    }
+
+    fn close(&mut self, ed: &mut Editor) -> Result<Option<ContextMessage>> {
+        ed.q_draw_cmd_line([], CmdLineFlags::RESTORECURSOR)?;
+        ed.on_current_window(|w, t| w.render(t))?;
+        Ok(Some(ContextMessage::Unit))
+    }
+}
+
+impl Context for ListView {
+    fn setup(&mut self, ed: &mut Editor) -> Result<Option<ContextMessage>> {
+        ed.terminal().save_cursor();
+        self.draw(ed)?;
+        Ok(None)
+    }
+
+    fn forward(&mut self, ed: &mut Editor, event: KeyEvent) -> Result<Option<ContextMessage>> {
+        let height = (ed.terminal().size().height - 1) as usize;
+        match event.code {
+            KeyCode::Char('j') | KeyCode::Down if self.first_line + height < self.lines.len() => {
+                self.first_line += 1;
+                self.draw(ed)?;
+            },
+            KeyCode::Char('k') | KeyCode::Up if self.first_line > 0 => {
+                self.first_line -= 1;
+                self.draw(ed)?;
+            },
+            KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => return self.close(ed),
+            _ => (),
+        }
+        Ok(None)
+    }
+}
+
+// e.g. "5 seconds ago"
+fn time_ago(now: SystemTime, time: SystemTime) -> String {
+    let seconds = now.duration_since(time).unwrap_or_default().as_secs();
+    let (n, unit) = match seconds {
+        0..=59 => (seconds, "second"),
+        60..=3599 => (seconds / 60, "minute"),
+        3600..=86399 => (seconds / 3600, "hour"),
+        _ => (seconds / 86400, "day"),
+    };
+    format!("{} {}{} ago", n, unit, if n == 1 { "" } else { "s" })
+}
@@ -4,40 +4,345 @@
 //!
 //! Differentials are records of the (unsaved) changes to a file. The differential files on disk
 //! normally have an extension of `.fdiff`.
+//!
+//! # Differential File Format
+//! A differential file starts with the magic bytes `FIMDIFF\0` and the format version, as a
+//! little-endian `u32`. The rest of the file is a sequence of records, each of which is the length
+//! of its payload (a little-endian `u32`), a checksum of its payload (a little-endian `u64`), and
+//! the payload, serialized with bincode. The first record is the header (the path of the file, and
+//! its modification time and hash when fim opened it), every record after that but the last is a
+//! [`Delta`], and the last record is the [`UndoHistory`] of the file.
+//!
+//! If a write to the differential file is cut off, every record before the damaged one can still
+//! be read (without an undo history, the changes are undone one at a time). Differential files
+//! written in older versions of the format are migrated when read.
 use bincode::{serialize, deserialize};
+use document::{Document, EditError, PieceNode};
+use document::ranked_tree::RankedTree;
+use crate::undo::UndoHistory;
 use serde::{Serialize, Deserialize};
 use std::ffi::OsString;
-use std::fs::{read, write};
-use std::io::{Error, ErrorKind, Result};
+use std::fmt;
+use std::fs::{metadata, read, remove_file, rename, File};
+#[cfg(test)]
+use std::fs::write;
+use std::io::{Error, ErrorKind, Result, Write};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use unicode_segmentation::UnicodeSegmentation;
+
+const MTIME_GRANULARITY: Duration = Duration::from_secs(2);
+const MAGIC: &[u8; 8] = b"FIMDIFF\0";
+const VERSION: u32 = 2;
+const RECORD_HEADER_LEN: usize = 4 + 8; // payload length and checksum
+
+/// Enum representing a change to a file open in fim.
+///
+/// Offsets are grapheme offsets into the document (see [`Document`]). Deltas keep the text they
+/// remove as well as the text they add, so every delta can be inverted. Splitting a line is an
+/// insertion of a newline, and joining two lines is a deletion of one.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Delta {
+    /// `text` was inserted so that its first grapheme is at `offset`.
+    Insert {
+        /// Grapheme offset of the start of the insertion.
+        offset: usize,
+        /// The inserted text.
+        text: String,
+    },
+    /// `text`, which started at `offset`, was deleted.
+    Delete {
+        /// Grapheme offset of the start of the deletion.
+        offset: usize,
+        /// The deleted text.
+        text: String,
+    },
+    /// `old`, which started at `offset`, was replaced by `new`.
+    Replace {
+        /// Grapheme offset of the start of the replacement.
+        offset: usize,
+        /// The replaced text.
+        old: String,
+        /// The replacement text.
+        new: String,
+    },
+}
+
+impl Delta {
+    /// Make the change described by this delta to `doc`.
+    pub fn apply<T: RankedTree<PieceNode>>(&self, doc: &mut Document<T>) -> std::result::Result<(), EditError> {
+        match self {
+            Delta::Insert{ offset, text } => doc.insert(*offset, text),
+            Delta::Delete{ offset, text } => doc.delete(*offset..*offset + graphemes(text)),
+            Delta::Replace{ offset, old, new } => {
+                doc.delete(*offset..*offset + graphemes(old))?;
+                doc.insert(*offset, new)
+            }
+        }
+    }
+
+    /// Returns a single delta that has the same effect as this delta followed by `next`, if there
+    /// is one.
+    ///
+    /// Insertions (and deletions) that are next to each other are merged, as are insertions
+    /// followed by deletions of some of the inserted text, so typing a word (and fixing typos
+    /// along the way) ends up as one insertion. An insertion that is entirely deleted again merges
+    /// into an insertion of nothing.
+    pub fn merge(&self, next: &Delta) -> Option<Delta> {
+        match (self, next) {
+            (Delta::Insert{ offset, text }, Delta::Insert{ offset: next_offset, text: next_text }) => {
+                let within = next_offset.checked_sub(*offset).filter(|w| *w <= graphemes(text))?;
+                let split = byte_offset(text, within);
+                let text = [&text[..split], next_text, &text[split..]].concat();
+                Some(Delta::Insert{ offset: *offset, text })
+            },
+            (Delta::Delete{ offset, text }, Delta::Delete{ offset: next_offset, text: next_text }) => {
+                if next_offset == offset {
+                    // deleted forwards (e.g. with the delete key)
+                    Some(Delta::Delete{ offset: *offset, text: format!("{}{}", text, next_text) })
+                } else if next_offset + graphemes(next_text) == *offset {
+                    // deleted backwards (e.g. with backspace)
+                    Some(Delta::Delete{ offset: *next_offset, text: format!("{}{}", next_text, text) })
+                } else {
+                    None
+                }
+            },
+            (Delta::Insert{ offset, text }, Delta::Delete{ offset: next_offset, text: next_text }) => {
+                let start = next_offset.checked_sub(*offset)?;
+                let end = start + graphemes(next_text);
+                if end > graphemes(text) {
+                    return None;
+                }
+                let (start, end) = (byte_offset(text, start), byte_offset(text, end));
+                if &text[start..end] != next_text {
+                    return None;
+                }
+                Some(Delta::Insert{ offset: *offset, text: [&text[..start], &text[end..]].concat() })
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if applying this delta doesn't change anything.
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Delta::Insert{ text, .. } | Delta::Delete{ text, .. } => text.is_empty(),
+            Delta::Replace{ old, new, .. } => old == new,
+        }
+    }
+
+    /// Returns the delta that undoes this one.
+    pub fn inverse(&self) -> Delta {
+        match self.clone() {
+            Delta::Insert{ offset, text } => Delta::Delete{ offset, text },
+            Delta::Delete{ offset, text } => Delta::Insert{ offset, text },
+            Delta::Replace{ offset, old, new } => Delta::Replace{ offset, old: new, new: old },
+        }
+    }
+}
 
-/// Struct representing a change to a file open in fim.
+/// Enum for containing errors that might occur in recovering a file from its differential.
+#[derive(Debug)]
+pub enum RecoverError {
+    /// The file was changed on disk after the differential was written, so its deltas might not
+    /// make sense anymore.
+    FileChanged,
+    /// A delta couldn't be applied to the file.
+    BadDelta(EditError),
+    /// IO error (e.g. the file can no longer be read).
+    IOError(Error),
+}
+
+impl fmt::Display for RecoverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FileChanged => write!(f, "file changed on disk since the differential was written"),
+            Self::BadDelta(_) => write!(f, "a recorded change is past the end of the file"),
+            Self::IOError(e) => e.fmt(f),
+        }
+    }
+}
+
+impl From<Error> for RecoverError {
+    fn from(e: Error) -> Self {
+        Self::IOError(e)
+    }
+}
+
+// the first record of a differential file
 #[derive(Serialize, Deserialize)]
-pub struct Delta {
-    
+struct Header {
+    file: PathBuf,
+    mtime: Option<SystemTime>,
+    hash: u64,
 }
 
-/// Struct representing all of the changes to a file open in fim.
+// the records after the header
+#[derive(Deserialize)]
+enum Record {
+    Delta(Delta),
+    History(UndoHistory),
+}
+
+// `Record`, for writing without copying
+#[derive(Serialize)]
+enum RecordRef<'a> {
+    Delta(&'a Delta),
+    History(&'a UndoHistory),
+}
+
+// differential files from before the format was versioned were a `Differential` serialized as a
+// whole with bincode, back when a `Delta` had no fields
 #[derive(Serialize, Deserialize)]
+struct UnversionedDifferential {
+    deltas: Vec<UnversionedDelta>,
+    file: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct UnversionedDelta {}
+
+/// Struct representing all of the changes to a file open in fim.
+///
+/// The differential also remembers the modification time and a hash of the file's contents when
+/// it was created, so that it can tell if the file was changed by something else afterwards.
+///
+/// Besides a log of every change made (including undoing and redoing), which can be replayed
+/// onto the file, the differential keeps the file's [`UndoHistory`], with every branch of it.
 pub struct Differential {
     #[doc(hidden)]
     deltas: Vec<Delta>,
     #[doc(hidden)]
+    history: UndoHistory,
+    #[doc(hidden)]
     file: PathBuf,
+    #[doc(hidden)]
+    mtime: Option<SystemTime>, // `None` if the mtime can't be trusted (or doesn't exist)
+    #[doc(hidden)]
+    hash: u64,
+    #[doc(hidden)]
+    damaged: bool, // read from a differential file that had damaged records
 }
 
 impl Differential {
     /// Create a new Differential with no changes from a file path.
     pub fn new<P: AsRef<Path>>(file: P) -> Result<Self> {
-        Ok(Differential{ deltas: Vec::new(), file: file.as_ref().canonicalize()? })
+        let file = file.as_ref().canonicalize()?;
+        // a file modified very recently could be modified again without its mtime changing (mtimes
+        // are only so precise), so its mtime can't be trusted to detect changes
+        let mtime = metadata(&file)?.modified().ok().filter(|m| {
+            SystemTime::now().duration_since(*m).is_ok_and(|age| age > MTIME_GRANULARITY)
+        });
+        let hash = hash(&read(&file)?[..]);
+        Ok(Differential{ deltas: Vec::new(), history: UndoHistory::new(), file, mtime, hash, damaged: false })
+    }
+
+    /// Record a change to the file.
+    pub fn push(&mut self, delta: Delta) {
+        self.deltas.push(delta);
+    }
+
+    /// Returns the changes to the file, oldest first.
+    pub fn deltas(&self) -> &[Delta] {
+        &self.deltas[..]
+    }
+
+    /// Returns the undo history of the file.
+    pub fn history(&self) -> &UndoHistory {
+        &self.history
+    }
+
+    /// Returns the undo history of the file, mutably.
+    ///
+    /// The history only keeps track of the changes; changes that are undone and redone should
+    /// still be made to the document and recorded with [`Differential::push`].
+    pub fn history_mut(&mut self) -> &mut UndoHistory {
+        &mut self.history
+    }
+
+    /// Returns `true` if this differential was read from a differential file that was damaged
+    /// (e.g. by a write that was cut off), so changes after the damaged part were lost.
+    pub fn damaged(&self) -> bool {
+        self.damaged
+    }
+
+    /// Merge adjacent changes that can be expressed as a single change, and remove changes that
+    /// don't do anything. See [`Delta::merge`].
+    pub fn compact(&mut self) {
+        self.deltas = compacted(&self.deltas);
+    }
+
+    /// Returns the path of the file this differential records changes to.
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// Returns the hash of the file's contents when this differential was created.
+    pub fn file_hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Returns the path of the differential file for `file`.
+    ///
+    /// This is `file` with a `.` prepended to its name and an extension of `.fdiff` (e.g.
+    /// `src/.main.rs.fdiff` for `src/main.rs`).
+    pub fn backup_path<P: AsRef<Path>>(file: P) -> Result<PathBuf> {
+        let file = file.as_ref();
+        let mut backup_name = OsString::from(".");
+        let filename = file.file_name().map_or_else(|| Err(Error::other(format!("{} has no file name", file.display()))), Ok)?;
+        backup_name.push(filename);
+        backup_name.push(".fdiff");
+        Ok(file.with_file_name(backup_name))
     }
 
     /// Read a Differential into memory from the differential file.
     ///
-    /// Differential files normally have an extension of `.fdiff`.
+    /// Differential files normally have an extension of `.fdiff`. If the file is damaged after its
+    /// header, the changes before the damage are still read (see [`Differential::damaged`]).
     pub fn from_backup<P: AsRef<Path>>(backup_file: P) -> Result<Self> {
         let bytes = read(backup_file)?;
-        deserialize(&bytes[..]).map_err(|e| Error::new(ErrorKind::Other, e))
+        let bytes = match bytes.strip_prefix(&MAGIC[..]) {
+            Some(bytes) => bytes,
+            None => return Self::migrate_unversioned(&bytes),
+        };
+        let (version, mut records) = match bytes.split_first_chunk::<4>() {
+            Some((version, records)) => (u32::from_le_bytes(*version), records),
+            None => return Err(Error::new(ErrorKind::InvalidData, "differential file has no version")),
+        };
+        // when the format changes, older versions should be migrated here
+        let read_payload = match version {
+            // version 1 had no undo history
+            1 => |payload: &[u8]| deserialize(payload).ok().map(Record::Delta),
+            VERSION => |payload: &[u8]| deserialize(payload).ok(),
+            _ => return Err(Error::new(ErrorKind::InvalidData, format!("unsupported differential file version {}", version))),
+        };
+        let header: Header = read_record(&mut records)
+            .and_then(|payload| deserialize(payload).ok())
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "differential file header is damaged"))?;
+        let mut deltas = Vec::new();
+        let mut history = None;
+        let mut damaged = false;
+        while !records.is_empty() {
+            match read_record(&mut records).and_then(read_payload) {
+                Some(Record::Delta(delta)) => deltas.push(delta),
+                Some(Record::History(h)) => history = Some(h),
+                None => {
+                    damaged = true;
+                    break;
+                },
+            }
+        }
+        let history = history.unwrap_or_else(|| UndoHistory::linear(&deltas));
+        Ok(Differential{ deltas, history, file: header.file, mtime: header.mtime, hash: header.hash, damaged })
+    }
+
+    fn migrate_unversioned(bytes: &[u8]) -> Result<Self> {
+        let old: UnversionedDifferential = deserialize(bytes).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        // the deltas didn't record what was changed, so there is nothing to recover, only a file
+        // to start over from
+        let mut diff = Differential::new(&old.file)?;
+        diff.damaged = !old.deltas.is_empty();
+        Ok(diff)
     }
  
     /*
@@ -51,16 +356,264 @@ impl Differential {
     /// Write this differential to disk.
     ///
     /// The resulting differential file has the same path as the main file, but has an extension of
-    /// `.fdiff`.
+    /// `.fdiff`. The changes are compacted as they are written (see [`Differential::compact`]).
     pub fn backup(&self) -> Result<()> {
-        let bytes = serialize(self).map_err(|e| Error::new(ErrorKind::Other, e))?;
-        let mut backup_name = OsString::from(".");
-        let filename = self.file.file_name().map_or_else(|| Err(Error::new(ErrorKind::Other, format!("could not serialize, {} has no file name", self.file.display()))), |f| Ok(f))?;
-        backup_name.push(filename);
-        backup_name.push(".fdiff");
-        write(self.file.with_file_name(backup_name), &bytes[..])
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        let header = Header{ file: self.file.clone(), mtime: self.mtime, hash: self.hash };
+        write_record(&mut bytes, &header)?;
+        for delta in compacted(&self.deltas).iter() {
+            write_record(&mut bytes, &RecordRef::Delta(delta))?;
+        }
+        // last, since it's the least important to recover
+        write_record(&mut bytes, &RecordRef::History(&self.history))?;
+        write_replacing(&Self::backup_path(&self.file)?, &bytes[..])
     }
 
-    // TODO:
-    // pub fn recover(&self) -> Result<Document> {}
+    /// Delete this differential's file from disk, if there is one.
+    pub fn remove_backup(&self) -> Result<()> {
+        match remove_file(Self::backup_path(&self.file)?) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns `true` if the file on disk isn't the one this differential was created from.
+    ///
+    /// A file with the same modification time is assumed to be unchanged. Otherwise (e.g. the
+    /// file was only touched, or the modification time couldn't be trusted), the contents are
+    /// compared by hash.
+    pub fn file_changed(&self) -> Result<bool> {
+        let mtime = metadata(&self.file)?.modified().ok();
+        if mtime.is_some() && mtime == self.mtime {
+            return Ok(false);
+        }
+        Ok(hash(&read(&self.file)?[..]) != self.hash)
+    }
+
+    /// Replay the recorded changes onto the file on disk, returning the resulting document.
+    ///
+    /// Fails with [`RecoverError::FileChanged`] if the file has changed since the differential was
+    /// created, rather than applying deltas to text they weren't recorded against.
+    pub fn recover<T: RankedTree<PieceNode> + Default>(&self) -> std::result::Result<Document<T>, RecoverError> {
+        if self.file_changed()? {
+            return Err(RecoverError::FileChanged);
+        }
+        let mut doc = Document::new(&self.file)?;
+        for delta in self.deltas.iter() {
+            delta.apply(&mut doc).map_err(RecoverError::BadDelta)?;
+        }
+        Ok(doc)
+    }
+}
+
+// 64-bit FNV-1a, which (unlike `std`'s hashers) is guaranteed to give the same hash for the same
+// bytes across runs and versions of fim
+pub(crate) fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| (hash ^ *b as u64).wrapping_mul(0x100000001b3))
+}
+
+// writes `bytes` to a temporary file next to `path`, then renames it to `path`, so that a crash
+// while writing never leaves behind a differential file with less in it than the last one
+fn write_replacing(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = PathBuf::from(temp_path);
+    let result = (|| {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        rename(&temp_path, path)
+    })();
+    if result.is_err() {
+        let _ = remove_file(&temp_path);
+    }
+    result
+}
+
+fn compacted(deltas: &[Delta]) -> Vec<Delta> {
+    let mut compacted: Vec<Delta> = Vec::with_capacity(deltas.len());
+    for delta in deltas.iter() {
+        match compacted.last().and_then(|last| last.merge(delta)) {
+            Some(merged) => *compacted.last_mut().unwrap() = merged,
+            None => compacted.push(delta.clone()),
+        }
+        if compacted.last().is_some_and(Delta::is_empty) {
+            compacted.pop();
+        }
+    }
+    compacted
+}
+
+pub(crate) fn write_record<T: Serialize>(bytes: &mut Vec<u8>, value: &T) -> Result<()> {
+    let payload = serialize(value).map_err(Error::other)?;
+    let len = u32::try_from(payload.len()).map_err(|_| Error::other("record is too large for a differential file"))?;
+    bytes.extend(len.to_le_bytes());
+    bytes.extend(hash(&payload).to_le_bytes());
+    bytes.extend(payload);
+    Ok(())
+}
+
+// returns the payload of the record at the start of `bytes`, and moves `bytes` past it
+// returns `None` (without moving `bytes`) if the record is cut off or its checksum doesn't match
+pub(crate) fn read_record<'a>(bytes: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let (header, rest) = bytes.split_at_checked(RECORD_HEADER_LEN)?;
+    let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+    let checksum = u64::from_le_bytes(header[4..].try_into().unwrap());
+    let (payload, rest) = rest.split_at_checked(len)?;
+    if hash(payload) != checksum {
+        return None;
+    }
+    *bytes = rest;
+    Some(payload)
+}
+
+// byte offset of the grapheme at (grapheme) offset `offset` in `text`
+fn byte_offset(text: &str, offset: usize) -> usize {
+    text.grapheme_indices(true).nth(offset).map_or(text.len(), |(i, _)| i)
+}
+
+fn graphemes(text: &str) -> usize {
+    // text in a document has its CRLFs converted, and a CRLF is a single grapheme anyway
+    text.graphemes(true).count()
+}
+
+#[test]
+fn test_delta_apply_inverse() {
+    let mut doc: Document = Document::from("first\nsecond");
+    let deltas = [
+        Delta::Insert{ offset: 5, text: " line".to_string() },
+        Delta::Delete{ offset: 10, text: "\n".to_string() },
+        Delta::Replace{ offset: 0, old: "first".to_string(), new: "1st".to_string() },
+    ];
+    for delta in deltas.iter() {
+        delta.apply(&mut doc).unwrap();
+    }
+    assert_eq!(doc.text(0..doc.graphemes()).unwrap(), "1st linesecond");
+    for delta in deltas.iter().rev() {
+        delta.inverse().apply(&mut doc).unwrap();
+    }
+    assert_eq!(doc.text(0..doc.graphemes()).unwrap(), "first\nsecond");
+}
+
+#[test]
+fn test_differential_recover() {
+    let dir = std::env::temp_dir().join(format!("fim-test-recover-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("file.txt");
+    write(&file, "first\nsecond").unwrap();
+
+    let mut diff = Differential::new(&file).unwrap();
+    diff.push(Delta::Insert{ offset: 5, text: " line".to_string() });
+    diff.backup().unwrap();
+    let backup = Differential::backup_path(&file).unwrap();
+    assert_eq!(backup, dir.canonicalize().unwrap().join(".file.txt.fdiff"));
+    // the temporary file the backup is written to is renamed over the differential file
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+    let recovered = Differential::from_backup(&backup).unwrap();
+    let doc: Document = recovered.recover().unwrap();
+    assert_eq!(doc.text(0..doc.graphemes()).unwrap(), "first line\nsecond");
+
+    write(&file, "other\ntext").unwrap();
+    assert!(matches!(recovered.recover::<document::scapegoat_tree::ScapegoatTree<PieceNode>>(), Err(RecoverError::FileChanged)));
+
+    recovered.remove_backup().unwrap();
+    assert!(!backup.exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_delta_merge() {
+    let typed = Delta::Insert{ offset: 3, text: "ab".to_string() };
+    assert_eq!(typed.merge(&Delta::Insert{ offset: 5, text: "c".to_string() }), Some(Delta::Insert{ offset: 3, text: "abc".to_string() }));
+    assert_eq!(typed.merge(&Delta::Insert{ offset: 4, text: "c".to_string() }), Some(Delta::Insert{ offset: 3, text: "acb".to_string() }));
+    assert_eq!(typed.merge(&Delta::Insert{ offset: 6, text: "c".to_string() }), None);
+    assert_eq!(typed.merge(&Delta::Delete{ offset: 4, text: "b".to_string() }), Some(Delta::Insert{ offset: 3, text: "a".to_string() }));
+    assert_eq!(typed.merge(&Delta::Delete{ offset: 4, text: "x".to_string() }), None);
+    assert!(typed.merge(&Delta::Delete{ offset: 3, text: "ab".to_string() }).unwrap().is_empty());
+
+    let deleted = Delta::Delete{ offset: 3, text: "b".to_string() };
+    assert_eq!(deleted.merge(&Delta::Delete{ offset: 3, text: "c".to_string() }), Some(Delta::Delete{ offset: 3, text: "bc".to_string() }));
+    assert_eq!(deleted.merge(&Delta::Delete{ offset: 2, text: "a".to_string() }), Some(Delta::Delete{ offset: 2, text: "ab".to_string() }));
+    assert_eq!(deleted.merge(&Delta::Delete{ offset: 1, text: "a".to_string() }), None);
+}
+
+#[test]
+fn test_differential_file_format() {
+    let dir = std::env::temp_dir().join(format!("fim-test-format-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("file.txt");
+    write(&file, "text").unwrap();
+    let backup = Differential::backup_path(file.canonicalize().unwrap()).unwrap();
+
+    let mut diff = Differential::new(&file).unwrap();
+    for (i, c) in "abc".chars().enumerate() {
+        diff.push(Delta::Insert{ offset: i, text: c.to_string() });
+    }
+    diff.push(Delta::Insert{ offset: 1, text: "d".to_string() });
+    diff.push(Delta::Delete{ offset: 5, text: "x".to_string() });
+    diff.history_mut().begin_group();
+    diff.deltas.clone().into_iter().for_each(|delta| diff.history_mut().record(delta, Default::default()));
+    diff.history_mut().end_group();
+    diff.backup().unwrap();
+    let recovered = Differential::from_backup(&backup).unwrap();
+    assert!(!recovered.damaged());
+    assert_eq!(recovered.file(), diff.file());
+    assert_eq!(recovered.deltas(), &[
+        Delta::Insert{ offset: 0, text: "adbc".to_string() },
+        Delta::Delete{ offset: 5, text: "x".to_string() },
+    ]);
+    assert_eq!(recovered.history().latest(), 1);
+
+    // a torn write loses the damaged record and everything after it
+    let bytes = read(&backup).unwrap();
+    write(&backup, &bytes[..bytes.len() - 1]).unwrap();
+    let recovered = Differential::from_backup(&backup).unwrap();
+    assert!(recovered.damaged());
+    assert_eq!(recovered.deltas().len(), 2);
+    assert_eq!(recovered.history().latest(), 2);
+    let history_len = RECORD_HEADER_LEN + serialize(&RecordRef::History(diff.history())).unwrap().len();
+    write(&backup, &bytes[..bytes.len() - history_len - 1]).unwrap();
+    let recovered = Differential::from_backup(&backup).unwrap();
+    assert_eq!(recovered.deltas(), &[Delta::Insert{ offset: 0, text: "adbc".to_string() }]);
+
+    // so does a record whose checksum doesn't match
+    let mut damaged = bytes.clone();
+    let last = damaged.len() - history_len - 3;
+    damaged[last] ^= 0xff;
+    write(&backup, &damaged).unwrap();
+    let recovered = Differential::from_backup(&backup).unwrap();
+    assert!(recovered.damaged());
+    assert_eq!(recovered.deltas().len(), 1);
+
+    // a damaged header can't be recovered from
+    write(&backup, &bytes[..MAGIC.len() + 6]).unwrap();
+    assert!(Differential::from_backup(&backup).is_err());
+
+    // differential files in older formats are migrated
+    let mut version_1 = bytes[..MAGIC.len()].to_vec();
+    version_1.extend(1u32.to_le_bytes());
+    write_record(&mut version_1, &Header{ file: diff.file.clone(), mtime: diff.mtime, hash: diff.hash }).unwrap();
+    diff.deltas().iter().for_each(|delta| write_record(&mut version_1, delta).unwrap());
+    write(&backup, version_1).unwrap();
+    let recovered = Differential::from_backup(&backup).unwrap();
+    assert!(!recovered.damaged());
+    assert_eq!(recovered.deltas(), diff.deltas());
+    assert_eq!(recovered.history().latest(), diff.deltas().len());
+
+    // unversioned differential files have nothing to recover, so changes in them are lost
+    let unversioned = UnversionedDifferential{ deltas: vec![UnversionedDelta{}, UnversionedDelta{}], file: diff.file.clone() };
+    write(&backup, serialize(&unversioned).unwrap()).unwrap();
+    let recovered = Differential::from_backup(&backup).unwrap();
+    assert!(recovered.damaged());
+    assert!(recovered.deltas().is_empty());
+    assert_eq!(recovered.file(), diff.file());
+    assert_eq!(recovered.hash, hash(&read(diff.file()).unwrap()));
+
+    let unversioned = UnversionedDifferential{ deltas: Vec::new(), file: diff.file.clone() };
+    write(&backup, serialize(&unversioned).unwrap()).unwrap();
+    assert!(!Differential::from_backup(&backup).unwrap().damaged());
+
+    std::fs::remove_dir_all(&dir).unwrap();
 }
@@ -1,20 +1,31 @@
 //! A module that contains the main editor logic.
+use crate::clipboard::{self, ClipboardKind, Osc52};
+use crate::command::ExCommand;
 use crate::config::Config;
-use crate::config::keybinds::KeyBinds;
+use crate::config::keybinds::{BindMatch, KeyBinds};
 use crate::config::options::Options;
 use crate::context::*;
+use crate::delta::Differential;
+use crate::motion::Operator;
+use crate::register::{Register, RegisterError, Registers};
 use crate::terminal::{Position, Terminal};
+use crate::undo::Travel;
 use crate::window::Window;
 use bitflags::bitflags;
 use crossterm::{
     Result,
+    event::{KeyCode, KeyEvent, KeyModifiers},
     terminal::{
         Clear,
         ClearType,
     },
     style::Print,
 };
+use std::collections::VecDeque;
+use std::iter::once;
+use std::mem::{replace, take};
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 /// Struct that represents the fim editor.
 pub struct Editor<'a> {
@@ -36,37 +47,287 @@ pub struct Editor<'a> {
     current_window: usize,
     #[doc(hidden)]
     config: Config,
+    #[doc(hidden)]
+    keys_since_backup: usize,
+    #[doc(hidden)]
+    last_key: Instant,
+    #[doc(hidden)]
+    pending_keys: Vec<KeyEvent>,
+    #[doc(hidden)]
+    pending_context: String,
+    #[doc(hidden)]
+    queued_keys: VecDeque<Queued>,
+    #[doc(hidden)]
+    typeahead: VecDeque<KeyEvent>, // key presses typed while a macro was being replayed
+    #[doc(hidden)]
+    macro_depth: usize, // how many macro replays are in progress, one inside the other
+    #[doc(hidden)]
+    remap: bool,
+    #[doc(hidden)]
+    count: Option<usize>,
+    #[doc(hidden)]
+    count_factor: usize, // what the count is multiplied by (e.g. the 2 in `2d3w`)
+    #[doc(hidden)]
+    operator: Option<Operator>, // the operator waiting for the next key bind (e.g. the `c` in `cw`)
+    #[doc(hidden)]
+    registers: Registers,
+    #[doc(hidden)]
+    register: Option<char>, // the register typed before a key bind (e.g. the `a` in `"ayy`)
+    #[doc(hidden)]
+    register_pending: bool, // whether a `"` was typed, and the register name is next
+    #[doc(hidden)]
+    recording: Option<(char, Vec<KeyEvent>)>, // the register a macro is being recorded into, and its key presses
+    #[doc(hidden)]
+    bind_start: usize, // how many key presses were recorded before the current key bind
+    #[doc(hidden)]
+    last_macro: Option<char>, // the register `@@` executes
+    #[doc(hidden)]
+    changes: Changes, // the change being made, and the change `.` repeats
+}
+
+// how many macro replays can be in progress at once, so that a macro that executes itself stops
+const MAX_MACRO_DEPTH: usize = 1000;
+
+// what is processed before the key presses typed in the terminal
+enum Queued {
+    // a key press, and whether it may be looked up in the key binds
+    Key(KeyEvent, bool),
+    // the key presses of a macro, and how many more times to replay them
+    Macro(Vec<KeyEvent>, usize),
+    // the end of one replay of a macro
+    MacroEnd,
+}
+
+// the key presses of a change made from NormalMode, so that it can be repeated
+#[derive(Debug, PartialEq)]
+struct Change {
+    count: Option<usize>, // the count of the whole change (e.g. 6 for `2d3w`)
+    register: Option<char>,
+    keys: Vec<KeyEvent>, // from the key bind that began the change to the key press that ended it,
+                         // without the digits of counts typed in between
+}
+
+// keeps track of the change being made, and of the last change that was made
+#[derive(Default)]
+struct Changes {
+    change: Option<Change>, // the change being made
+    change_depth: Option<usize>, // the height of the context stack the change ends at
+    last_change: Option<Change>, // the change `.` repeats
+}
+
+impl Changes {
+    // begins a change with the key bind `keys`, unless one is already being made
+    fn begin(&mut self, count: Option<usize>, register: Option<char>, keys: &[KeyEvent]) {
+        if self.change.is_none() {
+            self.change = Some(Change{ count, register, keys: keys.to_vec() });
+        }
+    }
+
+    // adds a key press forwarded to the active context to the change being made
+    fn key(&mut self, event: KeyEvent) {
+        if let Some(change) = &mut self.change {
+            change.keys.push(event);
+        }
+    }
+
+    // the last key press was a digit of a count typed inside the change being made (e.g. the `3`
+    // in `d3w`), which isn't replayed, since `count` keeps the count of the whole change
+    fn count_digit(&mut self) {
+        if let Some(change) = &mut self.change {
+            change.keys.pop();
+        }
+    }
+
+    // a key bind inside the change being made was given `count`, which is the count of the whole
+    // change (the count before the change multiplied by the counts typed inside it)
+    fn count(&mut self, count: usize) {
+        if let Some(change) = &mut self.change {
+            change.count = Some(count);
+        }
+    }
+
+    // contexts are about to be pushed onto a context stack of height `height`; the change ends
+    // once the stack is back to that height
+    fn push(&mut self, height: usize) {
+        if self.change.is_some() && self.change_depth.is_none() {
+            self.change_depth = Some(height);
+        }
+    }
+
+    // the context stack is now `height` high, which ends the change if the context that began it
+    // has returned
+    fn returned(&mut self, height: usize) {
+        if self.change_depth.is_some_and(|depth| height <= depth) {
+            self.change_depth = None;
+            self.last_change = self.change.take();
+        }
+    }
 }
 
 impl<'a> Editor<'a> {
     /// Create a new Editor struct from a file.
     pub fn new(filename: PathBuf, config: Option<Config>) -> Result<Editor<'a>> {
-        let config = config.unwrap_or_else(|| Config::default());
+        let config = config.unwrap_or_default();
         let term = Terminal::new()?;
+        let backup = Differential::backup_path(&filename)?;
         let window = Window::new(filename, &term, config.opt.clone())?;
         // TODO: add real default config handling
-        Ok(Editor{ terminal: term, quit: false, context_stack: vec![Box::new(NormalMode)], push_context_stack: Vec::new(), has_been_setup_stack: vec![true], command_stack: Vec::new(), windows: vec![window], current_window: 0, config })
+        let mut editor = Self::with_window(term, window, config);
+        if backup.exists() {
+            editor.push_context(RecoveryPrompt::new(backup));
+        }
+        Ok(editor)
     }
 
     /// Create a new Editor struct with the default welcome screen.
     pub fn default(config: Option<Config>) -> Result<Editor<'a>> {
-        let config = config.unwrap_or_else(|| Config::default());
+        let config = config.unwrap_or_default();
         let term = Terminal::new()?;
         let window = Window::default(&term, config.opt.clone());
-        Ok(Editor{ terminal: term, quit: false, context_stack: vec![Box::new(NormalMode)], push_context_stack: Vec::new(), has_been_setup_stack: vec![true], command_stack: Vec::new(), windows: vec![window], current_window: 0, config })
+        Ok(Self::with_window(term, window, config))
+    }
+
+    fn with_window(terminal: Terminal, window: Window, config: Config) -> Editor<'a> {
+        Editor{ terminal, quit: false, context_stack: vec![Box::new(NormalMode)], push_context_stack: Vec::new(), has_been_setup_stack: vec![true], command_stack: Vec::new(), windows: vec![window], current_window: 0, config, keys_since_backup: 0, last_key: Instant::now(), pending_keys: Vec::new(), pending_context: String::new(), queued_keys: VecDeque::new(), typeahead: VecDeque::new(), macro_depth: 0, remap: true, count: None, count_factor: 1, operator: None, registers: Registers::new(), register: None, register_pending: false, recording: None, bind_start: 0, last_macro: None, changes: Changes::default() }
     }
 
     /// Run the editor logic.
     ///
     /// Returns only when the user has signalled they want to quit.
+    ///
+    /// Unsaved changes are written to differential files after `update_count` key presses, or
+    /// after `update_time` milliseconds without a key press (see
+    /// [`Options`](crate::config::options::Options)). The differential files are deleted when the
+    /// user quits, and undo histories are written to undo files (if the `undo_file` option is
+    /// set).
+    ///
+    /// Key presses that are the start of a longer key bind wait for the rest of it for
+    /// `timeout_len` milliseconds (see [`Editor::dispatch_key`]).
     pub fn run(&mut self) -> Result<()> {
         self.setup()?;
         loop {
-            self.process_keypress()?; 
+            if let Some(queued) = self.queued_keys.pop_front() {
+                self.process_queued(queued)?;
+            } else {
+                let typed = match self.typeahead.pop_front() {
+                    Some(event) => Some(event),
+                    None => self.terminal.read_key_timeout(self.key_timeout())?,
+                };
+                match typed {
+                    Some(event) => {
+                        self.last_key = Instant::now();
+                        self.process_keypress(event)?;
+                        self.keys_since_backup += 1;
+                        let update_count = i32::from(self.config.opt.update_count) as usize;
+                        if update_count > 0 && self.keys_since_backup >= update_count {
+                            self.backup()?;
+                        }
+                    },
+                    None => self.timed_out()?,
+                }
+            }
             if self.quit {
                 break;
             }
         }
+        self.windows.iter_mut().try_for_each(|w| {
+            w.save_undo()?;
+            w.remove_backup()
+        })
+    }
+
+    fn process_queued(&mut self, queued: Queued) -> Result<()> {
+        match queued {
+            Queued::Key(event, remap) => {
+                if self.macro_depth > 0 && self.interrupted()? {
+                    self.abort_macros();
+                    return self.q_draw_cmd_line(["Interrupted"], CmdLineFlags::all());
+                }
+                self.remap = remap;
+                self.process_event(event)?;
+                self.remap = true;
+            },
+            Queued::Macro(keys, count) => {
+                if self.macro_depth >= MAX_MACRO_DEPTH {
+                    self.abort_macros();
+                    return self.q_draw_cmd_line(["Macros executed too deeply (recursive macro?)"], CmdLineFlags::all());
+                }
+                self.macro_depth += 1;
+                if count > 1 {
+                    self.queued_keys.push_front(Queued::Macro(keys.clone(), count - 1));
+                }
+                self.queued_keys.push_front(Queued::MacroEnd);
+                self.queue_keys_front(keys.into_iter().map(|k| (k, true)).collect());
+            },
+            Queued::MacroEnd => self.macro_depth -= 1,
+        }
+        Ok(())
+    }
+
+    // whether Esc or Ctrl-C was typed (while a macro is being replayed); any other key presses
+    // are kept to be processed afterwards
+    fn interrupted(&mut self) -> Result<bool> {
+        while let Some(event) = self.terminal.read_key_timeout(Some(Duration::ZERO))? {
+            match event.code {
+                KeyCode::Esc => return Ok(true),
+                KeyCode::Char('c') if event.modifiers == KeyModifiers::CONTROL => return Ok(true),
+                _ => self.typeahead.push_back(event),
+            }
+        }
+        Ok(false)
+    }
+
+    // how long to wait for a key press before writing differential files
+    fn backup_timeout(&self) -> Option<Duration> {
+        let update_time = i32::from(self.config.opt.update_time) as u64;
+        if update_time > 0 && self.windows.iter().any(|w| w.backup_pending()) {
+            Some(Duration::from_millis(update_time))
+        } else {
+            None
+        }
+    }
+
+    // how long pending keys wait for the next key of a key bind, if they are waiting at all
+    fn pending_timeout(&self) -> Option<Duration> {
+        let timeout_len = i32::from(self.config.opt.timeout_len) as u64;
+        if timeout_len > 0 && !self.pending_keys.is_empty() {
+            Some(Duration::from_millis(timeout_len))
+        } else {
+            None
+        }
+    }
+
+    // how much longer to wait for a key press, before pending keys time out or differential files
+    // are written
+    fn key_timeout(&self) -> Option<Duration> {
+        let idle = self.last_key.elapsed();
+        [self.pending_timeout(), self.backup_timeout()].into_iter().flatten().min().map(|t| t.saturating_sub(idle))
+    }
+
+    fn timed_out(&mut self) -> Result<()> {
+        let idle = self.last_key.elapsed();
+        if self.pending_timeout().is_some_and(|t| idle >= t) {
+            let keys = take(&mut self.pending_keys);
+            let context = take(&mut self.pending_context);
+            self.resolve_keys(&context, keys)?;
+            self.push_pending_contexts()?;
+        }
+        if self.backup_timeout().is_some_and(|t| idle >= t) {
+            self.backup()?;
+        }
+        Ok(())
+    }
+
+    // writes the unsaved changes in every window to their differential files
+    fn backup(&mut self) -> Result<()> {
+        self.keys_since_backup = 0;
+        for i in 0..self.windows.len() {
+            if let Err(e) = self.windows[i].backup() {
+                // not being able to write a differential file shouldn't stop the user from editing
+                let message = format!("Could not write differential file ({})", e);
+                self.q_draw_cmd_line([&message], CmdLineFlags::all())?;
+            }
+        }
         Ok(())
     }
 
@@ -74,50 +335,403 @@ impl<'a> Editor<'a> {
         self.terminal.enter_alternate_screen()?;
         self.windows.iter().try_for_each(|w| w.render(&mut self.terminal))?;
         let Position{ x, y } = self.windows[self.current_window].to_term(0, 0);
-        self.terminal.move_cursor_to(x, y)
+        self.terminal.move_cursor_to(x, y)?;
+        // e.g. the prompt to recover a file's differential
+        self.push_pending_contexts()
     }
 
-    fn process_keypress(&mut self) -> Result<()> {
-        let event = self.config.to_current_layout_event(self.terminal.read_key()?);
+    fn process_keypress(&mut self, event: KeyEvent) -> Result<()> {
+        // before translating, so macros replay the same keys in any layout
+        if let Some((_, keys)) = &mut self.recording {
+            keys.push(event);
+        }
+        let event = self.config.to_current_layout_event(event);
+        self.process_event(event)
+    }
 
+    // forwards a key press (in the current layout) to the active context
+    fn process_event(&mut self, event: KeyEvent) -> Result<()> {
+        self.changes.key(event);
         if let Some(mut context) = self.context_stack.pop() {
             self.has_been_setup_stack.pop().unwrap();
-            let mut setup;
-            let mut msg = context.forward(self, event)?;
-            while msg.is_some() {
-                context = self.context_stack.pop().expect("Context stack is empty during message propagation");
-                setup = self.has_been_setup_stack.pop().unwrap();
-                if !setup {
-                    let returned = context.setup(self)?;
-                    if returned { continue; }
-                }
-                msg = context.receive(self, msg.unwrap())?;
+            match context.forward(self, event)? {
+                Some(msg) => self.return_message(msg)?,
+                None => {
+                    self.context_stack.push(context);
+                    self.has_been_setup_stack.push(true);
+                },
             }
-            self.context_stack.push(context);
-            self.has_been_setup_stack.push(true);
-            while !self.push_context_stack.is_empty() {
-                self.push_context_stack.iter().for_each(|_| self.has_been_setup_stack.push(false));
-                self.context_stack.append(&mut self.push_context_stack);
-                let mut context = self.context_stack.pop().unwrap();
-                self.has_been_setup_stack.pop().unwrap();
-                let returned = context.setup(self)?;
-                if !returned {
+            self.push_pending_contexts()?;
+        }
+        Ok(())
+    }
+
+    // hands the message returned by the (already popped) active context to the contexts beneath
+    // it, until one of them doesn't return
+    fn return_message(&mut self, msg: ContextMessage) -> Result<()> {
+        let mut msg = Some(msg);
+        while let Some(arg) = msg {
+            let mut context = self.context_stack.pop().expect("Context stack is empty during message propagation");
+            let setup = self.has_been_setup_stack.pop().unwrap();
+            msg = match if setup { None } else { context.setup(self)? } {
+                // returned from setup, so `arg` is never received
+                Some(returned) => Some(returned),
+                None => context.receive(self, arg)?,
+            };
+            if msg.is_none() {
+                self.context_stack.push(context);
+                self.has_been_setup_stack.push(true);
+            }
+        }
+        Ok(())
+    }
+
+    // moves the contexts pushed with `push_context` onto the context stack, setting them up, and
+    // ends the change being made once the context that began it has returned
+    fn push_pending_contexts(&mut self) -> Result<()> {
+        while !self.push_context_stack.is_empty() {
+            self.changes.push(self.context_stack.len());
+            self.push_context_stack.iter().for_each(|_| self.has_been_setup_stack.push(false));
+            self.context_stack.append(&mut self.push_context_stack);
+            let mut context = self.context_stack.pop().unwrap();
+            self.has_been_setup_stack.pop().unwrap();
+            match context.setup(self)? {
+                Some(msg) => self.return_message(msg)?,
+                None => {
                     self.context_stack.push(context);
                     self.has_been_setup_stack.push(true);
+                },
+            }
+        }
+        self.changes.returned(self.context_stack.len());
+        Ok(())
+    }
+
+    /// Look up a key press in the key binds of `context`, as the next key press of a key bind.
+    ///
+    /// Returns `Ok(true)` if the key press was taken by the key binds, and `Ok(false)` if the
+    /// context should handle it itself (because it isn't bound). A key press is taken if:
+    /// - it finishes a key bind, in which case the context the keys are bound to is pushed.
+    /// - it is the start (or the continuation) of a longer key bind, in which case it waits for
+    ///   the next key press, or for `timeout_len` milliseconds to pass.
+    /// - it is a key press that no key bind continues with. Then the longest bound start of the
+    ///   pending key presses is used, and the key presses after it are processed again. If none of
+    ///   them are bound, the first key press is processed again without looking at key binds.
+    ///
+    /// The count typed before the key bind (see [`Editor::count_digit`]) is handed to the context
+    /// that is pushed, and is dropped if the key presses aren't bound.
+    pub fn dispatch_key(&mut self, context: &str, event: KeyEvent) -> Result<bool> {
+        if !self.remap {
+            return Ok(false);
+        }
+        if self.pending_keys.is_empty() {
+            self.bind_start = self.recording.as_ref().map_or(0, |(_, keys)| keys.len().saturating_sub(1));
+        }
+        self.pending_keys.push(event);
+        match self.config.query_binds(context, &self.pending_keys) {
+            BindMatch::Complete(factory) => {
+                let created = factory.create();
+                let keys = take(&mut self.pending_keys);
+                self.push_bound_context(context, &keys, created);
+            },
+            BindMatch::Prefix | BindMatch::Ambiguous(_) => self.pending_context = context.to_string(),
+            BindMatch::Nothing if self.pending_keys.len() == 1 => {
+                self.pending_keys.clear();
+                self.count_factor = 1;
+                self.operator = None;
+                if self.count.take().is_some() | self.register.take().is_some() {
+                    self.q_draw_showcmd()?;
                 }
+                return Ok(false);
+            },
+            BindMatch::Nothing => {
+                let keys = take(&mut self.pending_keys);
+                self.resolve_keys(context, keys)?;
+            },
+        }
+        self.q_draw_showcmd()?;
+        Ok(true)
+    }
+
+    // pushes the context bound to the longest bound start of `keys`, and queues the keys after it
+    // to be processed again (the first key is queued without remapping if no start is bound)
+    fn resolve_keys(&mut self, context: &str, keys: Vec<KeyEvent>) -> Result<()> {
+        let bound = (1..=keys.len()).rev().find_map(|len| match self.config.query_binds(context, &keys[..len]) {
+            BindMatch::Ambiguous(factory) | BindMatch::Complete(factory) => Some((len, factory.create())),
+            BindMatch::Prefix | BindMatch::Nothing => None,
+        });
+        let queue: Vec<(KeyEvent, bool)> = match bound {
+            Some((len, created)) => {
+                self.push_bound_context(context, &keys[..len], created);
+                keys[len..].iter().map(|k| (*k, true)).collect()
+            },
+            None => {
+                self.count = None;
+                self.count_factor = 1;
+                self.operator = None;
+                once((keys[0], false)).chain(keys[1..].iter().map(|k| (*k, true))).collect()
+            },
+        };
+        self.queue_keys_front(queue);
+        self.q_draw_showcmd()
+    }
+
+    // queues keys to be processed before any keys that were already queued
+    fn queue_keys_front(&mut self, mut keys: Vec<(KeyEvent, bool)>) {
+        while let Some((key, remap)) = keys.pop() {
+            self.queued_keys.push_front(Queued::Key(key, remap));
+        }
+    }
+
+    // pushes a context created by the key bind `keys` in `context_name`, handing it the count and
+    // register typed before the key bind (and beginning a change, if it makes one)
+    fn push_bound_context(&mut self, context_name: &str, keys: &[KeyEvent], mut context: Box<dyn Context>) {
+        let factor = replace(&mut self.count_factor, 1);
+        let count = match self.count.take() {
+            Some(count) => Some(count.saturating_mul(factor)),
+            None if factor > 1 => Some(factor),
+            None => None,
+        };
+        if let Some(count) = count {
+            context.count(count);
+        }
+        let register = self.register.take();
+        if let Some(name) = register {
+            context.register(name);
+        }
+        if let Some(operator) = self.operator.take() {
+            context.operator(operator);
+        }
+        // only changes made from NormalMode can be repeated, since that's where they're replayed
+        if context.is_change() && context_name == "NormalMode" {
+            self.changes.begin(count, register, keys);
+        } else if let Some(count) = count {
+            self.changes.count(count);
+        }
+        self.push_boxed_context(context);
+    }
+
+    /// Multiply the count of the next key bind by `factor`.
+    ///
+    /// This is for contexts that were given a count, and that pass it on to the next key bind
+    /// (e.g. `OperatorPending`, where `2d3w` deletes 6 words). The factor is dropped along with the
+    /// count if the next key presses aren't bound.
+    pub fn multiply_count(&mut self, factor: usize) {
+        self.count_factor = self.count_factor.saturating_mul(factor);
+    }
+
+    /// Hand `operator` to the next key bind (see [`Context::operator`]).
+    ///
+    /// This is for `OperatorPending`, so that motions can act differently for some operators (e.g.
+    /// `cw` changes to the end of the word, like `ce`). Like the count factor, the operator is
+    /// dropped if the next key presses aren't bound.
+    pub fn pending_operator(&mut self, operator: Operator) {
+        self.operator = Some(operator);
+    }
+
+    /// Add a key press to the count typed before a key bind, if it is a digit.
+    ///
+    /// Returns `Ok(true)` if the key press was a digit that was added to the count, and
+    /// `Ok(false)` otherwise. The digit is the character typed in the current layout (not the
+    /// QWERTY key), so counts can be typed in layouts that have digits in other places. A `0` only
+    /// continues a count, so that it can be bound by itself, and digits can't be typed in the
+    /// middle of a key sequence.
+    pub fn count_digit(&mut self, event: KeyEvent) -> Result<bool> {
+        let count = add_count_digit(self.count, event, !self.pending_keys.is_empty());
+        if count.is_some() {
+            self.count = count;
+            self.changes.count_digit();
+            self.q_draw_showcmd()?;
+        }
+        Ok(count.is_some())
+    }
+
+    /// Select the register for the next key bind, if the key press is a `"` or the register name
+    /// after it (e.g. the `"a` in `"ayy`).
+    ///
+    /// Returns `Ok(true)` if the key press was part of selecting a register, and `Ok(false)`
+    /// otherwise. Like digits in a count, the register name is the character typed in the current
+    /// layout, and a register can't be selected in the middle of a key sequence. A name that isn't
+    /// a register drops the count and register typed so far (see [`Registers::is_register`]).
+    pub fn register_prefix(&mut self, event: KeyEvent) -> Result<bool> {
+        let c = match event.code {
+            KeyCode::Char(c) if (event.modifiers - KeyModifiers::SHIFT).is_empty() && self.pending_keys.is_empty() => c,
+            _ => return Ok(false),
+        };
+        if take(&mut self.register_pending) {
+            if Registers::is_register(c) {
+                self.register = Some(c);
+            } else {
+                self.register = None;
+                self.count = None;
+                self.count_factor = 1;
+                self.operator = None;
+            }
+        } else if c == '"' {
+            self.register_pending = true;
+        } else {
+            return Ok(false);
+        }
+        self.q_draw_showcmd()?;
+        Ok(true)
+    }
+
+    /// Start recording key presses into the register named `name`, until
+    /// [`Editor::stop_recording`] is called.
+    ///
+    /// The key presses are recorded as they are read from the terminal, before they are translated
+    /// into the current layout, so a macro replays the same keys after the layout is changed. Key
+    /// presses replayed from macros aren't recorded. `recording @q` is shown on the command line
+    /// while recording into the `"q` register.
+    pub fn start_recording(&mut self, name: char) -> Result<()> {
+        self.recording = Some((name, Vec::new()));
+        self.q_draw_cmd_line([], CmdLineFlags::all())
+    }
+
+    /// Stop recording key presses, and put those that were recorded into the register they were
+    /// recorded into (see [`Registers::record`]).
+    ///
+    /// The key presses of the key bind that stopped the recording (e.g. the last `q` of `qaq`) are
+    /// left out.
+    pub fn stop_recording(&mut self) -> Result<()> {
+        if let Some((name, mut keys)) = self.recording.take() {
+            keys.truncate(self.bind_start);
+            if let Err(e) = self.registers.record(name, keys) {
+                return self.q_draw_cmd_line([&e.to_string()], CmdLineFlags::all());
             }
         }
+        self.q_draw_cmd_line([], CmdLineFlags::all())
+    }
+
+    /// Returns the name of the register a macro is being recorded into, if one is.
+    pub fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|(name, _)| *name)
+    }
+
+    /// Replay the key presses in the register named `name` (see [`Register::keys`]) `count` times,
+    /// as if they were typed.
+    ///
+    /// `@` replays the register that was replayed last, and `:` runs the last command line again.
+    /// Key presses that were recorded are translated into the current layout, while the text in
+    /// other registers is typed as it is.
+    ///
+    /// Like in vim, replaying stops when a key bind in the macro fails (see
+    /// [`Editor::abort_macros`]), when Esc or Ctrl-C is typed, or when macros are executed too
+    /// deeply inside each other (i.e. a macro executes itself, and never fails).
+    pub fn execute_register(&mut self, name: char, count: usize) -> Result<()> {
+        let name = match (name, self.last_macro) {
+            ('@', Some(last)) => last,
+            ('@', None) => {
+                self.abort_macros();
+                return self.q_draw_cmd_line([&RegisterError::Empty('@').to_string()], CmdLineFlags::all());
+            },
+            _ => name,
+        };
+        let register = match self.read_register(name) {
+            Ok(register) => register,
+            Err(e) => {
+                self.abort_macros();
+                return self.q_draw_cmd_line([&e.to_string()], CmdLineFlags::all());
+            },
+        };
+        self.last_macro = Some(name);
+        let keys: Vec<KeyEvent> = if register.is_recorded() {
+            register.keys().into_iter().map(|k| self.config.to_current_layout_event(k)).collect()
+        } else if name == ':' {
+            let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE);
+            once(KeyEvent::new(KeyCode::Char(':'), KeyModifiers::NONE)).chain(register.keys()).chain(once(enter)).collect()
+        } else {
+            register.keys()
+        };
+        // before any keys that were already queued, in case this is in a macro too
+        if !keys.is_empty() && count > 0 {
+            self.queued_keys.push_front(Queued::Macro(keys, count));
+        }
         Ok(())
     }
 
+    /// Stop replaying the macros being replayed (see [`Editor::execute_register`]), if any.
+    ///
+    /// This is for key binds that fail (e.g. a motion that can't be made), which stop a macro like
+    /// they do in vim. For example, a macro that ends by executing itself after moving down a line
+    /// stops at the last line.
+    pub fn abort_macros(&mut self) {
+        if self.macro_depth > 0 {
+            self.queued_keys.clear();
+            self.macro_depth = 0;
+        }
+    }
+
+    /// Repeat the last change made from `NormalMode` (e.g. `dw`, or `i` and the text typed until
+    /// `<Esc>`), by replaying its key presses.
+    ///
+    /// A change begins with a key bind that pushes a [`Context`] that makes changes (see
+    /// [`Context::is_change`]), and ends when that `Context` returns. The change is made again with
+    /// the same register, and with `count` instead of its count if one is given (like in vim,
+    /// `3.` after `2d3w` deletes 3 words).
+    pub fn repeat_change(&mut self, count: Option<usize>) -> Result<()> {
+        let change = match &self.changes.last_change {
+            Some(change) => change,
+            None => return Ok(()),
+        };
+        let char_key = |c| (KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE), true);
+        let register = change.register.into_iter().flat_map(|name| ['"', name]).map(char_key);
+        let count = count.or(change.count).map_or_else(String::new, |c| c.to_string());
+        let keys = register.chain(count.chars().map(char_key)).chain(change.keys.iter().map(|k| (*k, true))).collect();
+        self.queue_keys_front(keys);
+        Ok(())
+    }
+
+    // draws the count and pending keys at the right end of the command line
+    fn q_draw_showcmd(&mut self) -> Result<()> {
+        const SHOWCMD_WIDTH: usize = 10;
+        let size = self.terminal.size();
+        if !bool::from(self.config.opt.show_cmd) || usize::from(size.width) <= SHOWCMD_WIDTH + 1 {
+            return Ok(());
+        }
+        let register = match (self.register, self.register_pending) {
+            (_, true) => String::from("\""),
+            (Some(name), false) => format!("\"{}", name),
+            (None, false) => String::new(),
+        };
+        let count = self.count.map_or_else(String::new, |c| c.to_string());
+        let keys = register + &count + &KeyBinds::key_sequence_string(&self.pending_keys);
+        let skip = keys.chars().count().saturating_sub(SHOWCMD_WIDTH);
+        let shown: String = keys.chars().skip(skip).collect();
+        self.terminal.save_cursor();
+        self.terminal.cursor_to(size.width - SHOWCMD_WIDTH as u16 - 1, size.height - 1).q_move_cursor()?
+            .q(Print(format!("{:<width$}", shown, width = SHOWCMD_WIDTH)))?;
+        self.terminal.restore_cursor();
+        self.terminal.q_move_cursor()?;
+        self.terminal.flush()
+    }
+
     /// Actual implementor of [`Action`].
     ///
     /// Necessary due to borrow checker's interaction with disjoint struct fields accessed through
-    /// methods.
-    pub fn action(&mut self, action: &str) -> Result<()> {
+    /// methods. Returns `Ok(false)` if the action didn't move the cursor or change the document
+    /// (e.g. moving left at the beginning of a line), so that repeating it would do nothing.
+    pub fn action(&mut self, action: &str) -> Result<bool> {
         let current_window = &mut self.windows[self.current_window];
+        let before = current_window.progress();
         let term = &mut self.terminal;
         match action {
+            "undo" => {
+                let undone = current_window.undo(term)?;
+                if !undone { self.q_draw_cmd_line(["Already at oldest change"], CmdLineFlags::all())?; }
+            },
+            "redo" => {
+                let redone = current_window.redo(term)?;
+                if !redone { self.q_draw_cmd_line(["Already at newest change"], CmdLineFlags::all())?; }
+            },
+            "earlier" => {
+                let traveled = current_window.travel(Travel::Steps(1), true, term)?;
+                if !traveled { self.q_draw_cmd_line(["Already at oldest change"], CmdLineFlags::all())?; }
+            },
+            "later" => {
+                let traveled = current_window.travel(Travel::Steps(1), false, term)?;
+                if !traveled { self.q_draw_cmd_line(["Already at newest change"], CmdLineFlags::all())?; }
+            },
             "move_left" => current_window.move_left(term)?,
             "move_right" => current_window.move_right(term)?,
             "move_up" => current_window.move_up(term)?,
@@ -126,10 +740,14 @@ impl<'a> Editor<'a> {
             "end" => current_window.end(term)?,
             "delete" => current_window.delete(term)?,
             "backspace" => current_window.backspace(term)?,
+            "repeat" => {
+                self.repeat_change(None)?;
+                return Ok(true);
+            },
             _ => (),
         }
 
-        Ok(())
+        Ok(self.windows[self.current_window].progress() != before)
     }
 
     /// Set the quit flag.
@@ -153,11 +771,17 @@ impl<'a> Editor<'a> {
     /// Flushes the `Command` only if `flush` is `true`. If `save_cursor` is `true`, saves the
     /// cursor before the draw. If `reset_cursor` is `true`, after the draw
     /// the cursor will be placed where it was previously. Otherwise, it will remain on the command
-    /// line after the drawn text.
+    /// line after the drawn text. While a macro is being recorded, an empty command line shows
+    /// `recording @q` (see [`Editor::start_recording`]).
     pub fn q_draw_cmd_line<const N: usize>(&mut self, text: [&str; N], flags: CmdLineFlags) -> Result<()> {
         if flags.contains(CmdLineFlags::SAVECURSOR) { self.terminal.save_cursor(); }
         let height = self.terminal.size().height;
         self.terminal.cursor_to(0, height - 1).q_move_cursor()?.q(Clear(ClearType::CurrentLine))?;
+        if text.iter().all(|t| t.is_empty()) {
+            if let Some(name) = self.recording() {
+                self.terminal.q(Print(format!("recording @{}", name)))?;
+            }
+        }
         for text_bit in text {
             self.terminal.q(Print(text_bit))?;
         }
@@ -168,6 +792,13 @@ impl<'a> Editor<'a> {
         if flags.contains(CmdLineFlags::FLUSH) { self.terminal.flush() } else { Ok(()) }
     }
 
+    /// Draw the name of a mode (e.g. `-- INSERT --`) on the command line, followed by
+    /// `recording @q` while a macro is being recorded.
+    pub fn q_draw_mode(&mut self, mode: &str) -> Result<()> {
+        let recording = self.recording().map_or_else(String::new, |name| format!("recording @{}", name));
+        self.q_draw_cmd_line([mode, &recording], CmdLineFlags::all())
+    }
+
     /// Return a reference to the terminal.
     pub fn terminal(&mut self) -> &mut Terminal {
         &mut self.terminal
@@ -183,16 +814,90 @@ impl<'a> Editor<'a> {
         &self.config.opt
     }
 
+    /// Return a mutable reference to the `Config` object.
+    pub fn config_mut(&mut self) -> &mut Config {
+        &mut self.config
+    }
+
+    /// Replace the `Options` object, updating every window (and rendering them again) to use the
+    /// new options.
+    pub fn set_options(&mut self, opt: Options) -> Result<()> {
+        self.config.opt = opt;
+        // the current window goes last, so that it ends up with the cursor
+        let current = self.current_window;
+        let order = (0..self.windows.len()).filter(|i| *i != current).chain(once(current));
+        for i in order {
+            self.windows[i].update_options(&self.config.opt, &mut self.terminal)?;
+        }
+        Ok(())
+    }
+
     /// Return a reference to the `Config` object.
     pub fn config(&self) -> &Config {
         &self.config
     }
 
-    /// Push a command to the command history stack.
+    /// Add an ex command, replacing any command with the same name.
+    ///
+    /// See [`ExCommands::add`](crate::command::ExCommands::add).
+    pub fn register_command(&mut self, command: ExCommand) {
+        self.config.commands.add(command);
+    }
+
+    /// Push a command to the command history stack (it's also the `":` register now).
     pub fn push_command(&mut self, cmd: String) {
+        self.registers.set_last_command(&cmd);
         self.command_stack.push(cmd);
     }
 
+    /// Return a reference to the registers.
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    /// Return a mutable reference to the registers.
+    pub fn registers_mut(&mut self) -> &mut Registers {
+        &mut self.registers
+    }
+
+    /// Put yanked (or, if `deleted` is `true`, deleted) text into the register named `name` (see
+    /// [`Registers::yank`] and [`Registers::delete`]).
+    ///
+    /// Text put into the `"+` or `"*` register is also copied to the system clipboard, with the
+    /// `clipboard_command` option if it is set, and with OSC 52 otherwise.
+    pub fn store_register(&mut self, name: Option<char>, text: Register, deleted: bool) -> std::result::Result<(), RegisterError> {
+        let kind = name.and_then(ClipboardKind::of_register);
+        let copied = kind.map(|kind| (kind, text.text()));
+        if deleted {
+            self.registers.delete(name, text)?;
+        } else {
+            self.registers.yank(name, text)?;
+        }
+        if let Some((kind, text)) = copied {
+            let copied = match self.config.opt.clipboard_command.command() {
+                Some(command) => clipboard::copy_with_command(command, kind, &text),
+                None => self.terminal.q(Osc52{ kind, text: &text }).and_then(Terminal::flush),
+            };
+            copied.map_err(|e| RegisterError::Clipboard(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the text in the register named `name`.
+    ///
+    /// The `"+` and `"*` registers are read from the system clipboard if the
+    /// `clipboard_paste_command` option is set.
+    pub fn read_register(&self, name: char) -> std::result::Result<Register, RegisterError> {
+        match (ClipboardKind::of_register(name), self.config.opt.clipboard_paste_command.command()) {
+            (Some(kind), Some(command)) => match clipboard::paste_with_command(command, kind) {
+                Ok(text) if text.is_empty() => Err(RegisterError::Empty(name)),
+                Ok(text) => Ok(Register::from_text(&text)),
+                Err(e) => Err(RegisterError::Clipboard(e.to_string())),
+            },
+            _ => self.registers.get(name),
+        }
+    }
+
     /// Return a reference to the command history stack.
     pub fn command_stack(&self) -> &Vec<String> {
         &self.command_stack
@@ -225,3 +930,97 @@ bitflags! {
         const RESTORECURSOR = 0b100;
     }
 }
+
+// the count after the key press `event`, if it is a digit that adds to `count` (see
+// `Editor::count_digit`); `in_sequence` is whether part of a key sequence has been typed
+fn add_count_digit(count: Option<usize>, event: KeyEvent, in_sequence: bool) -> Option<usize> {
+    let digit = match event.code {
+        KeyCode::Char(c) if (event.modifiers - KeyModifiers::SHIFT).is_empty() => c.to_digit(10),
+        _ => None,
+    };
+    match digit {
+        Some(0) if count.is_none() => None,
+        Some(d) if !in_sequence => {
+            let count = count.unwrap_or(0);
+            Some(count.checked_mul(10).and_then(|c| c.checked_add(d as usize)).unwrap_or(count))
+        },
+        _ => None,
+    }
+}
+
+#[test]
+fn test_add_count_digit() {
+    let key = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+    let count = "123".chars().try_fold(None, |count, c| add_count_digit(count, key(c), false).map(Some));
+    assert_eq!(count, Some(Some(123)));
+    // `0` only continues a count
+    assert_eq!(add_count_digit(None, key('0'), false), None);
+    assert_eq!(add_count_digit(Some(1), key('0'), false), Some(10));
+    // digits are the characters typed, whatever keys they are on (e.g. shifted in AZERTY)
+    assert_eq!(add_count_digit(None, KeyEvent::new(KeyCode::Char('7'), KeyModifiers::SHIFT), false), Some(7));
+    assert_eq!(add_count_digit(None, KeyEvent::new(KeyCode::Char('7'), KeyModifiers::CONTROL), false), None);
+    assert_eq!(add_count_digit(Some(2), key('j'), false), None);
+    assert_eq!(add_count_digit(None, key('5'), true), None);
+    // a count too large to keep ignores the extra digits
+    assert_eq!(add_count_digit(Some(usize::MAX), key('9'), false), Some(usize::MAX));
+}
+
+#[test]
+fn test_changes() {
+    let key = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+    let keys = |s: &str| s.chars().map(key).collect::<Vec<_>>();
+    // `d3w`: NormalMode binds `d`, OperatorPending takes the count and binds `w`
+    let mut changes = Changes::default();
+    changes.key(key('d'));
+    changes.begin(None, None, &keys("d"));
+    changes.push(1);
+    changes.returned(2);
+    changes.key(key('3'));
+    changes.count_digit();
+    changes.key(key('w'));
+    changes.count(3);
+    changes.push(2);
+    assert!(changes.last_change.is_none());
+    changes.returned(1);
+    assert_eq!(changes.last_change, Some(Change{ count: Some(3), register: None, keys: keys("dw") }));
+    assert!(changes.change.is_none() && changes.change_depth.is_none());
+    // `"a2d3w` keeps the register and the count of the whole change
+    changes.begin(Some(2), Some('a'), &keys("d"));
+    changes.push(1);
+    changes.key(key('3'));
+    changes.count_digit();
+    changes.key(key('w'));
+    changes.count(6);
+    changes.returned(1);
+    assert_eq!(changes.last_change, Some(Change{ count: Some(6), register: Some('a'), keys: keys("dw") }));
+    // `ihi<Esc>` ends when InsertMode returns, and its keys aren't counts
+    changes.begin(None, None, &keys("i"));
+    changes.push(1);
+    changes.returned(2);
+    for c in "hi".chars() {
+        changes.key(key(c));
+        changes.returned(2);
+    }
+    assert_eq!(changes.last_change.as_ref().unwrap().keys, keys("dw"));
+    changes.key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+    changes.returned(1);
+    let mut expected = keys("ihi");
+    expected.push(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+    assert_eq!(changes.last_change, Some(Change{ count: None, register: None, keys: expected }));
+    // `cchi<Esc>` keeps the typed text in order, for `.` to type it again
+    changes.begin(None, None, &keys("c"));
+    changes.push(1);
+    changes.returned(2);
+    for c in "chi".chars() {
+        changes.key(key(c));
+        changes.returned(2);
+    }
+    changes.key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+    changes.returned(1);
+    assert_eq!(changes.last_change.as_ref().unwrap().keys[..4], keys("cchi")[..]);
+    // key presses and counts outside of a change aren't recorded
+    changes.key(key('j'));
+    changes.count(2);
+    changes.returned(1);
+    assert_eq!(changes.last_change.as_ref().unwrap().count, None);
+}
@@ -1,3 +1,4 @@
+//! A module for grapheme-based string manipulation.
 use crate::config::options::{Options, TabType};
 use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthStr;
@@ -36,11 +37,13 @@ impl GraphemeString {
     ///
     /// # Examples
     /// ```
-    /// #use crate::config::options::Options;
-    /// let gs = GraphemeString::new("\t\tlet x = 1;\n");
+    /// # use libfim::config::options::Options;
+    /// # use libfim::grapheme_string::GraphemeString;
+    /// let mut gs = GraphemeString::new("\t\tlet x = 1;\n");
     /// assert_eq!(gs.cached(None), "let x = 1;\n");
     /// // does not recompute tabs as according to Options
-    /// assert_eq!(gs.cached(Some(Options::default())), "let x = 1;\n");
+    /// assert_eq!(gs.cached(Some(&Options::default())), "let x = 1;\n");
+    /// ```
     pub fn cached(&mut self, opt: Option<&Options>) -> &str {
         if self.dirty {
             if let Some(opt) = opt {
@@ -28,6 +28,7 @@ use std::fs::read_to_string;
 use std::path::PathBuf;
 
 /// An interface for keyboard layouts.
+#[allow(clippy::wrong_self_convention)]
 pub trait Layout {
     /// Translate a QWERTY key press into a key press from this layout, by keyboard position.
     /// For example, a QWERTY 's' translates into a Dvorak 'o' because they are located at the same
@@ -88,10 +89,10 @@ pub trait Layout {
 pub fn shift_qwerty(qwerty_press: u8) -> u8 {
     // Uppercase Letters => Themselves
     // I'm not sure if this will be needed, but just in case.
-    if qwerty_press >= 65 && qwerty_press <= 90 {
+    if (65..=90).contains(&qwerty_press) {
         return qwerty_press;
     // Lowercase Letters => Uppercase letters (- 32)
-    } else if qwerty_press >= 97 && qwerty_press <= 122 {
+    } else if (97..=122).contains(&qwerty_press) {
         return qwerty_press - 32;
     }
     // Numbers, backtick, minus, equals, open/close square brackets, semicolon,
@@ -144,7 +145,7 @@ pub fn shift_qwerty(qwerty_press: u8) -> u8 {
 /// ```
 pub fn deshift_qwerty(qwerty_shift_press: u8) -> u8 {
     // Uppercase Letters => Lowercase letters (+ 32)
-    if qwerty_shift_press >= 65 && qwerty_shift_press <= 90 {
+    if (65..=90).contains(&qwerty_shift_press) {
         return qwerty_shift_press + 32;
     }
     // Deshifted:
@@ -237,7 +238,7 @@ impl Layout for Dvorak {
             other => {
                 // shift + qwerty letter => shift + dvorak equivalent
                 // _, +, {, }, :, ", <, >, ? => shift + dvorak equivalent
-                if (other >= 65 && other <= 90) || "_+{}:\"<>?".contains(other as char) {
+                if (65..=90).contains(&other) || "_+{}:\"<>?".contains(other as char) {
                     shift_qwerty(self.from_qwerty(deshift_qwerty(other)))
                 } else {
                     other
@@ -286,7 +287,7 @@ impl Layout for Dvorak {
             other => {
                 // shift + dvorak letter => shift + qwerty equivalent
                 // _, +, {, }, :, ", <, >, ? => shift + qwerty equivalent
-                if (other >= 65 && other <= 90) || "_+{}:\"<>?".contains(other as char) {
+                if (65..=90).contains(&other) || "_+{}:\"<>?".contains(other as char) {
                     shift_qwerty(self.to_qwerty(deshift_qwerty(other)))
                 } else {
                     other
@@ -325,7 +326,7 @@ impl Layout for Colemak {
             other => {
                 // shift + qwerty letter => shift + colemak equivalent
                 // : => O
-                if (other >= 65 && other <= 90) || other == 58 {
+                if (65..=90).contains(&other) || other == 58 {
                     shift_qwerty(self.from_qwerty(deshift_qwerty(other)))
                 } else {
                     other
@@ -356,7 +357,7 @@ impl Layout for Colemak {
             other => {
                 // shift + colemak letter => shift + qwerty equivalent
                 // O => :
-                if (other >= 65 && other <= 90) || other == 58 {
+                if (65..=90).contains(&other) || other == 58 {
                     shift_qwerty(self.to_qwerty(deshift_qwerty(other)))
                 } else {
                     other
@@ -4,11 +4,16 @@
 #[macro_use]
 extern crate lazy_static;
 
+pub mod clipboard;
+pub mod command;
 pub mod config;
 pub mod context;
 pub mod delta;
 pub mod editor;
 pub mod grapheme_string;
 pub mod layout;
+pub mod motion;
+pub mod register;
 pub mod terminal;
+pub mod undo;
 pub mod window;
@@ -0,0 +1,1055 @@
+//! A module for motions, text objects and the operators that act on them.
+//!
+//! A motion (e.g. `w`, to the start of the next word) moves from the cursor to somewhere else in
+//! the document. A text object (e.g. `iw`, the word under the cursor) is a piece of text around the
+//! cursor. Both are described by a [`TextRange`]: the motion contexts in [`crate::context`] return
+//! one, which either moves the cursor (in `NormalMode`) or is handed to an [`Operator`] (in
+//! `OperatorPending`).
+//!
+//! Like vim, a word is a run of letters, digits and underscores, or a run of other non-blank
+//! characters, and an empty line is a word of its own. Runs of letters and digits are also split
+//! at Unicode word boundaries, so that words in scripts without spaces between them (e.g. Chinese)
+//! can be moved over one at a time. A WORD is a run of non-blank characters, and a paragraph is a
+//! run of non-empty lines.
+//!
+//! Ranges are made of grapheme offsets in the [`Document`], where the newline at the end of a line
+//! is a grapheme too.
+use crate::window::DocPosition;
+use document::Document;
+use std::collections::HashSet;
+use std::iter::once;
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Struct that represents a range of text in a document, as described by a motion or text
+/// object.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextRange {
+    /// The grapheme offsets of the text (the end is exclusive).
+    pub range: Range<usize>,
+    /// Whether an operator acts on all of the lines the range is in, rather than just the
+    /// graphemes in it (e.g. `dj` or `dip`).
+    pub linewise: bool,
+    /// The grapheme offset the cursor goes to when the range comes from a motion.
+    pub cursor: usize,
+}
+
+/// Enum that represents the motions that a [`Motion`](crate::context::Motion) context can make.
+///
+/// A WORD (as opposed to a word) is a run of non-blank characters.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MotionKind {
+    /// To the start of the next word (vim's `w`); exclusive.
+    WordForward,
+    /// To the start of the next WORD (vim's `W`); exclusive.
+    BigWordForward,
+    /// To the end of the word (vim's `e`); inclusive.
+    WordEnd,
+    /// To the end of the WORD (vim's `E`); inclusive.
+    BigWordEnd,
+    /// To the start of the word (vim's `b`); exclusive.
+    WordBackward,
+    /// To the start of the WORD (vim's `B`); exclusive.
+    BigWordBackward,
+    /// To the end of the previous word (vim's `ge`); inclusive.
+    WordEndBackward,
+    /// To the end of the previous WORD (vim's `gE`); inclusive.
+    BigWordEndBackward,
+    /// To the start of the next sentence (vim's `)`); exclusive.
+    SentenceForward,
+    /// To the start of the sentence (vim's `(`); exclusive.
+    SentenceBackward,
+    /// To the empty line after the paragraph (vim's `}`); exclusive.
+    ParagraphForward,
+    /// To the empty line before the paragraph (vim's `{`); exclusive.
+    ParagraphBackward,
+    /// To the start of the line (vim's `0`); exclusive.
+    LineStart,
+    /// To the end of the line (vim's `$`); inclusive.
+    LineEnd,
+    /// To the first line, or the line given by the count (vim's `gg`); linewise.
+    FirstLine,
+    /// To the last line, or the line given by the count (vim's `G`); linewise.
+    LastLine,
+    /// To the first line on the screen (vim's `H`); linewise.
+    ScreenTop,
+    /// To the middle line on the screen (vim's `M`); linewise.
+    ScreenMiddle,
+    /// To the last line on the screen (vim's `L`); linewise.
+    ScreenBottom,
+    /// To the bracket that matches the next bracket in the line, or to a percentage of the
+    /// document with a count (vim's `%`); inclusive.
+    MatchPair,
+    /// To the next occurrence of a character in the line (vim's `f`); inclusive.
+    FindForward,
+    /// To the previous occurrence of a character in the line (vim's `F`); exclusive.
+    FindBackward,
+    /// To just before the next occurrence of a character in the line (vim's `t`); inclusive.
+    TillForward,
+    /// To just after the previous occurrence of a character in the line (vim's `T`); exclusive.
+    TillBackward,
+    /// Repeats the last `f`, `F`, `t` or `T` (vim's `;`).
+    RepeatFind,
+    /// Repeats the last `f`, `F`, `t` or `T` in the other direction (vim's `,`).
+    RepeatFindReverse,
+    /// Down a line (vim's `j`); linewise.
+    Down,
+    /// Up a line (vim's `k`); linewise.
+    Up,
+    /// The current line, and the lines after it with a count (for doubled operators like `dd`);
+    /// linewise.
+    Line,
+}
+
+impl MotionKind {
+    /// Returns the motion with the given name (e.g. `word_forward`), as it is written in a `bind`
+    /// line.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "word_forward" => Self::WordForward,
+            "big_word_forward" => Self::BigWordForward,
+            "word_end" => Self::WordEnd,
+            "big_word_end" => Self::BigWordEnd,
+            "word_backward" => Self::WordBackward,
+            "big_word_backward" => Self::BigWordBackward,
+            "word_end_backward" => Self::WordEndBackward,
+            "big_word_end_backward" => Self::BigWordEndBackward,
+            "sentence_forward" => Self::SentenceForward,
+            "sentence_backward" => Self::SentenceBackward,
+            "paragraph_forward" => Self::ParagraphForward,
+            "paragraph_backward" => Self::ParagraphBackward,
+            "line_start" => Self::LineStart,
+            "line_end" => Self::LineEnd,
+            "first_line" => Self::FirstLine,
+            "last_line" => Self::LastLine,
+            "screen_top" => Self::ScreenTop,
+            "screen_middle" => Self::ScreenMiddle,
+            "screen_bottom" => Self::ScreenBottom,
+            "match_pair" => Self::MatchPair,
+            "find_forward" => Self::FindForward,
+            "find_backward" => Self::FindBackward,
+            "till_forward" => Self::TillForward,
+            "till_backward" => Self::TillBackward,
+            "repeat_find" => Self::RepeatFind,
+            "repeat_find_reverse" => Self::RepeatFindReverse,
+            "down" => Self::Down,
+            "up" => Self::Up,
+            "line" => Self::Line,
+            _ => return None,
+        })
+    }
+
+    /// Returns `true` if the motion needs a character typed after it (e.g. `f{char}`).
+    pub fn needs_char(&self) -> bool {
+        matches!(self, Self::FindForward | Self::FindBackward | Self::TillForward | Self::TillBackward)
+    }
+
+    /// Returns the find motion that goes the other way (e.g. `F` for `f`), for repeating a find
+    /// with `,`. Other motions are returned as they are.
+    pub fn reversed(&self) -> Self {
+        match self {
+            Self::FindForward => Self::FindBackward,
+            Self::FindBackward => Self::FindForward,
+            Self::TillForward => Self::TillBackward,
+            Self::TillBackward => Self::TillForward,
+            other => *other,
+        }
+    }
+}
+
+/// Enum that represents the text objects that a [`TextObject`](crate::context::TextObject)
+/// context can select.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ObjectKind {
+    /// The word under the cursor (vim's `iw`).
+    InnerWord,
+    /// The word under the cursor and the blanks after it, or before it if there are none after it
+    /// (vim's `aw`).
+    AWord,
+    /// The text between a pair of quotes in the line (vim's `i"`).
+    InnerQuote(char),
+    /// The text between a pair of quotes in the line, with the quotes and the blanks after them
+    /// (vim's `a"`).
+    AQuote(char),
+    /// The text inside the brackets around the cursor (vim's `i(`).
+    InnerBlock(char, char),
+    /// The text inside the brackets around the cursor, with the brackets (vim's `a(`).
+    ABlock(char, char),
+    /// The paragraph (or run of empty lines) the cursor is in (vim's `ip`); linewise.
+    InnerParagraph,
+    /// The paragraph the cursor is in and the empty lines after it (vim's `ap`); linewise.
+    AParagraph,
+}
+
+impl ObjectKind {
+    /// Returns the text object with the given name (e.g. `inner_word` or `a_quote "`), as it is
+    /// written in a `bind` line.
+    ///
+    /// Quote objects take the quote character, and block objects take the opening bracket: one of
+    /// `(`, `[`, `{` or `<`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let mut words = name.split_whitespace();
+        let kind = words.next()?;
+        let arg = words.next();
+        if words.next().is_some() {
+            return None;
+        }
+        let char_arg = || {
+            let mut chars = arg?.chars();
+            let c = chars.next()?;
+            if chars.next().is_some() { None } else { Some(c) }
+        };
+        let block = || Some(match char_arg()? {
+            '(' | ')' => ('(', ')'),
+            '[' | ']' => ('[', ']'),
+            '{' | '}' => ('{', '}'),
+            '<' | '>' => ('<', '>'),
+            _ => return None,
+        });
+        Some(match (kind, arg) {
+            ("inner_word", None) => Self::InnerWord,
+            ("a_word", None) => Self::AWord,
+            ("inner_quote", Some(_)) => Self::InnerQuote(char_arg()?),
+            ("a_quote", Some(_)) => Self::AQuote(char_arg()?),
+            ("inner_block", Some(_)) => {
+                let (open, close) = block()?;
+                Self::InnerBlock(open, close)
+            },
+            ("a_block", Some(_)) => {
+                let (open, close) = block()?;
+                Self::ABlock(open, close)
+            },
+            ("inner_paragraph", None) => Self::InnerParagraph,
+            ("a_paragraph", None) => Self::AParagraph,
+            _ => return None,
+        })
+    }
+}
+
+/// Enum that represents what an operator does to a [`TextRange`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operator {
+    /// Delete the text (vim's `d`).
+    Delete,
+    /// Delete the text and start `InsertMode` (vim's `c`).
+    Change,
+    /// Copy the text (vim's `y`).
+    Yank,
+    /// Indent the lines (vim's `>`).
+    Indent,
+    /// Remove one level of indentation from the lines (vim's `<`).
+    Outdent,
+    /// Make the text lowercase (vim's `gu`).
+    Lowercase,
+    /// Make the text uppercase (vim's `gU`).
+    Uppercase,
+}
+
+impl Operator {
+    /// Returns the operator with the given name (e.g. `delete`), as it is written in a `bind`
+    /// line.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "delete" => Self::Delete,
+            "change" => Self::Change,
+            "yank" => Self::Yank,
+            "indent" => Self::Indent,
+            "outdent" => Self::Outdent,
+            "lowercase" => Self::Lowercase,
+            "uppercase" => Self::Uppercase,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Class {
+    Blank,
+    Newline,
+    Word,
+    Punctuation,
+}
+
+// a grapheme-by-grapheme view of a document, reading a line at a time
+struct Text<'a> {
+    doc: &'a Document,
+    start: usize, // offset of the line in `graphemes`
+    graphemes: Vec<String>, // the graphemes of the line, and its newline (if it has one)
+    bounds: Vec<bool>, // whether there is a Unicode word boundary before each grapheme
+}
+
+impl<'a> Text<'a> {
+    fn new(doc: &'a Document) -> Self {
+        Text{ doc, start: 0, graphemes: Vec::new(), bounds: Vec::new() }
+    }
+
+    fn len(&self) -> usize {
+        self.doc.graphemes()
+    }
+
+    fn position(&self, offset: usize) -> DocPosition {
+        let y = self.doc.line_of(offset).expect("offset should be in the document");
+        DocPosition{ x: offset - self.line_start(y), y }
+    }
+
+    fn offset(&self, pos: DocPosition) -> usize {
+        self.line_start(pos.y) + pos.x
+    }
+
+    fn line_start(&self, line: usize) -> usize {
+        self.doc.line_start(line).expect("line should be in the document")
+    }
+
+    fn line_end(&self, line: usize) -> usize {
+        self.line_start(line) + self.doc.line_graphemes(line).expect("line should be in the document")
+    }
+
+    fn last_line(&self) -> usize {
+        self.doc.num_lines() - 1
+    }
+
+    fn is_empty_line(&self, line: usize) -> bool {
+        self.doc.line_graphemes(line) == Some(0)
+    }
+
+    // reads the line `offset` is in, if it hasn't been read, returning the index of `offset` in it
+    fn load(&mut self, offset: usize) -> Option<usize> {
+        if offset >= self.len() {
+            return None;
+        }
+        if offset < self.start || offset >= self.start + self.graphemes.len() {
+            let line = self.doc.line_of(offset)?;
+            self.start = self.line_start(line);
+            let text = self.doc.line(line)?.text;
+            let bounds: HashSet<usize> = text.split_word_bound_indices().map(|(i, _)| i).collect();
+            (self.graphemes, self.bounds) = text.grapheme_indices(true).map(|(i, g)| (String::from(g), bounds.contains(&i))).unzip();
+            if line < self.last_line() {
+                self.graphemes.push(String::from("\n"));
+                self.bounds.push(true);
+            }
+        }
+        Some(offset - self.start)
+    }
+
+    fn grapheme(&mut self, offset: usize) -> Option<&str> {
+        let i = self.load(offset)?;
+        Some(self.graphemes[i].as_str())
+    }
+
+    fn is_char(&mut self, offset: usize, c: char) -> bool {
+        self.grapheme(offset).is_some_and(|g| g.chars().eq(once(c)))
+    }
+
+    fn class(&mut self, offset: usize) -> Class {
+        match self.grapheme(offset) {
+            None | Some("\n") => Class::Newline,
+            Some(g) => {
+                let c = g.chars().next().unwrap_or(' ');
+                if c == ' ' || c == '\t' {
+                    Class::Blank
+                } else if c.is_alphanumeric() || c == '_' {
+                    Class::Word
+                } else {
+                    Class::Punctuation
+                }
+            },
+        }
+    }
+
+    // whether the graphemes at `offset - 1` and `offset` are in the same word (or WORD, if `big`);
+    // letters and digits are split at Unicode word boundaries, and punctuation is grouped like vim
+    fn joined(&mut self, offset: usize, big: bool) -> bool {
+        if offset == 0 || offset >= self.len() {
+            return false;
+        }
+        match (self.class(offset - 1), self.class(offset)) {
+            (Class::Blank | Class::Newline, _) | (_, Class::Blank | Class::Newline) => false,
+            _ if big => true,
+            (Class::Word, Class::Word) => self.load(offset).is_some_and(|i| !self.bounds[i]),
+            (before, class) => before == class,
+        }
+    }
+
+    // whether `offset` is the newline of an empty line (which counts as a word)
+    fn is_empty_line_at(&mut self, offset: usize) -> bool {
+        offset < self.len() && self.class(offset) == Class::Newline && (offset == 0 || self.class(offset - 1) == Class::Newline)
+    }
+
+    // whether `offset` is blank, or a newline that isn't an empty line (i.e. skipped by word motions)
+    fn is_space(&mut self, offset: usize) -> bool {
+        match self.class(offset) {
+            Class::Blank => true,
+            Class::Newline => !self.is_empty_line_at(offset),
+            _ => false,
+        }
+    }
+
+    fn next_word_start(&mut self, mut offset: usize, big: bool) -> usize {
+        match self.class(offset) {
+            Class::Word | Class::Punctuation => {
+                offset += 1;
+                while self.joined(offset, big) {
+                    offset += 1;
+                }
+            },
+            Class::Newline => offset += 1,
+            Class::Blank => (),
+        }
+        while offset < self.len() && self.is_space(offset) {
+            offset += 1;
+        }
+        offset.min(self.len())
+    }
+
+    fn word_end(&mut self, offset: usize, big: bool) -> Option<usize> {
+        let mut offset = offset + 1;
+        while offset < self.len() && matches!(self.class(offset), Class::Blank | Class::Newline) {
+            offset += 1;
+        }
+        if offset >= self.len() {
+            return None;
+        }
+        while self.joined(offset + 1, big) {
+            offset += 1;
+        }
+        Some(offset)
+    }
+
+    fn word_start(&mut self, offset: usize, big: bool) -> Option<usize> {
+        let mut offset = offset.checked_sub(1)?;
+        while offset > 0 && self.is_space(offset) {
+            offset -= 1;
+        }
+        while self.joined(offset, big) {
+            offset -= 1;
+        }
+        Some(offset)
+    }
+
+    fn previous_word_end(&mut self, mut offset: usize, big: bool) -> Option<usize> {
+        while self.joined(offset, big) {
+            offset -= 1;
+        }
+        let mut offset = offset.checked_sub(1)?;
+        while offset > 0 && self.is_space(offset) {
+            offset -= 1;
+        }
+        Some(offset)
+    }
+
+    // the word (or run of blanks) around `offset`, in the line
+    fn run(&mut self, offset: usize) -> Range<usize> {
+        match self.class(offset) {
+            Class::Newline => offset..offset,
+            Class::Blank => self.blanks_before(offset)..self.blanks_after(offset),
+            _ => {
+                let (mut start, mut end) = (offset, offset + 1);
+                while self.joined(start, false) {
+                    start -= 1;
+                }
+                while self.joined(end, false) {
+                    end += 1;
+                }
+                start..end
+            },
+        }
+    }
+
+    fn blanks_after(&mut self, mut offset: usize) -> usize {
+        while offset < self.len() && self.class(offset) == Class::Blank {
+            offset += 1;
+        }
+        offset
+    }
+
+    fn blanks_before(&mut self, mut offset: usize) -> usize {
+        while offset > 0 && self.class(offset - 1) == Class::Blank {
+            offset -= 1;
+        }
+        offset
+    }
+
+    // whether a sentence starts at `offset`: after the end of a sentence (a `.`, `!` or `?`, any
+    // closing brackets and quotes, and then blanks or the end of the line), at the start of a
+    // paragraph, or at the first of a run of empty lines
+    fn is_sentence_start(&mut self, offset: usize) -> bool {
+        if self.is_empty_line_at(offset) {
+            return offset == 0 || !self.is_empty_line_at(offset - 1);
+        }
+        if matches!(self.class(offset), Class::Blank | Class::Newline) {
+            return false;
+        }
+        let mut before = offset;
+        while before > 0 && matches!(self.class(before - 1), Class::Blank | Class::Newline) {
+            if self.is_empty_line_at(before - 1) {
+                return true;
+            }
+            before -= 1;
+        }
+        if before == 0 || before == offset {
+            return before == 0;
+        }
+        while before > 0 && [')', ']', '"', '\''].into_iter().any(|c| self.is_char(before - 1, c)) {
+            before -= 1;
+        }
+        before > 0 && ['.', '!', '?'].into_iter().any(|c| self.is_char(before - 1, c))
+    }
+
+    // the offset of the bracket that matches the one at `offset`
+    fn matching_bracket(&mut self, offset: usize) -> Option<usize> {
+        let (this, other, forward) = match self.grapheme(offset)? {
+            "(" => ('(', ')', true),
+            "[" => ('[', ']', true),
+            "{" => ('{', '}', true),
+            ")" => (')', '(', false),
+            "]" => (']', '[', false),
+            "}" => ('}', '{', false),
+            _ => return None,
+        };
+        let mut depth = 0;
+        let mut o = offset;
+        loop {
+            o = if forward { o + 1 } else { o.checked_sub(1)? };
+            if o >= self.len() {
+                return None;
+            } else if self.is_char(o, this) {
+                depth += 1;
+            } else if self.is_char(o, other) {
+                if depth == 0 {
+                    return Some(o);
+                }
+                depth -= 1;
+            }
+        }
+    }
+}
+
+/// Returns the range from the cursor to where a motion goes, `count` times (once if there is no
+/// count), or `None` if the motion can't be made (e.g. `f` for a character that isn't in the
+/// line).
+///
+/// `c` is the character typed after the motion, for motions that need one. The motions that need
+/// more than the document (the screen motions, and repeating a find) are made by
+/// [`Window::motion`](crate::window::Window::motion), and are `None` here.
+pub fn motion(doc: &Document, cursor: DocPosition, kind: MotionKind, count: Option<usize>, c: Option<char>) -> Option<TextRange> {
+    let mut text = Text::new(doc);
+    let offset = text.offset(cursor);
+    let n = count.unwrap_or(1).max(1);
+    let DocPosition{ x, y } = cursor;
+    let inclusive = |end: usize| TextRange{ range: offset..end + 1, linewise: false, cursor: end };
+    let backward = |start: usize| TextRange{ range: start..offset, linewise: false, cursor: start };
+    let lines = |first: usize, last: usize, target: usize, text: &Text| {
+        TextRange{ range: text.line_start(first)..text.line_end(last), linewise: true, cursor: target }
+    };
+    let big = matches!(kind, MotionKind::BigWordForward | MotionKind::BigWordEnd | MotionKind::BigWordBackward | MotionKind::BigWordEndBackward);
+    match kind {
+        MotionKind::WordForward | MotionKind::BigWordForward => {
+            let target = (0..n).try_fold(offset, |o, _| if o < text.len() { Some(text.next_word_start(o, big)) } else { None })?;
+            Some(exclusive(&mut text, offset, target))
+        },
+        MotionKind::WordEnd | MotionKind::BigWordEnd => {
+            let target = (0..n).try_fold(offset, |o, _| text.word_end(o, big))?;
+            Some(inclusive(target))
+        },
+        MotionKind::WordBackward | MotionKind::BigWordBackward => {
+            let target = (0..n).try_fold(offset, |o, _| text.word_start(o, big))?;
+            Some(backward(target))
+        },
+        MotionKind::WordEndBackward | MotionKind::BigWordEndBackward => {
+            let target = (0..n).try_fold(offset, |o, _| text.previous_word_end(o, big))?;
+            Some(TextRange{ range: target..(offset + 1).min(text.len()), linewise: false, cursor: target })
+        },
+        MotionKind::SentenceForward => {
+            let mut target = offset;
+            for _ in 0..n {
+                match (target + 1..text.len()).find(|o| text.is_sentence_start(*o)) {
+                    Some(start) => target = start,
+                    None => return end_of_document(&mut text, offset),
+                }
+            }
+            Some(exclusive(&mut text, offset, target))
+        },
+        MotionKind::SentenceBackward => {
+            let mut target = offset;
+            for _ in 0..n {
+                target = (0..target).rev().find(|o| text.is_sentence_start(*o)).unwrap_or(0);
+            }
+            if target == offset { None } else { Some(backward(target)) }
+        },
+        MotionKind::ParagraphForward => {
+            let mut line = y;
+            for _ in 0..n {
+                while line < text.last_line() && text.is_empty_line(line) {
+                    line += 1;
+                }
+                while line < text.last_line() && !text.is_empty_line(line) {
+                    line += 1;
+                }
+            }
+            if text.is_empty_line(line) && line != y {
+                let target = text.line_start(line);
+                Some(exclusive(&mut text, offset, target))
+            } else {
+                end_of_document(&mut text, offset)
+            }
+        },
+        MotionKind::ParagraphBackward => {
+            let mut line = y;
+            for _ in 0..n {
+                while line > 0 && text.is_empty_line(line) {
+                    line -= 1;
+                }
+                while line > 0 && !text.is_empty_line(line) {
+                    line -= 1;
+                }
+            }
+            let target = text.line_start(line);
+            if target == offset { None } else { Some(backward(target)) }
+        },
+        MotionKind::LineStart => Some(backward(text.line_start(y))),
+        MotionKind::LineEnd => {
+            let line = y + n - 1;
+            if line > text.last_line() {
+                return None;
+            }
+            let end = text.line_end(line);
+            let target = if text.is_empty_line(line) { end } else { end - 1 };
+            Some(TextRange{ range: offset..end, linewise: false, cursor: target })
+        },
+        MotionKind::FirstLine => Some(to_line(doc, cursor, count.unwrap_or(1).max(1) - 1)),
+        MotionKind::LastLine => Some(to_line(doc, cursor, count.map_or(text.last_line(), |n| n.max(1) - 1))),
+        MotionKind::MatchPair => {
+            // with a count, to that percentage of the document (like vim)
+            if let Some(percent) = count {
+                if percent > 100 {
+                    return None;
+                }
+                let line = (percent * doc.num_lines()).div_ceil(100);
+                return Some(to_line(doc, cursor, line.max(1) - 1));
+            }
+            // the first bracket at or after the cursor in the line
+            let end = text.line_end(y);
+            let target = (offset..end).find_map(|o| text.matching_bracket(o))?;
+            if target > offset {
+                Some(inclusive(target))
+            } else {
+                Some(TextRange{ range: target..offset + 1, linewise: false, cursor: target })
+            }
+        },
+        MotionKind::FindForward | MotionKind::TillForward => {
+            let c = c?;
+            let end = text.line_end(y);
+            let mut found = offset;
+            for _ in 0..n {
+                found = (found + 1..end).find(|o| text.is_char(*o, c))?;
+            }
+            let target = if kind == MotionKind::TillForward { found - 1 } else { found };
+            if target == offset { None } else { Some(inclusive(target)) }
+        },
+        MotionKind::FindBackward | MotionKind::TillBackward => {
+            let c = c?;
+            let start = text.line_start(y);
+            let mut found = offset;
+            for _ in 0..n {
+                found = (start..found).rev().find(|o| text.is_char(*o, c))?;
+            }
+            let target = if kind == MotionKind::TillBackward { found + 1 } else { found };
+            if target == offset { None } else { Some(backward(target)) }
+        },
+        MotionKind::Down => {
+            let last = y + n;
+            if last > text.last_line() {
+                return None;
+            }
+            let target = text.line_start(last) + x.min(doc.line_graphemes(last)?.saturating_sub(1));
+            Some(lines(y, last, target, &text))
+        },
+        MotionKind::Up => {
+            let first = y.checked_sub(n)?;
+            let target = text.line_start(first) + x.min(doc.line_graphemes(first)?.saturating_sub(1));
+            Some(lines(first, y, target, &text))
+        },
+        MotionKind::Line => {
+            let last = (y + n - 1).min(text.last_line());
+            Some(lines(y, last, offset, &text))
+        },
+        MotionKind::ScreenTop | MotionKind::ScreenMiddle | MotionKind::ScreenBottom
+            | MotionKind::RepeatFind | MotionKind::RepeatFindReverse => None,
+    }
+}
+
+/// Returns the linewise range from the cursor's line to `line` (or the last line, if there aren't
+/// that many lines), with the cursor going to the first non-blank character of `line` (like
+/// vim's `G`).
+pub fn to_line(doc: &Document, cursor: DocPosition, line: usize) -> TextRange {
+    let mut text = Text::new(doc);
+    let line = line.min(text.last_line());
+    let (start, end) = (text.line_start(line), text.line_end(line));
+    let target = text.blanks_after(start).min(end.saturating_sub(1).max(start));
+    TextRange{ range: text.line_start(line.min(cursor.y))..text.line_end(line.max(cursor.y)), linewise: true, cursor: target }
+}
+
+// the range from `start` to the end of the document (which is included), or `None` if `start` is
+// already at the last grapheme
+fn end_of_document(text: &mut Text, start: usize) -> Option<TextRange> {
+    let last = text.len().checked_sub(1)?;
+    if start >= last {
+        return None;
+    }
+    Some(TextRange{ range: start..text.len(), linewise: false, cursor: last })
+}
+
+// an exclusive range from `start` to `end`; like vim, if `end` is at the start of a later line,
+// the range stops at the end of the line before it (so `dw` on the last word of a line doesn't
+// join the lines)
+fn exclusive(text: &mut Text, start: usize, end: usize) -> TextRange {
+    let range_end = if end > start && end < text.len() && text.position(end).x == 0 && text.position(end).y > text.position(start).y {
+        end - 1
+    } else {
+        end
+    };
+    TextRange{ range: start..range_end, linewise: false, cursor: end }
+}
+
+/// Returns the range that vim's `cw` (or `cW`, if `big` is `true`) changes, `count` words (one if
+/// there is no count), or `None` if the cursor isn't on a non-blank (where `cw` acts like `dw`).
+///
+/// Like `ce`, this is to the end of the word rather than to the start of the next one, except that
+/// the word the cursor is on counts as the first word, even at its last character.
+pub fn change_word(doc: &Document, cursor: DocPosition, big: bool, count: Option<usize>) -> Option<TextRange> {
+    let mut text = Text::new(doc);
+    let offset = text.offset(cursor);
+    if offset >= text.len() || !matches!(text.class(offset), Class::Word | Class::Punctuation) {
+        return None;
+    }
+    let mut end = offset;
+    while text.joined(end + 1, big) {
+        end += 1;
+    }
+    // past the last word, change as many words as there are
+    for _ in 1..count.unwrap_or(1).max(1) {
+        match text.word_end(end, big) {
+            Some(next) => end = next,
+            None => break,
+        }
+    }
+    Some(TextRange{ range: offset..end + 1, linewise: false, cursor: end })
+}
+
+/// Returns the range of a text object around the cursor (`count` of them, for objects that can be
+/// repeated), or `None` if there is no such text object (e.g. `i(` outside of parentheses).
+pub fn text_object(doc: &Document, cursor: DocPosition, kind: ObjectKind, count: usize) -> Option<TextRange> {
+    let mut text = Text::new(doc);
+    let offset = text.offset(cursor);
+    let count = count.max(1);
+    let chars = |range: Range<usize>| TextRange{ cursor: range.start, range, linewise: false };
+    match kind {
+        ObjectKind::InnerWord => {
+            let mut range = text.run(offset);
+            if range.is_empty() {
+                return None;
+            }
+            for _ in 1..count {
+                let next = text.run(range.end);
+                if next.is_empty() { break; }
+                range.end = next.end;
+            }
+            Some(chars(range))
+        },
+        ObjectKind::AWord => {
+            let mut range = text.run(offset);
+            if range.is_empty() {
+                return None;
+            }
+            let on_blank = text.class(offset) == Class::Blank;
+            for i in 0..count {
+                if i > 0 || on_blank {
+                    // the word after the blanks
+                    let next = text.run(range.end);
+                    if next.is_empty() { break; }
+                    range.end = next.end;
+                }
+                if !on_blank {
+                    let end = text.blanks_after(range.end);
+                    if end == range.end && i == 0 && count == 1 {
+                        range.start = text.blanks_before(range.start);
+                    }
+                    range.end = end;
+                }
+            }
+            Some(chars(range))
+        },
+        ObjectKind::InnerQuote(quote) | ObjectKind::AQuote(quote) => {
+            let (start, end) = (text.line_start(cursor.y), text.line_end(cursor.y));
+            let mut quotes = Vec::new();
+            let mut escaped = false;
+            for o in start..end {
+                let g = text.grapheme(o)?;
+                if escaped {
+                    escaped = false;
+                } else if g == "\\" {
+                    escaped = true;
+                } else if g.chars().eq(once(quote)) {
+                    quotes.push(o);
+                }
+            }
+            let (open, close) = quotes.chunks_exact(2).map(|p| (p[0], p[1]))
+                .find(|(open, close)| (*open..=*close).contains(&offset))
+                .or_else(|| quotes.chunks_exact(2).map(|p| (p[0], p[1])).find(|(open, _)| *open > offset))?;
+            if let ObjectKind::InnerQuote(_) = kind {
+                Some(chars(open + 1..close))
+            } else {
+                let after = text.blanks_after(close + 1);
+                let before = if after == close + 1 { text.blanks_before(open) } else { open };
+                Some(chars(before..after))
+            }
+        },
+        ObjectKind::InnerBlock(open_char, close_char) | ObjectKind::ABlock(open_char, close_char) => {
+            // the `count`th unmatched opening bracket at or before the cursor
+            let mut open = if text.is_char(offset, close_char) { offset.checked_sub(1)? } else { offset };
+            let mut depth = 0;
+            let mut found = 0;
+            loop {
+                if text.is_char(open, close_char) {
+                    depth += 1;
+                } else if text.is_char(open, open_char) {
+                    if depth == 0 {
+                        found += 1;
+                        if found == count { break; }
+                    } else {
+                        depth -= 1;
+                    }
+                }
+                open = open.checked_sub(1)?;
+            }
+            // the bracket that matches it
+            let mut close = open + 1;
+            let mut depth = 0;
+            while close < text.len() {
+                if text.is_char(close, open_char) {
+                    depth += 1;
+                } else if text.is_char(close, close_char) {
+                    if depth == 0 { break; }
+                    depth -= 1;
+                }
+                close += 1;
+            }
+            if close >= text.len() {
+                return None;
+            }
+            if let ObjectKind::ABlock(..) = kind {
+                return Some(chars(open..close + 1));
+            }
+            // like vim, a block that starts at the end of a line and ends at the start of one
+            // (after its indentation) is the lines in between
+            let mut start = open + 1;
+            if text.class(start) == Class::Newline && start < close {
+                start += 1;
+            }
+            let close_line = text.position(close).y;
+            let mut end = close;
+            if close_line > text.position(start).y && text.blanks_before(close) == text.line_start(close_line) {
+                end = text.line_start(close_line).saturating_sub(1).max(start);
+                if end > start { end += 1; }
+            }
+            Some(chars(start..end.max(start)))
+        },
+        ObjectKind::InnerParagraph | ObjectKind::AParagraph => {
+            let blank = text.is_empty_line(cursor.y);
+            let same = |text: &Text, line: usize| text.is_empty_line(line) == blank;
+            let mut first = cursor.y;
+            while first > 0 && same(&text, first - 1) {
+                first -= 1;
+            }
+            let mut last = cursor.y;
+            let runs = if kind == ObjectKind::AParagraph { 2 * count } else { count };
+            for i in 0..runs {
+                if i > 0 {
+                    if last == text.last_line() { break; }
+                    last += 1;
+                }
+                let empty = text.is_empty_line(last);
+                while last < text.last_line() && text.is_empty_line(last + 1) == empty {
+                    last += 1;
+                }
+            }
+            // `ap` at the end of the document takes the empty lines before the paragraph instead
+            if kind == ObjectKind::AParagraph && !blank && text.is_empty_line(last) == blank {
+                while first > 0 && text.is_empty_line(first - 1) {
+                    first -= 1;
+                }
+            }
+            Some(TextRange{ range: text.line_start(first)..text.line_end(last), linewise: true, cursor: text.line_start(first) })
+        },
+    }
+}
+
+#[cfg(test)]
+fn at(doc: &Document, offset: usize) -> DocPosition {
+    Text::new(doc).position(offset)
+}
+
+#[test]
+fn test_word_motions() {
+    let doc = Document::from("foo bar.baz  qux\n\n  last_word!");
+    let w = |offset, count| motion(&doc, at(&doc, offset), MotionKind::WordForward, Some(count), None);
+    assert_eq!(w(0, 1), Some(TextRange{ range: 0..4, linewise: false, cursor: 4 }));
+    assert_eq!(w(4, 1).unwrap().cursor, 7);
+    assert_eq!(w(4, 3).unwrap().cursor, 13);
+    // stops at the empty line, but not at the end of the line before it
+    assert_eq!(w(13, 1), Some(TextRange{ range: 13..16, linewise: false, cursor: 17 }));
+    assert_eq!(w(17, 1).unwrap().cursor, 20);
+    assert_eq!(w(29, 1).unwrap().cursor, 30);
+    assert_eq!(w(30, 1), None);
+
+    let e = |offset, count| motion(&doc, at(&doc, offset), MotionKind::WordEnd, Some(count), None).map(|r| r.cursor);
+    assert_eq!(e(0, 1), Some(2));
+    assert_eq!(e(2, 1), Some(6));
+    assert_eq!(e(4, 2), Some(7));
+    assert_eq!(e(13, 1), Some(15));
+    assert_eq!(e(15, 1), Some(28));
+    assert_eq!(motion(&doc, at(&doc, 0), MotionKind::WordEnd, None, None).unwrap().range, 0..3);
+    assert_eq!(e(29, 1), None);
+
+    let cw = |offset, big, count| change_word(&doc, at(&doc, offset), big, Some(count)).map(|r| r.range);
+    assert_eq!(cw(0, false, 1), Some(0..3));
+    // the end of the word the cursor is on counts as a word
+    assert_eq!(cw(2, false, 1), Some(2..3));
+    assert_eq!(cw(4, false, 2), Some(4..8));
+    assert_eq!(cw(4, true, 1), Some(4..11));
+    assert_eq!(cw(13, false, 5), Some(13..30));
+    assert_eq!(cw(3, false, 1), None);
+    assert_eq!(cw(16, false, 1), None);
+
+    let b = |offset, count| motion(&doc, at(&doc, offset), MotionKind::WordBackward, Some(count), None);
+    assert_eq!(b(6, 1), Some(TextRange{ range: 4..6, linewise: false, cursor: 4 }));
+    assert_eq!(b(8, 2).unwrap().cursor, 4);
+    assert_eq!(b(20, 1).unwrap().cursor, 17);
+    assert_eq!(b(17, 1).unwrap().cursor, 13);
+    assert_eq!(b(0, 1), None);
+}
+
+#[test]
+fn test_line_motions() {
+    let doc = Document::from("first line\n\nthird\nfourth\n\nlast");
+    let m = |offset, kind, count, c| motion(&doc, at(&doc, offset), kind, Some(count), c);
+    assert_eq!(m(3, MotionKind::LineStart, 1, None), Some(TextRange{ range: 0..3, linewise: false, cursor: 0 }));
+    assert_eq!(m(3, MotionKind::LineEnd, 1, None), Some(TextRange{ range: 3..10, linewise: false, cursor: 9 }));
+    assert_eq!(m(3, MotionKind::LineEnd, 3, None).unwrap().range, 3..17);
+    assert_eq!(m(11, MotionKind::LineEnd, 1, None), Some(TextRange{ range: 11..11, linewise: false, cursor: 11 }));
+    assert_eq!(m(3, MotionKind::LineEnd, 7, None), None);
+
+    // to the empty line, without its newline
+    assert_eq!(m(3, MotionKind::ParagraphForward, 1, None), Some(TextRange{ range: 3..10, linewise: false, cursor: 11 }));
+    assert_eq!(m(3, MotionKind::ParagraphForward, 2, None).unwrap().cursor, 25);
+    // to the end of the document from the last paragraph
+    assert_eq!(m(27, MotionKind::ParagraphForward, 1, None), Some(TextRange{ range: 27..30, linewise: false, cursor: 29 }));
+    assert_eq!(m(29, MotionKind::ParagraphForward, 1, None), None);
+    assert_eq!(m(27, MotionKind::ParagraphBackward, 1, None), Some(TextRange{ range: 25..27, linewise: false, cursor: 25 }));
+    assert_eq!(m(20, MotionKind::ParagraphBackward, 1, None).unwrap().cursor, 11);
+    assert_eq!(m(20, MotionKind::ParagraphBackward, 2, None).unwrap().cursor, 0);
+    assert_eq!(m(0, MotionKind::ParagraphBackward, 1, None), None);
+    assert_eq!(m(11, MotionKind::ParagraphForward, 2, None), Some(TextRange{ range: 11..30, linewise: false, cursor: 29 }));
+
+    assert_eq!(m(2, MotionKind::FindForward, 1, Some('i')), Some(TextRange{ range: 2..8, linewise: false, cursor: 7 }));
+    assert_eq!(m(0, MotionKind::FindForward, 2, Some('i')).unwrap().cursor, 7);
+    assert_eq!(m(0, MotionKind::TillForward, 1, Some(' ')), Some(TextRange{ range: 0..5, linewise: false, cursor: 4 }));
+    assert_eq!(m(4, MotionKind::TillForward, 1, Some(' ')), None);
+    assert_eq!(m(0, MotionKind::FindForward, 1, Some('h')), None);
+
+    assert_eq!(m(3, MotionKind::Down, 2, None), Some(TextRange{ range: 0..17, linewise: true, cursor: 15 }));
+    assert_eq!(m(13, MotionKind::Up, 1, None), Some(TextRange{ range: 11..17, linewise: true, cursor: 11 }));
+    assert_eq!(m(3, MotionKind::Up, 1, None), None);
+    assert_eq!(m(3, MotionKind::Line, 2, None), Some(TextRange{ range: 0..11, linewise: true, cursor: 3 }));
+    assert_eq!(m(27, MotionKind::Line, 5, None).unwrap().range, 26..30);
+}
+
+#[test]
+fn test_more_motions() {
+    let doc = Document::from("Hello wörld. Second (one)! 日本語 x\n\nfoo-bar  baz.qux");
+    let m = |offset, kind, count: Option<usize>, c| motion(&doc, at(&doc, offset), kind, count, c);
+    let to = |offset, kind| m(offset, kind, None, None).map(|r| r.cursor);
+    // words follow Unicode word boundaries
+    assert_eq!(to(6, MotionKind::WordForward), Some(11));
+    assert_eq!(to(27, MotionKind::WordForward), Some(28));
+    assert_eq!(to(34, MotionKind::BigWordForward), Some(43));
+    assert_eq!(to(13, MotionKind::BigWordForward), Some(20));
+    assert_eq!(to(34, MotionKind::WordEnd), Some(36));
+    assert_eq!(to(34, MotionKind::BigWordEnd), Some(40));
+    assert_eq!(to(49, MotionKind::WordBackward), Some(47));
+    assert_eq!(to(49, MotionKind::BigWordBackward), Some(43));
+    assert_eq!(m(43, MotionKind::WordEndBackward, None, None), Some(TextRange{ range: 40..44, linewise: false, cursor: 40 }));
+    assert_eq!(to(47, MotionKind::BigWordEndBackward), Some(40));
+    assert_eq!(to(34, MotionKind::WordEndBackward), Some(33));
+
+    assert_eq!(to(0, MotionKind::SentenceForward), Some(13));
+    assert_eq!(to(13, MotionKind::SentenceForward), Some(27));
+    assert_eq!(m(0, MotionKind::SentenceForward, Some(3), None).unwrap().cursor, 33);
+    assert_eq!(to(33, MotionKind::SentenceForward), Some(34));
+    assert_eq!(m(34, MotionKind::SentenceForward, None, None), Some(TextRange{ range: 34..50, linewise: false, cursor: 49 }));
+    assert_eq!(to(20, MotionKind::SentenceBackward), Some(13));
+    assert_eq!(to(13, MotionKind::SentenceBackward), Some(0));
+    assert_eq!(to(34, MotionKind::SentenceBackward), Some(33));
+    assert_eq!(to(0, MotionKind::SentenceBackward), None);
+
+    assert_eq!(m(13, MotionKind::MatchPair, None, None), Some(TextRange{ range: 13..25, linewise: false, cursor: 24 }));
+    assert_eq!(m(24, MotionKind::MatchPair, None, None), Some(TextRange{ range: 20..25, linewise: false, cursor: 20 }));
+    assert_eq!(to(27, MotionKind::MatchPair), None);
+    assert_eq!(m(0, MotionKind::MatchPair, Some(50), None), Some(TextRange{ range: 0..33, linewise: true, cursor: 33 }));
+    assert_eq!(m(0, MotionKind::MatchPair, Some(101), None), None);
+
+    assert_eq!(m(40, MotionKind::FirstLine, None, None), Some(TextRange{ range: 0..50, linewise: true, cursor: 0 }));
+    assert_eq!(m(0, MotionKind::LastLine, None, None), Some(TextRange{ range: 0..50, linewise: true, cursor: 34 }));
+    assert_eq!(m(0, MotionKind::LastLine, Some(2), None).unwrap().cursor, 33);
+    assert_eq!(m(0, MotionKind::FirstLine, Some(9), None).unwrap().cursor, 34);
+    assert_eq!(to_line(&Document::from("  x\ny"), DocPosition{ x: 0, y: 1 }, 0).cursor, 2);
+
+    assert_eq!(m(31, MotionKind::FindBackward, None, Some('o')), Some(TextRange{ range: 21..31, linewise: false, cursor: 21 }));
+    assert_eq!(m(31, MotionKind::FindBackward, Some(2), Some('o')).unwrap().cursor, 16);
+    assert_eq!(m(31, MotionKind::TillBackward, None, Some('o')).unwrap().cursor, 22);
+    assert_eq!(m(22, MotionKind::TillBackward, None, Some('o')), None);
+    assert_eq!(m(0, MotionKind::FindForward, None, Some('ö')).unwrap().cursor, 7);
+    assert_eq!(m(0, MotionKind::RepeatFind, None, None), None);
+}
+
+#[test]
+fn test_text_objects() {
+    let doc = Document::from("call(foo, \"a b\", (bar))  end\n\nfn x() {\n    body\n}\npara one\npara two\n\n\nnext");
+    let o = |offset, kind, count| text_object(&doc, at(&doc, offset), kind, count).map(|r| r.range);
+    assert_eq!(o(6, ObjectKind::InnerWord, 1), Some(5..8));
+    assert_eq!(o(6, ObjectKind::InnerWord, 3), Some(5..10));
+    assert_eq!(o(6, ObjectKind::AWord, 1), Some(5..8));
+    assert_eq!(o(25, ObjectKind::AWord, 1), Some(23..28));
+    assert_eq!(o(24, ObjectKind::AWord, 1), Some(23..28));
+    assert_eq!(o(5, ObjectKind::AWord, 2), Some(5..10));
+    assert_eq!(o(12, ObjectKind::InnerQuote('"'), 1), Some(11..14));
+    assert_eq!(o(12, ObjectKind::AQuote('"'), 1), Some(9..15));
+    assert_eq!(o(0, ObjectKind::InnerQuote('"'), 1), Some(11..14));
+    assert_eq!(o(19, ObjectKind::InnerBlock('(', ')'), 1), Some(18..21));
+    assert_eq!(o(19, ObjectKind::InnerBlock('(', ')'), 2), Some(5..22));
+    assert_eq!(o(17, ObjectKind::ABlock('(', ')'), 1), Some(17..22));
+    assert_eq!(o(21, ObjectKind::ABlock('(', ')'), 1), Some(17..22));
+    assert_eq!(o(25, ObjectKind::InnerBlock('(', ')'), 1), None);
+    // a block over whole lines is the lines in between
+    assert_eq!(o(42, ObjectKind::InnerBlock('{', '}'), 1), Some(39..48));
+    assert_eq!(o(51, ObjectKind::InnerParagraph, 1), Some(30..67));
+    assert_eq!(o(51, ObjectKind::AParagraph, 1), Some(30..69));
+    assert_eq!(o(68, ObjectKind::InnerParagraph, 1), Some(68..69));
+    assert_eq!(o(70, ObjectKind::AParagraph, 1), Some(68..74));
+    assert!(text_object(&doc, at(&doc, 51), ObjectKind::InnerParagraph, 1).unwrap().linewise);
+}
+
+#[test]
+fn test_from_names() {
+    assert_eq!(MotionKind::from_name("word_forward"), Some(MotionKind::WordForward));
+    assert_eq!(MotionKind::from_name("nope"), None);
+    assert!(MotionKind::from_name("find_forward").unwrap().needs_char());
+    assert!(!MotionKind::from_name("repeat_find").unwrap().needs_char());
+    assert_eq!(MotionKind::TillBackward.reversed(), MotionKind::TillForward);
+    assert_eq!(ObjectKind::from_name("a_quote \""), Some(ObjectKind::AQuote('"')));
+    assert_eq!(ObjectKind::from_name("inner_block )"), Some(ObjectKind::InnerBlock('(', ')')));
+    assert_eq!(ObjectKind::from_name("inner_block x"), None);
+    assert_eq!(ObjectKind::from_name("inner_word extra"), None);
+    assert_eq!(ObjectKind::from_name("a_quote"), None);
+    assert_eq!(Operator::from_name("uppercase"), Some(Operator::Uppercase));
+}
@@ -0,0 +1,427 @@
+//! A module for registers, where yanked and deleted text is kept so it can be put somewhere else
+//! (like vim's registers).
+//!
+//! # Registers
+//! | Register       | Contents                                                                 |
+//! |----------------|--------------------------------------------------------------------------|
+//! | `""` (unnamed) | the text of the last yank, delete or change, whichever register it went to |
+//! | `"0`           | the text of the last yank that wasn't given a register                   |
+//! | `"1` to `"9`   | the text of the last nine deletes and changes of more than part of a line that weren't given a register (`"1` is the newest) |
+//! | `"-`           | the text of the last delete or change within a line that wasn't given a register |
+//! | `"a` to `"z`   | named registers (`"A` to `"Z` add to the end of them instead)            |
+//! | `"_`           | the black hole register: it's always empty, and yanking or deleting into it keeps the other registers as they were |
+//! | `"/`           | the last search pattern (read-only)                                      |
+//! | `":`           | the last command line (read-only)                                        |
+//! | `"+`           | the system clipboard                                                     |
+//! | `"*`           | the primary selection (the system clipboard where there isn't one)       |
+//!
+//! A register is typed before a key bind with a `"` (e.g. `"ayy` or `"bp`); see
+//! [`Editor::register_prefix`](crate::editor::Editor::register_prefix).
+//!
+//! Macros are recorded into registers too (e.g. with `qa`), and any register can be executed as a
+//! macro (e.g. with `@a`); see [`Editor::start_recording`](crate::editor::Editor::start_recording).
+//!
+//! `Registers` only keeps a copy of what was yanked into `"+` and `"*`; the editor copies it to the
+//! system clipboard as well (see [`crate::clipboard`]).
+use crate::config::keybinds::KeyBinds;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::fmt;
+use std::iter::once;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Enum that represents how the text in a register is put into a document.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RegisterKind {
+    /// The text goes where the cursor is (e.g. after `yw`).
+    Char,
+    /// The text is whole lines, which go above or below the cursor's line (e.g. after `yy`).
+    Line,
+    /// The text is a block, which goes into the lines from the cursor's line down (e.g. after
+    /// yanking a `VisualBlockMode` selection).
+    Block,
+}
+
+/// Struct that represents the text in a register.
+///
+/// The text is kept as lines of graphemes, so that it is put as the same graphemes it was yanked
+/// as (e.g. a letter and a combining accent stay together when a block is put into the middle of
+/// other lines).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Register {
+    /// How the text is put into a document.
+    pub kind: RegisterKind,
+    /// The lines of the text, without newlines.
+    pub lines: Vec<Vec<String>>,
+    #[doc(hidden)]
+    keys: Option<Vec<KeyEvent>>, // the key presses, if the text was recorded as a macro
+}
+
+impl Register {
+    /// Create a new `Register` from text whose lines are separated by newlines.
+    ///
+    /// Linewise text doesn't have a newline after its last line.
+    pub fn new(kind: RegisterKind, text: &str) -> Self {
+        let lines = text.split('\n').map(|line| line.graphemes(true).map(String::from).collect()).collect();
+        Register{ kind, lines, keys: None }
+    }
+
+    /// Create a new `Register` from the key presses of a recorded macro.
+    ///
+    /// The text is the key presses, written the way they would be in a `bind` line (except that
+    /// letters keep their case, e.g. `dwi<Esc>`).
+    pub fn recorded(keys: Vec<KeyEvent>) -> Self {
+        let text: String = keys.iter().map(|key| match key.code {
+            KeyCode::Char(c) if (key.modifiers - KeyModifiers::SHIFT).is_empty() => c.to_string(),
+            _ => KeyBinds::key_event_string(*key),
+        }).collect();
+        Register{ keys: Some(keys), ..Self::new(RegisterKind::Char, &text) }
+    }
+
+    /// Create a new `Register` from text that came from outside the editor (e.g. the system
+    /// clipboard).
+    ///
+    /// The text is linewise if it ends with a newline, like the text [`Register::text`] returns.
+    pub fn from_text(text: &str) -> Self {
+        let text = text.replace("\r\n", "\n");
+        match text.strip_suffix('\n') {
+            Some(lines) => Self::new(RegisterKind::Line, lines),
+            None => Self::new(RegisterKind::Char, &text),
+        }
+    }
+
+    /// Create a new blockwise `Register` from the text of each line of the block.
+    pub fn block<'a, I: IntoIterator<Item = &'a str>>(lines: I) -> Self {
+        let lines = lines.into_iter().map(|line| line.graphemes(true).map(String::from).collect()).collect();
+        Register{ kind: RegisterKind::Block, lines, keys: None }
+    }
+
+    /// Returns the text, with its lines separated by newlines (linewise text also has a newline
+    /// after its last line).
+    pub fn text(&self) -> String {
+        let text = self.lines.iter().map(|line| line.concat()).collect::<Vec<String>>().join("\n");
+        if self.kind == RegisterKind::Line { text + "\n" } else { text }
+    }
+
+    /// Returns whether the text was recorded as a macro (see [`Register::recorded`]).
+    pub fn is_recorded(&self) -> bool {
+        self.keys.is_some()
+    }
+
+    /// Returns the key presses to replay when the register is executed as a macro: the key presses
+    /// that were recorded, or the characters of the text (with newlines as `<CR>`).
+    pub fn keys(&self) -> Vec<KeyEvent> {
+        if let Some(keys) = &self.keys {
+            return keys.clone();
+        }
+        self.text().chars().map(|c| match c {
+            '\n' => KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE),
+            c if c.is_uppercase() => KeyEvent::new(KeyCode::Char(c), KeyModifiers::SHIFT),
+            c => KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE),
+        }).collect()
+    }
+
+    /// Returns the number of graphemes in the longest line (i.e. the width of a block).
+    pub fn width(&self) -> usize {
+        self.lines.iter().map(Vec::len).max().unwrap_or(0)
+    }
+
+    // adds `other` to the end of the text; like vim, adding lines to any text makes it linewise
+    fn append(&mut self, mut other: Register) {
+        if self.keys.is_some() || other.keys.is_some() {
+            self.keys = Some(self.keys().into_iter().chain(other.keys()).collect());
+        }
+        match (self.kind, other.kind) {
+            (RegisterKind::Char, RegisterKind::Char) => {
+                let mut rest = other.lines.drain(..);
+                if let (Some(last), Some(first)) = (self.lines.last_mut(), rest.next()) {
+                    last.extend(first);
+                }
+                self.lines.extend(rest);
+            },
+            (RegisterKind::Block, RegisterKind::Block) => self.lines.append(&mut other.lines),
+            _ => {
+                self.kind = RegisterKind::Line;
+                self.lines.append(&mut other.lines);
+            },
+        }
+    }
+}
+
+/// Enum for containing errors that might occur in using registers.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RegisterError {
+    /// There is no register with the given name.
+    InvalidName(char),
+    /// The register can't be written to.
+    ReadOnly(char),
+    /// There is nothing in the register.
+    Empty(char),
+    /// The system clipboard couldn't be copied to or read (see [`crate::clipboard`]).
+    Clipboard(String),
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidName(name) => write!(f, "Invalid register name: '{}'", name),
+            Self::ReadOnly(name) => write!(f, "Register \"{} is read-only", name),
+            Self::Empty(name) => write!(f, "Nothing in register \"{}", name),
+            Self::Clipboard(e) => write!(f, "Clipboard error: {}", e),
+        }
+    }
+}
+
+/// Struct that represents every register (see the [module-level documentation](index.html)).
+#[derive(Default)]
+pub struct Registers {
+    #[doc(hidden)]
+    unnamed: Option<Register>,
+    #[doc(hidden)]
+    numbered: [Option<Register>; 10],
+    #[doc(hidden)]
+    small_delete: Option<Register>,
+    #[doc(hidden)]
+    named: [Option<Register>; 26],
+    #[doc(hidden)]
+    search: Option<String>,
+    #[doc(hidden)]
+    command: Option<String>,
+    #[doc(hidden)]
+    clipboard: Option<Register>,
+    #[doc(hidden)]
+    primary: Option<Register>,
+}
+
+impl Registers {
+    /// Create a new `Registers`, with every register empty.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns whether there is a register named `name`.
+    pub fn is_register(name: char) -> bool {
+        name.is_ascii_alphanumeric() || matches!(name, '"' | '-' | '_' | '/' | ':' | '+' | '*')
+    }
+
+    /// Returns `Ok(())` if the register named `name` can be yanked or deleted into.
+    pub fn check_writable(name: char) -> Result<(), RegisterError> {
+        match name {
+            '/' | ':' => Err(RegisterError::ReadOnly(name)),
+            _ if Self::is_register(name) => Ok(()),
+            _ => Err(RegisterError::InvalidName(name)),
+        }
+    }
+
+    /// Returns the text in the register named `name`.
+    pub fn get(&self, name: char) -> Result<Register, RegisterError> {
+        let register = match name {
+            '"' => self.unnamed.clone(),
+            '0'..='9' => self.numbered[name as usize - '0' as usize].clone(),
+            'a'..='z' | 'A'..='Z' => self.named[Self::named_index(name)].clone(),
+            '-' => self.small_delete.clone(),
+            '_' => None,
+            '+' => self.clipboard.clone(),
+            '*' => self.primary.clone(),
+            '/' => self.search.as_deref().map(|s| Register::new(RegisterKind::Char, s)),
+            ':' => self.command.as_deref().map(|s| Register::new(RegisterKind::Char, s)),
+            _ => return Err(RegisterError::InvalidName(name)),
+        };
+        register.ok_or(RegisterError::Empty(name))
+    }
+
+    /// Puts yanked text into the register named `name`, or the `"0` register if `name` is `None`.
+    ///
+    /// The text also goes into the unnamed register, unless it went into the black hole register.
+    pub fn yank(&mut self, name: Option<char>, text: Register) -> Result<(), RegisterError> {
+        match name {
+            None | Some('"') => {
+                self.numbered[0] = Some(text.clone());
+                self.unnamed = Some(text);
+                Ok(())
+            },
+            Some(name) => self.write(name, text),
+        }
+    }
+
+    /// Puts deleted (or changed) text into the register named `name`.
+    ///
+    /// If `name` is `None`, text within a line goes into the `"-` register, and anything longer
+    /// goes into the `"1` register, moving the text in `"1` to `"8` down a register. The text also
+    /// goes into the unnamed register, unless it went into the black hole register.
+    pub fn delete(&mut self, name: Option<char>, text: Register) -> Result<(), RegisterError> {
+        match name {
+            None | Some('"') => {
+                if text.kind == RegisterKind::Char && text.lines.len() == 1 {
+                    self.small_delete = Some(text.clone());
+                } else {
+                    self.numbered[1..].rotate_right(1);
+                    self.numbered[1] = Some(text.clone());
+                }
+                self.unnamed = Some(text);
+                Ok(())
+            },
+            Some(name) => self.write(name, text),
+        }
+    }
+
+    /// Sets the text of the `"/` register.
+    pub fn set_last_search(&mut self, pattern: &str) {
+        self.search = Some(pattern.to_string());
+    }
+
+    /// Sets the text of the `":` register.
+    pub fn set_last_command(&mut self, line: &str) {
+        self.command = Some(line.to_string());
+    }
+
+    /// Returns the name and text of every register that isn't empty, in the order vim's
+    /// `:registers` lists them.
+    pub fn list(&self) -> Vec<(char, Register)> {
+        once('"').chain('0'..='9').chain(once('-')).chain('a'..='z').chain(['/', ':', '+', '*'])
+            .filter_map(|name| self.get(name).ok().map(|text| (name, text)))
+            .collect()
+    }
+
+    /// Puts the key presses of a recorded macro into the register named `name`.
+    ///
+    /// Unlike yanking, only the register named `name` changes (`"A` to `"Z` add to the end of `"a`
+    /// to `"z`). See [`Registers::is_macro_register`] for the registers macros can be recorded into.
+    pub fn record(&mut self, name: char, keys: Vec<KeyEvent>) -> Result<(), RegisterError> {
+        let text = Register::recorded(keys);
+        match name {
+            '"' => self.unnamed = Some(text),
+            _ if Self::is_macro_register(name) => {
+                self.set(name, text);
+            },
+            _ => return Err(RegisterError::InvalidName(name)),
+        }
+        Ok(())
+    }
+
+    /// Returns whether a macro can be recorded into the register named `name` (`""`, `"0` to `"9`,
+    /// and `"a` to `"z` or `"A` to `"Z`, like vim).
+    pub fn is_macro_register(name: char) -> bool {
+        name.is_ascii_alphanumeric() || name == '"'
+    }
+
+    // writes to a register that was named (and isn't the unnamed register)
+    fn write(&mut self, name: char, text: Register) -> Result<(), RegisterError> {
+        Self::check_writable(name)?;
+        if name != '_' {
+            self.unnamed = Some(self.set(name, text));
+        }
+        Ok(())
+    }
+
+    // sets a writable register other than the unnamed and black hole registers, returning its new
+    // text (`"A` to `"Z` add to the end of `"a` to `"z`)
+    fn set(&mut self, name: char, text: Register) -> Register {
+        let register = match name {
+            '0'..='9' => &mut self.numbered[name as usize - '0' as usize],
+            '-' => &mut self.small_delete,
+            '+' => &mut self.clipboard,
+            '*' => &mut self.primary,
+            _ => &mut self.named[Self::named_index(name)],
+        };
+        let text = match register.take() {
+            Some(mut old) if name.is_ascii_uppercase() => {
+                old.append(text);
+                old
+            },
+            _ => text,
+        };
+        *register = Some(text.clone());
+        text
+    }
+
+    fn named_index(name: char) -> usize {
+        name.to_ascii_lowercase() as usize - 'a' as usize
+    }
+}
+
+#[test]
+fn test_register_text() {
+    let reg = Register::new(RegisterKind::Char, "e\u{301}t\u{e9}\nx");
+    assert_eq!(reg.lines, vec![vec!["e\u{301}", "t", "\u{e9}"], vec!["x"]]);
+    assert_eq!(reg.text(), "e\u{301}t\u{e9}\nx");
+    assert_eq!(reg.width(), 3);
+    assert_eq!(Register::new(RegisterKind::Line, "a\nb").text(), "a\nb\n");
+    assert_eq!(Register::new(RegisterKind::Line, "").text(), "\n");
+    let block = Register::block(["ab", "", "c"]);
+    assert_eq!(block.text(), "ab\n\nc");
+    assert_eq!(block.width(), 2);
+    assert_eq!(Register::from_text("a\r\nb\r\n"), Register::new(RegisterKind::Line, "a\nb"));
+    assert_eq!(Register::from_text("a\nb"), Register::new(RegisterKind::Char, "a\nb"));
+}
+
+#[test]
+fn test_yank_and_delete() {
+    let char_text = |s: &str| Register::new(RegisterKind::Char, s);
+    let line_text = |s: &str| Register::new(RegisterKind::Line, s);
+    let mut regs = Registers::new();
+    assert_eq!(regs.get('"'), Err(RegisterError::Empty('"')));
+    regs.yank(None, char_text("yanked")).unwrap();
+    assert_eq!(regs.get('0'), Ok(char_text("yanked")));
+    assert_eq!(regs.get('"'), Ok(char_text("yanked")));
+
+    // small deletes go into "-, the rest shift through "1 to "9
+    regs.delete(None, char_text("word")).unwrap();
+    assert_eq!(regs.get('-'), Ok(char_text("word")));
+    assert_eq!(regs.get('1'), Err(RegisterError::Empty('1')));
+    for i in 0..10 {
+        regs.delete(None, line_text(&i.to_string())).unwrap();
+    }
+    assert_eq!(regs.get('1'), Ok(line_text("9")));
+    assert_eq!(regs.get('9'), Ok(line_text("1")));
+    assert_eq!(regs.get('"'), Ok(line_text("9")));
+    assert_eq!(regs.get('0'), Ok(char_text("yanked")));
+
+    // named registers don't touch the numbered ones, and uppercase appends
+    regs.delete(Some('a'), char_text("one")).unwrap();
+    regs.yank(Some('A'), char_text(" two")).unwrap();
+    assert_eq!(regs.get('a'), Ok(char_text("one two")));
+    assert_eq!(regs.get('"'), Ok(char_text("one two")));
+    assert_eq!(regs.get('1'), Ok(line_text("9")));
+    regs.yank(Some('A'), line_text("three")).unwrap();
+    assert_eq!(regs.get('a').map(|r| r.text()), Ok(String::from("one two\nthree\n")));
+    regs.yank(Some('B'), char_text("new")).unwrap();
+    assert_eq!(regs.get('b'), Ok(char_text("new")));
+
+    // the black hole register keeps everything as it was
+    regs.delete(Some('_'), line_text("gone")).unwrap();
+    assert_eq!(regs.get('"'), Ok(char_text("new")));
+    assert_eq!(regs.get('_'), Err(RegisterError::Empty('_')));
+
+    assert_eq!(regs.yank(Some(':'), char_text("x")), Err(RegisterError::ReadOnly(':')));
+    assert_eq!(regs.yank(Some('%'), char_text("x")), Err(RegisterError::InvalidName('%')));
+    regs.set_last_command("w");
+    assert_eq!(regs.get(':'), Ok(char_text("w")));
+    let names: String = regs.list().iter().map(|(name, _)| *name).collect();
+    assert_eq!(names, "\"0123456789-ab:");
+    regs.yank(Some('+'), char_text("copied")).unwrap();
+    assert_eq!(regs.get('+'), Ok(char_text("copied")));
+    assert_eq!(regs.get('"'), Ok(char_text("copied")));
+    assert_eq!(regs.get('*'), Err(RegisterError::Empty('*')));
+}
+
+#[test]
+fn test_record() {
+    let key = |c: char| KeyEvent::new(KeyCode::Char(c), if c.is_uppercase() { KeyModifiers::SHIFT } else { KeyModifiers::NONE });
+    let esc = KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE);
+    let mut regs = Registers::new();
+    regs.yank(None, Register::new(RegisterKind::Char, "unnamed")).unwrap();
+    regs.record('q', vec![key('d'), key('w'), key('A'), key('!'), esc]).unwrap();
+    let recorded = regs.get('q').unwrap();
+    assert!(recorded.is_recorded());
+    assert_eq!(recorded.text(), "dwA!<Esc>");
+    assert_eq!(recorded.keys(), vec![key('d'), key('w'), key('A'), key('!'), esc]);
+    assert_eq!(regs.get('"').map(|r| r.text()), Ok(String::from("unnamed")));
+
+    // appending keeps the key presses, and text is executed as the characters in it
+    regs.record('Q', vec![key('j')]).unwrap();
+    assert_eq!(regs.get('q').unwrap().keys(), vec![key('d'), key('w'), key('A'), key('!'), esc, key('j')]);
+    let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE);
+    assert_eq!(Register::new(RegisterKind::Line, "x").keys(), vec![key('x'), enter]);
+    assert!(!regs.get('"').unwrap().is_recorded());
+    assert_eq!(regs.record('+', vec![key('x')]), Err(RegisterError::InvalidName('+')));
+}
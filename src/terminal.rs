@@ -1,5 +1,6 @@
 //! A module for dealing with the terminal device.
 use std::io::{Stdout, Write, stdout};
+use std::time::{Duration, Instant};
 use crossterm::{
     Command,
     Result,
@@ -10,6 +11,7 @@ use crossterm::{
     event::{
         Event,
         KeyEvent,
+        poll,
         read,
     },
     execute,
@@ -60,11 +62,7 @@ impl Terminal {
     }
 
     fn saturating_sub(x: u16, d: u16) -> u16 {
-        if d > x {
-            0
-        } else {
-            x - d
-        }
+        x.saturating_sub(d)
     }
 
     fn set_x(&mut self, x: u16) {
@@ -196,6 +194,29 @@ impl Terminal {
             }
         }
     }
+
+    /// Poll a [`KeyEvent`](https://docs.rs/crossterm/latest/crossterm/event/struct.KeyEvent.html),
+    /// waiting at most `timeout` for it.
+    ///
+    /// Returns `Ok(None)` if there was no key press before the timeout. A `timeout` of `None`
+    /// blocks, like [`Terminal::read_key`].
+    pub fn read_key_timeout(&self, timeout: Option<Duration>) -> Result<Option<KeyEvent>> {
+        let timeout = match timeout {
+            Some(timeout) => timeout,
+            None => return self.read_key().map(Some),
+        };
+        let deadline = Instant::now() + timeout;
+        loop {
+            // other events (e.g. resizes) don't reset the timeout
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if !poll(remaining)? {
+                return Ok(None);
+            }
+            if let Event::Key(key_event) = read()? {
+                return Ok(Some(key_event));
+            }
+        }
+    }
 }
 
 #[doc(hidden)]
@@ -0,0 +1,470 @@
+//! A module for undoing and redoing changes to a document.
+//!
+//! Every change made to a document is a [`Delta`], which is recorded in the window's
+//! [`Differential`](crate::delta::Differential) as well as in its [`UndoHistory`]. Undoing a change
+//! applies the inverses of its deltas, and redoing it applies the deltas again; both of these are
+//! themselves recorded in the differential, so the differential can always be replayed onto the
+//! file on disk.
+//!
+//! Like vim, changes are undone in steps: all of the changes made in one `InsertMode` session are
+//! a single step, and every other change (e.g. deleting a character in `NormalMode`) is a step of
+//! its own.
+//!
+//! Also like vim, the history is a tree: making a change after undoing starts a new branch, rather
+//! than throwing away the changes that were undone. Steps are numbered in the order they were made
+//! (step zero being the document as it was opened), so every state of the document can be reached
+//! by number (see [`UndoHistory::parent`] and [`UndoHistory::redo_towards`]) or by time (see
+//! [`UndoHistory::travel`]).
+//!
+//! # Undo Files
+//! An undo history can be kept between editing sessions in an undo file (see
+//! [`UndoHistory::save`]). Undo files use the same kind of records as differential files (see
+//! [`crate::delta`]): they start with the magic bytes `FIMUNDO\0` and the format version (a
+//! little-endian `u32`), followed by a record with the hash of the file's contents and a record
+//! with the history.
+use crate::delta::{Delta, read_record, write_record};
+use crate::window::DocPosition;
+use bincode::deserialize;
+use serde::{Serialize, Deserialize};
+use std::ffi::OsString;
+use std::fs::{create_dir_all, read, write};
+use std::io::{self, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+const MAGIC: &[u8; 8] = b"FIMUNDO\0";
+const VERSION: u32 = 1;
+
+/// Struct that represents one undo step: changes that are undone and redone together.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UndoStep {
+    #[doc(hidden)]
+    deltas: Vec<Delta>,
+    #[doc(hidden)]
+    cursor: DocPosition, // where the cursor was when the first change was made
+    #[doc(hidden)]
+    time: SystemTime, // when the last change was made
+    #[doc(hidden)]
+    parent: usize, // the root is its own parent
+    #[doc(hidden)]
+    children: Vec<usize>, // oldest first
+    #[doc(hidden)]
+    redo_child: Option<usize>, // the child that redoing goes to
+}
+
+impl UndoStep {
+    fn new(parent: usize, cursor: DocPosition) -> Self {
+        UndoStep{ deltas: Vec::new(), cursor, time: SystemTime::now(), parent, children: Vec::new(), redo_child: None }
+    }
+
+    /// Returns the changes in this step, oldest first.
+    pub fn deltas(&self) -> &[Delta] {
+        &self.deltas[..]
+    }
+
+    /// Returns the position of the cursor when the first change of this step was made.
+    pub fn cursor(&self) -> DocPosition {
+        self.cursor
+    }
+
+    /// Returns the time that the last change of this step was made.
+    pub fn time(&self) -> SystemTime {
+        self.time
+    }
+}
+
+/// Enum that represents how far to travel through the undo history, chronologically (like vim's
+/// `:earlier` and `:later`).
+///
+/// Parsed from a number of steps (e.g. `5`), or a number followed by `s`, `m`, `h` or `d` for a
+/// number of seconds, minutes, hours or days (e.g. `30s`).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Travel {
+    /// Travel a number of steps.
+    Steps(usize),
+    /// Travel a length of time.
+    Time(Duration),
+}
+
+impl FromStr for Travel {
+    type Err = std::num::ParseIntError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let unit = match s.chars().last() {
+            Some('s') => 1,
+            Some('m') => 60,
+            Some('h') => 60 * 60,
+            Some('d') => 24 * 60 * 60,
+            _ => return Ok(Travel::Steps(s.parse()?)),
+        };
+        let n: u64 = s[..s.len() - 1].parse()?;
+        Ok(Travel::Time(Duration::from_secs(n.saturating_mul(unit))))
+    }
+}
+
+/// Struct that represents the changes that can be undone and redone in a document, as a tree of
+/// [`UndoStep`]s.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct UndoHistory {
+    #[doc(hidden)]
+    steps: Vec<UndoStep>, // indexed by step number, step zero has no changes
+    #[doc(hidden)]
+    current: usize,
+    #[doc(hidden)]
+    saved: usize, // the step the file on disk is at
+    #[doc(hidden)]
+    #[serde(skip)]
+    grouping: bool,
+    #[doc(hidden)]
+    #[serde(skip)]
+    group: Option<usize>, // the step being made, if changes are being grouped
+}
+
+impl Default for UndoHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UndoHistory {
+    /// Create a new, empty `UndoHistory`.
+    pub fn new() -> Self {
+        UndoHistory{ steps: vec![UndoStep::new(0, DocPosition::default())], current: 0, saved: 0, grouping: false, group: None }
+    }
+
+    /// Create an `UndoHistory` in which each of `deltas` is a step, with the current step being
+    /// the last one.
+    ///
+    /// This is for when only the changes themselves are known (e.g. when recovering from a
+    /// differential file without a history), so the steps have no cursor positions, and the times
+    /// of the steps are all now.
+    pub fn linear(deltas: &[Delta]) -> Self {
+        let mut history = Self::new();
+        deltas.iter().for_each(|delta| history.record(delta.clone(), DocPosition::default()));
+        history
+    }
+
+    /// Start grouping changes into a single step, until [`UndoHistory::end_group`] is called.
+    pub fn begin_group(&mut self) {
+        self.grouping = true;
+    }
+
+    /// Stop grouping changes, making the changes since [`UndoHistory::begin_group`] a single step.
+    pub fn end_group(&mut self) {
+        self.grouping = false;
+        self.group = None;
+    }
+
+    /// Record a change that was made with the cursor at `cursor`.
+    ///
+    /// Unless changes are being grouped into the current step, this makes a new step (starting a
+    /// new branch, if the current step has any children).
+    pub fn record(&mut self, delta: Delta, cursor: DocPosition) {
+        if self.group != Some(self.current) {
+            let step = self.steps.len();
+            self.steps.push(UndoStep::new(self.current, cursor));
+            self.steps[self.current].children.push(step);
+            self.steps[self.current].redo_child = Some(step);
+            self.current = step;
+            if self.grouping {
+                self.group = Some(step);
+            }
+        }
+        let step = &mut self.steps[self.current];
+        step.deltas.push(delta);
+        step.time = SystemTime::now();
+    }
+
+    /// Returns the step to undo, making its parent the current step.
+    ///
+    /// The caller has to make the changes (i.e. apply the inverses of the step's deltas, newest
+    /// first). Any group being made is ended first.
+    pub fn undo(&mut self) -> Option<&UndoStep> {
+        self.end_group();
+        if self.current == 0 {
+            return None;
+        }
+        let step = self.current;
+        self.current = self.steps[step].parent;
+        self.steps[self.current].redo_child = Some(step);
+        Some(&self.steps[step])
+    }
+
+    /// Returns the step to redo (the child of the current step that was most recently made,
+    /// undone or chosen with [`UndoHistory::redo_towards`]), making it the current step.
+    ///
+    /// The caller has to make the changes (i.e. apply the step's deltas, oldest first). Any group
+    /// being made is ended first.
+    pub fn redo(&mut self) -> Option<&UndoStep> {
+        self.end_group();
+        let step = self.steps[self.current].redo_child?;
+        self.current = step;
+        Some(&self.steps[step])
+    }
+
+    /// Make [`UndoHistory::redo`] go towards step `target`, which must be a descendant of the
+    /// current step.
+    ///
+    /// Returns `false` if `target` isn't a descendant of the current step.
+    pub fn redo_towards(&mut self, target: usize) -> bool {
+        let mut step = target;
+        while step != 0 && self.steps.get(step).is_some_and(|s| s.parent != self.current) {
+            step = self.steps[step].parent;
+        }
+        if step == 0 || step >= self.steps.len() || self.steps[step].parent != self.current {
+            return false;
+        }
+        self.steps[self.current].redo_child = Some(step);
+        true
+    }
+
+    /// Returns `true` if step `ancestor` is step `step` or one of its ancestors.
+    pub fn is_ancestor(&self, ancestor: usize, mut step: usize) -> bool {
+        loop {
+            if step == ancestor {
+                return true;
+            } else if step == 0 || step >= self.steps.len() {
+                return false;
+            }
+            step = self.steps[step].parent;
+        }
+    }
+
+    /// Returns the number of the step that undoing from step `step` goes to.
+    pub fn parent(&self, step: usize) -> Option<usize> {
+        self.steps.get(step).map(|s| s.parent)
+    }
+
+    /// Returns the step numbered `step`.
+    pub fn step(&self, step: usize) -> Option<&UndoStep> {
+        self.steps.get(step)
+    }
+
+    /// Returns the number of the current step (zero being the document as it was opened).
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Returns the number of the step that the file on disk is at (i.e. the step the document was
+    /// at when it was opened or last written).
+    pub fn saved(&self) -> usize {
+        self.saved
+    }
+
+    /// Mark the current step as the one that the file on disk is at.
+    pub fn mark_saved(&mut self) {
+        self.saved = self.current;
+    }
+
+    /// Returns the number of the latest step.
+    pub fn latest(&self) -> usize {
+        self.steps.len() - 1
+    }
+
+    /// Returns the number of the step that traveling `travel` from the current step goes to,
+    /// `earlier` or later.
+    ///
+    /// Steps are traveled in the order they were made, regardless of branches. When traveling by
+    /// time, this is the latest step made at least that long before the current step (or the
+    /// first step), or the latest step made at most that long after it.
+    pub fn travel(&self, travel: Travel, earlier: bool) -> usize {
+        match (travel, earlier) {
+            (Travel::Steps(n), true) => self.current.saturating_sub(n),
+            (Travel::Steps(n), false) => self.current.saturating_add(n).min(self.latest()),
+            (Travel::Time(d), true) => {
+                let time = self.steps[self.current].time.checked_sub(d);
+                (0..self.current).rev().find(|s| time.is_some_and(|t| self.steps[*s].time <= t)).unwrap_or(0)
+            },
+            (Travel::Time(d), false) => {
+                // a time too far in the future to represent is after every step
+                let time = self.steps[self.current].time.checked_add(d);
+                (self.current + 1..self.steps.len()).rev().find(|s| time.is_none_or(|t| self.steps[*s].time <= t)).unwrap_or(self.current)
+            },
+        }
+    }
+
+    /// Returns the path of the undo file for `file`.
+    ///
+    /// Without an `undo_dir`, this is `file` with a `.` prepended to its name and an extension of
+    /// `.fim-undo` (e.g. `src/.main.rs.fim-undo` for `src/main.rs`). Otherwise, it is the full path
+    /// of `file` with every `/` replaced by a `%`, in `undo_dir` (like vim's undo files).
+    pub fn undo_path<P: AsRef<Path>>(file: P, undo_dir: Option<&Path>) -> io::Result<PathBuf> {
+        let file = file.as_ref().canonicalize()?;
+        let name = match undo_dir {
+            Some(_) => OsString::from(file.to_string_lossy().replace(std::path::MAIN_SEPARATOR, "%")),
+            None => {
+                let mut name = OsString::from(".");
+                name.push(file.file_name().ok_or_else(|| Error::other(format!("{} has no file name", file.display())))?);
+                name.push(".fim-undo");
+                name
+            },
+        };
+        Ok(undo_dir.map_or_else(|| file.with_file_name(&name), |dir| dir.join(&name)))
+    }
+
+    /// Write this history to the undo file `path`, for a file whose contents hash to `file_hash`.
+    ///
+    /// The history should be at its saved step (see [`UndoHistory::saved`]), so that it matches the
+    /// file. The directory of `path` is created if it doesn't exist.
+    pub fn save<P: AsRef<Path>>(&self, path: P, file_hash: u64) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        write_record(&mut bytes, &file_hash)?;
+        write_record(&mut bytes, self)?;
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir)?;
+        }
+        write(path, bytes)
+    }
+
+    /// Read a history from the undo file `path`, if it was written for a file whose contents hash
+    /// to `file_hash`.
+    ///
+    /// Returns `Ok(None)` if there is no undo file, or if it was written for different contents
+    /// (i.e. the file was changed without fim).
+    pub fn load<P: AsRef<Path>>(path: P, file_hash: u64) -> io::Result<Option<Self>> {
+        let bytes = match read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let damaged = || Error::new(ErrorKind::InvalidData, "undo file is damaged");
+        let mut records = bytes.strip_prefix(&MAGIC[..]).ok_or_else(damaged)?;
+        let version = records.split_first_chunk::<4>().map(|(version, rest)| {
+            records = rest;
+            u32::from_le_bytes(*version)
+        });
+        if version != Some(VERSION) {
+            return Err(Error::new(ErrorKind::InvalidData, "unsupported undo file version"));
+        }
+        let saved_hash: u64 = read_record(&mut records).and_then(|r| deserialize(r).ok()).ok_or_else(damaged)?;
+        if saved_hash != file_hash {
+            return Ok(None);
+        }
+        let mut history: UndoHistory = read_record(&mut records).and_then(|r| deserialize(r).ok()).ok_or_else(damaged)?;
+        // the undo file is written at the saved step, but don't trust that blindly
+        history.current = history.saved;
+        Ok(Some(history))
+    }
+
+    /// Returns the steps of the tree in the order they should be listed, with the depth of the
+    /// branch each is on.
+    ///
+    /// Newer steps come first. A step's newest child continues its branch, and any older children
+    /// start branches one level deeper.
+    pub fn tree(&self) -> Vec<(usize, usize)> {
+        let mut list = Vec::with_capacity(self.steps.len());
+        self.list_branch(0, 0, &mut list);
+        list.reverse();
+        list
+    }
+
+    // lists, oldest first, the steps on the branch starting at `step`, then every branch off of it
+    fn list_branch(&self, mut step: usize, depth: usize, list: &mut Vec<(usize, usize)>) {
+        loop {
+            list.push((step, depth));
+            let children = &self.steps[step].children;
+            for child in children.iter().take(children.len().saturating_sub(1)) {
+                self.list_branch(*child, depth + 1, list);
+            }
+            match children.last() {
+                Some(child) => step = *child,
+                None => return,
+            }
+        }
+    }
+}
+
+#[test]
+fn test_undo_history() {
+    let insert = |offset: usize, text: &str| Delta::Insert{ offset, text: text.to_string() };
+    let mut history = UndoHistory::new();
+    history.record(insert(0, "a"), DocPosition{ x: 0, y: 0 });
+    history.begin_group();
+    history.record(insert(1, "b"), DocPosition{ x: 1, y: 0 });
+    history.record(insert(2, "c"), DocPosition{ x: 2, y: 0 });
+    history.end_group();
+    assert_eq!(history.current(), 2);
+
+    let step = history.undo().unwrap();
+    assert_eq!(step.deltas(), &[insert(1, "b"), insert(2, "c")]);
+    assert_eq!(step.cursor().x, 1);
+    assert_eq!(history.undo().unwrap().deltas(), &[insert(0, "a")]);
+    assert!(history.undo().is_none());
+    assert_eq!(history.redo().unwrap().deltas(), &[insert(0, "a")]);
+
+    // a new change starts a branch, and redo follows it
+    history.record(insert(1, "d"), DocPosition{ x: 1, y: 0 });
+    assert_eq!(history.current(), 3);
+    assert!(history.redo().is_none());
+    assert_eq!(history.latest(), 3);
+    assert_eq!(history.tree(), vec![(3, 0), (2, 1), (1, 0), (0, 0)]);
+
+    // but the old branch can still be reached
+    history.undo();
+    assert!(history.redo_towards(2));
+    assert_eq!(history.redo().unwrap().deltas(), &[insert(1, "b"), insert(2, "c")]);
+    assert!(history.is_ancestor(1, 2));
+    assert!(!history.is_ancestor(3, 2));
+    assert!(!history.redo_towards(3));
+}
+
+#[test]
+fn test_undo_travel() {
+    assert_eq!("5".parse(), Ok(Travel::Steps(5)));
+    assert_eq!("30s".parse(), Ok(Travel::Time(Duration::from_secs(30))));
+    assert_eq!("2m".parse(), Ok(Travel::Time(Duration::from_secs(120))));
+    assert!("m".parse::<Travel>().is_err());
+
+    let mut history = UndoHistory::linear(&[
+        Delta::Insert{ offset: 0, text: "a".to_string() },
+        Delta::Insert{ offset: 1, text: "b".to_string() },
+        Delta::Insert{ offset: 2, text: "c".to_string() },
+    ]);
+    let now = SystemTime::now();
+    for (step, minutes) in [(0, 10), (1, 6), (2, 4), (3, 0)] {
+        history.steps[step].time = now - Duration::from_secs(minutes * 60);
+    }
+    assert_eq!(history.travel(Travel::Steps(2), true), 1);
+    assert_eq!(history.travel(Travel::Steps(5), false), 3);
+    assert_eq!(history.travel(Travel::Time(Duration::from_secs(5 * 60)), true), 1);
+    assert_eq!(history.travel(Travel::Time(Duration::from_secs(60 * 60)), true), 0);
+    history.current = 1;
+    assert_eq!(history.travel(Travel::Time(Duration::from_secs(3 * 60)), false), 2);
+    assert_eq!(history.travel(Travel::Time(Duration::from_secs(60)), false), 1);
+    // e.g. `:later 99999999999999999d`, which is too long to add to a time
+    let forever = "99999999999999999d".parse().unwrap();
+    assert_eq!(forever, Travel::Time(Duration::from_secs(u64::MAX)));
+    assert_eq!(history.travel(forever, false), 3);
+    assert_eq!(history.travel(forever, true), 0);
+}
+
+#[test]
+fn test_undo_file() {
+    let dir = std::env::temp_dir().join(format!("fim-test-undo-file-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("file.txt");
+    write(&file, "text").unwrap();
+    let file = file.canonicalize().unwrap();
+    assert_eq!(UndoHistory::undo_path(&file, None).unwrap(), file.with_file_name(".file.txt.fim-undo"));
+    let undo_dir = dir.join("undo");
+    let path = UndoHistory::undo_path(&file, Some(&undo_dir)).unwrap();
+    assert_eq!(path.parent(), Some(undo_dir.as_path()));
+    assert!(!path.file_name().unwrap().to_string_lossy().contains('/'));
+
+    let mut history = UndoHistory::linear(&[Delta::Insert{ offset: 0, text: "a".to_string() }]);
+    history.mark_saved();
+    history.save(&path, 42).unwrap();
+    let loaded = UndoHistory::load(&path, 42).unwrap().unwrap();
+    assert_eq!(loaded.current(), 1);
+    assert_eq!(loaded.saved(), 1);
+    assert_eq!(loaded.step(1).unwrap().deltas(), history.step(1).unwrap().deltas());
+    assert!(UndoHistory::load(&path, 43).unwrap().is_none());
+    assert!(UndoHistory::load(dir.join("missing"), 42).unwrap().is_none());
+
+    let bytes = read(&path).unwrap();
+    write(&path, &bytes[..bytes.len() - 1]).unwrap();
+    assert!(UndoHistory::load(&path, 42).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,9 +1,14 @@
 //! A module for handling fim's editor windows.
 //!
 //! A window has a single active [`Document`] and can be split vertically or horizontally.
-use crate::config::options::{LineNumbers, Options};
-use crate::document::Document;
+use crate::config::options::{LineNumbers, Options, TabType};
+use crate::delta::{Delta, Differential};
+use crate::motion::{self, MotionKind, ObjectKind, Operator, TextRange};
+use crate::register::{Register, RegisterKind};
 use crate::terminal::{Position, Size, Terminal};
+use crate::undo::{Travel, UndoHistory};
+use document::{Document, Line};
+use serde::{Serialize, Deserialize};
 use crossterm::{
     Result,
     cursor::{Hide, Show},
@@ -11,8 +16,12 @@ use crossterm::{
 };
 use std::cmp::{max, min};
 use std::collections::HashMap;
-use std::iter::{once, empty, repeat};
-use std::path::PathBuf;
+use std::iter::{once, repeat};
+use std::ops::{Range, RangeInclusive};
+use std::fs::{rename, remove_file, File, Metadata, OpenOptions};
+use std::io::{BufWriter, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use unicode_segmentation::UnicodeSegmentation;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -25,7 +34,7 @@ lazy_static! {
 ///
 /// Because it is possible for documents to have more than 2^16 - 1 lines, this needs to have usize
 /// fields instead of u16 fields, so we can't reuse [`crate::terminal::Position`].
-#[derive(Copy, Clone, Default)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct DocPosition {
     /// Position, horizontally (this is actually a column number).
     pub x: usize,
@@ -33,6 +42,26 @@ pub struct DocPosition {
     pub y: usize
 }
 
+/// Enum that represents the kinds of selections (vim's visual modes).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SelectionKind {
+    /// The graphemes from the anchor to the cursor (vim's `v`).
+    Char,
+    /// The lines from the anchor's line to the cursor's line (vim's `V`).
+    Line,
+    /// The rectangle with the anchor and the cursor at its corners (vim's `<C-v>`).
+    Block,
+}
+
+/// Struct that represents a selection in a window, from its anchor to the cursor (inclusive).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Selection {
+    /// Where the selection was started.
+    pub anchor: DocPosition,
+    /// What is selected between the anchor and the cursor.
+    pub kind: SelectionKind,
+}
+
 #[derive(Copy, Clone)]
 enum WindowLineType {
     DocLine(usize),
@@ -51,6 +80,7 @@ enum ClearType {
 #[derive(Copy, Clone)]
 struct WindowLineProperties {
     pub lines: usize,    // number of window lines the Line takes up
+    #[allow(dead_code)]
     pub cells: usize    // number of terminal cells/columns the Line takes up, we assume cells >= graphemes
 }
 
@@ -68,7 +98,6 @@ impl WindowLineProperties {
 enum LineType<'a> {
     Content(&'a str),
     Continued(&'a str),
-    Tilde
 }
 
 /// Struct that represents a fim window.
@@ -76,6 +105,14 @@ pub struct Window {
     #[doc(hidden)]
     doc: Option<Document>,
     #[doc(hidden)]
+    diff: Option<Differential>, // record of the changes to `doc`
+    #[doc(hidden)]
+    read_only: bool,
+    #[doc(hidden)]
+    backup_pending: bool, // `diff` has changes that haven't been written to its file
+    #[doc(hidden)]
+    backed_up: bool, // this window has written `diff` to its file
+    #[doc(hidden)]
     opt: Options,
     #[doc(hidden)]
     first_line: usize, // zero-based index of first line in document on screen
@@ -92,9 +129,13 @@ pub struct Window {
     #[doc(hidden)]
     target_x: usize, // target x-value (used for moving up and down in documents)
     #[doc(hidden)]
-    line_properties: Vec<WindowLineProperties>,
-    #[doc(hidden)]
     clear_lines: HashMap<ClearType, String>,
+    #[doc(hidden)]
+    last_find: Option<(MotionKind, char)>, // the last `f`, `F`, `t` or `T`, for `;` and `,`
+    #[doc(hidden)]
+    selection: Option<Selection>,
+    #[doc(hidden)]
+    last_selection: Option<(Selection, DocPosition)>, // and where the cursor was, for `gv`
 }
 
 impl Window {
@@ -103,7 +144,7 @@ impl Window {
         let size = term.size();
         assert!(size.height > 1 && size.width > 1);
         let size = Size{ width: size.width, height: size.height - 1 };
-        Window{ doc: None, first_line: 0, pos_in_doc: DocPosition::default(), raw_window_pos: Position::default(), raw_window_size: size, text_start: 0, text_width: size.width - 1, target_x: 0, opt, line_properties: Vec::new(), clear_lines: HashMap::new() }
+        Window{ doc: None, diff: None, read_only: false, backup_pending: false, backed_up: false, first_line: 0, pos_in_doc: DocPosition::default(), raw_window_pos: Position::default(), raw_window_size: size, text_start: 0, text_width: size.width - 1, target_x: 0, opt, clear_lines: HashMap::new(), last_find: None, selection: None, last_selection: None }
     }
 
     /// Create a new, full-terminal Window with the contents of the given file.
@@ -112,15 +153,279 @@ impl Window {
         assert!(size.height > 1 && size.width > 1);
         let size = Size{ width: size.width, height: size.height - 1 };
         let pos_in_doc = DocPosition::default();
-        let document = Document::new(filename)?;
+        let (document, diff) = Self::load(&filename, &opt)?;
         let (text_start, text_width) = Self::compute_text_attrs(&opt, &size, document.num_lines());
-        let line_properties = Self::setup_line_properties(&document, text_width);
-        Ok(Window{ doc: Some(document), first_line: 0, pos_in_doc, raw_window_pos: Position::default(), raw_window_size: size, text_start, text_width, target_x: 0, opt, line_properties, clear_lines: Self::make_clear_lines(size, text_start, text_width) })
+        Ok(Window{ doc: Some(document), diff: Some(diff), read_only: false, backup_pending: false, backed_up: false, first_line: 0, pos_in_doc, raw_window_pos: Position::default(), raw_window_size: size, text_start, text_width, target_x: 0, opt, clear_lines: Self::make_clear_lines(size, text_start, text_width), last_find: None, selection: None, last_selection: None })
     }
 
-    /// Update the window's options.
-    pub fn update_options(&mut self, opt: &Options) {
+    /// Update the window's options, and render it again with them.
+    pub fn update_options(&mut self, opt: &Options, term: &mut Terminal) -> Result<()> {
         self.opt = opt.clone();
+        if self.doc.is_some() {
+            self.lines_changed(term)
+        } else {
+            self.render(term)
+        }
+    }
+
+    /// Replace this window's document with one recovered from a differential file.
+    ///
+    /// `diff` should be the differential that `doc` was recovered from, so that the recovered
+    /// changes are kept in it. The cursor is moved to the start of the document.
+    pub fn recover(&mut self, doc: Document, diff: Differential, term: &mut Terminal) -> Result<()> {
+        self.replace_document(doc, diff, term)
+    }
+
+    /// Replace this window's document with the contents of the given file (like vim's `:edit`).
+    ///
+    /// If this window wrote a differential file for its old document, it is deleted, so any
+    /// unsaved changes to the old document are lost.
+    pub fn open<P: AsRef<Path>>(&mut self, filename: P, term: &mut Terminal) -> Result<()> {
+        let (doc, diff) = Self::load(filename.as_ref(), &self.opt)?;
+        self.remove_backup()?;
+        self.read_only = false;
+        self.replace_document(doc, diff, term)
+    }
+
+    /// Write this window's document to `path`, or to its own file if `path` is `None`.
+    ///
+    /// The file is written atomically: the document is written to a temporary file in the same
+    /// directory, which then replaces the file. If the document was written to its own file, the
+    /// differential starts over (deleting the differential file, since there are no unsaved
+    /// changes), and the undo history is written to the undo file (if the `undo_file` option is
+    /// set). Returns the number of bytes written.
+    pub fn write(&mut self, path: Option<&Path>) -> Result<u64> {
+        let (doc, diff) = match (self.doc.as_ref(), self.diff.as_ref()) {
+            (Some(doc), Some(diff)) => (doc, diff),
+            _ => return Err(Error::other("no file name")),
+        };
+        let own_file = match path {
+            None => true,
+            Some(path) => path.canonicalize().is_ok_and(|p| p == diff.file()),
+        };
+        let path = path.unwrap_or_else(|| diff.file()).to_path_buf();
+        let bytes = write_atomically(doc, &path)?;
+        if own_file {
+            self.rebind(&path)?;
+        }
+        Ok(bytes)
+    }
+
+    /// Write this window's document to `path`, and make `path` the window's file (like vim's
+    /// `:saveas`). Returns the number of bytes written.
+    pub fn save_as(&mut self, path: &Path) -> Result<u64> {
+        let doc = self.doc.as_ref().ok_or_else(|| Error::other("no document"))?;
+        let bytes = write_atomically(doc, path)?;
+        self.rebind(path)?;
+        Ok(bytes)
+    }
+
+    /// Returns `true` if this window's document has changes that haven't been written to its file.
+    pub fn modified(&self) -> bool {
+        self.history().is_some_and(|h| h.current() != h.saved())
+    }
+
+    /// Returns the path of the file this window's document is from, if it has one.
+    pub fn file(&self) -> Option<&Path> {
+        self.diff.as_ref().map(|d| d.file())
+    }
+
+    /// Returns the number of lines in this window's document, if it has one.
+    pub fn num_lines(&self) -> Option<usize> {
+        self.doc.as_ref().map(|d| d.num_lines())
+    }
+
+    fn load(filename: &Path, opt: &Options) -> Result<(Document, Differential)> {
+        let document = Document::new(filename)?;
+        let mut diff = Differential::new(filename)?;
+        if bool::from(opt.undo_file) {
+            let path = UndoHistory::undo_path(filename, opt.undo_dir.path().as_deref())?;
+            // a damaged undo file shouldn't stop the file from being opened
+            if let Ok(Some(history)) = UndoHistory::load(path, diff.file_hash()) {
+                *diff.history_mut() = history;
+            }
+        }
+        Ok((document, diff))
+    }
+
+    // starts a new differential for `path` after the document was written to it, keeping the
+    // undo history
+    fn rebind(&mut self, path: &Path) -> Result<()> {
+        let mut diff = Differential::new(path)?;
+        if let Some(old) = self.diff.as_ref() {
+            *diff.history_mut() = old.history().clone();
+            // the changes in a differential file of the file that was just written are in the file
+            // now, even if this window didn't write the differential file (e.g. it was recovered)
+            if old.file() == diff.file() || self.backed_up {
+                old.remove_backup()?;
+            }
+        }
+        diff.history_mut().mark_saved();
+        self.diff = Some(diff);
+        self.backup_pending = false;
+        self.backed_up = false;
+        self.save_undo()
+    }
+
+    fn replace_document(&mut self, doc: Document, diff: Differential, term: &mut Terminal) -> Result<()> {
+        let (text_start, text_width) = Self::compute_text_attrs(&self.opt, &self.raw_window_size, doc.num_lines());
+        self.doc = Some(doc);
+        self.diff = Some(diff);
+        self.backup_pending = false;
+        self.first_line = 0;
+        self.pos_in_doc = DocPosition::default();
+        self.target_x = 0;
+        self.text_start = text_start;
+        self.text_width = text_width;
+        self.clear_lines = Self::make_clear_lines(self.raw_window_size, text_start, text_width);
+        self.render(term)?;
+        self.q_move(term)?;
+        term.flush()
+    }
+
+    /// Returns the record of the changes made to this window's document, if it has one.
+    pub fn differential(&self) -> Option<&Differential> {
+        self.diff.as_ref()
+    }
+
+    /// Start grouping changes into a single undo step (e.g. when entering insert mode).
+    pub fn begin_undo_group(&mut self) {
+        if let Some(history) = self.history_mut() {
+            history.begin_group();
+        }
+    }
+
+    /// Stop grouping changes into a single undo step.
+    pub fn end_undo_group(&mut self) {
+        if let Some(history) = self.history_mut() {
+            history.end_group();
+        }
+    }
+
+    /// Undo the current undo step, moving the cursor to where it was when the step was made.
+    ///
+    /// Returns `Ok(false)` if there was nothing to undo.
+    pub fn undo(&mut self, term: &mut Terminal) -> Result<bool> {
+        if self.doc.is_none() || self.read_only { return Ok(false); }
+        match self.step_back() {
+            Some(cursor) => self.restore_cursor(cursor, term).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    /// Redo the most recently undone undo step, moving the cursor to where it was when the step
+    /// was made.
+    ///
+    /// Returns `Ok(false)` if there was nothing to redo.
+    pub fn redo(&mut self, term: &mut Terminal) -> Result<bool> {
+        if self.doc.is_none() || self.read_only { return Ok(false); }
+        match self.step_forward() {
+            Some(cursor) => self.restore_cursor(cursor, term).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    /// Undo and redo undo steps (on any branch of the undo tree) until the document is as it was
+    /// after undo step number `step` (where step zero is the document as it was opened).
+    ///
+    /// Returns `Ok(false)` if there is no such step.
+    pub fn undo_to(&mut self, step: usize, term: &mut Terminal) -> Result<bool> {
+        if self.doc.is_none() || self.read_only { return Ok(false); }
+        if self.history().is_none_or(|h| step > h.latest()) { return Ok(false); }
+        // undo back to a step the target is on a branch of, then redo along that branch
+        let mut cursor = None;
+        while self.history().is_some_and(|h| !h.is_ancestor(h.current(), step)) {
+            cursor = self.step_back().or(cursor);
+        }
+        while self.history().is_some_and(|h| h.current() != step) {
+            self.history_mut().unwrap().redo_towards(step);
+            match self.step_forward() {
+                Some(step_cursor) => cursor = Some(step_cursor),
+                None => break,
+            }
+        }
+        if let Some(cursor) = cursor {
+            self.restore_cursor(cursor, term)?;
+        }
+        Ok(true)
+    }
+
+    /// Travel chronologically through the undo history (like vim's `g-`, `g+`, `:earlier` and
+    /// `:later`). See [`UndoHistory::travel`].
+    ///
+    /// Returns `Ok(false)` if there was nowhere to travel to.
+    pub fn travel(&mut self, travel: Travel, earlier: bool, term: &mut Terminal) -> Result<bool> {
+        let (current, target) = match self.history() {
+            Some(history) => (history.current(), history.travel(travel, earlier)),
+            None => return Ok(false),
+        };
+        if current == target { return Ok(false); }
+        self.undo_to(target, term)
+    }
+
+    /// Write the undo history of this window's document to its undo file, if the `undo_file`
+    /// option is set and the document is the same as the file on disk.
+    pub fn save_undo(&self) -> Result<()> {
+        let diff = match self.diff.as_ref() {
+            Some(diff) if bool::from(self.opt.undo_file) => diff,
+            _ => return Ok(()),
+        };
+        let history = diff.history();
+        if history.latest() == 0 || history.current() != history.saved() { return Ok(()); }
+        let path = UndoHistory::undo_path(diff.file(), self.opt.undo_dir.path().as_deref())?;
+        history.save(path, diff.file_hash())
+    }
+
+    /// Returns the cursor position and the number of changes made to the document, which change
+    /// whenever an edit or a cursor movement does anything.
+    pub fn progress(&self) -> (DocPosition, usize) {
+        (self.pos_in_doc, self.diff.as_ref().map_or(0, |diff| diff.deltas().len()))
+    }
+
+    /// Returns the undo history of this window's document, if it has one.
+    pub fn history(&self) -> Option<&UndoHistory> {
+        self.diff.as_ref().map(|d| d.history())
+    }
+
+    /// Returns `true` if there are changes that haven't been written to the differential file.
+    pub fn backup_pending(&self) -> bool {
+        self.backup_pending
+    }
+
+    /// Write the changes to this window's document to its differential file, if any changes
+    /// haven't been written yet.
+    pub fn backup(&mut self) -> Result<()> {
+        if !self.backup_pending { return Ok(()); }
+        // if writing fails, don't retry until there's another change
+        self.backup_pending = false;
+        if let Some(diff) = self.diff.as_ref() {
+            diff.backup()?;
+            self.backed_up = true;
+        }
+        Ok(())
+    }
+
+    /// Delete the differential file, if this window wrote it.
+    ///
+    /// A differential file that was already there (e.g. one that the user chose not to recover)
+    /// is left alone.
+    pub fn remove_backup(&mut self) -> Result<()> {
+        if !self.backed_up { return Ok(()); }
+        if let Some(diff) = self.diff.as_ref() {
+            diff.remove_backup()?;
+            self.backed_up = false;
+        }
+        Ok(())
+    }
+
+    /// Set whether this window's document can be changed.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Returns `true` if this window's document can't be changed.
+    pub fn read_only(&self) -> bool {
+        self.read_only
     }
 
     /// Render this window's contents to the terminal screen.
@@ -149,7 +454,7 @@ impl Window {
     pub fn move_right(&mut self, term: &mut Terminal) -> Result<()> {
         // TODO: multicell characters
         if self.doc.is_none() { return Ok(()) }
-        if self.pos_in_doc.x + 1 < self.doc.as_ref().unwrap().line(self.pos_in_doc.y).unwrap().graphemes {
+        if self.pos_in_doc.x + 1 < self.line_graphemes(self.pos_in_doc.y) {
             self.pos_in_doc.x += 1;
             self.target_x = self.pos_in_doc.x;
             self.q_move(term)?;
@@ -158,6 +463,20 @@ impl Window {
         Ok(())
     }
 
+    /// Move the cursor back onto the last character of the line, if it is past the end of it
+    /// (which it can only be in InsertMode).
+    pub fn leave_line_end(&mut self, term: &mut Terminal) -> Result<()> {
+        if self.doc.is_none() { return Ok(()) }
+        let len = self.line_graphemes(self.pos_in_doc.y);
+        if self.pos_in_doc.x > 0 && self.pos_in_doc.x >= len {
+            self.pos_in_doc.x = len.saturating_sub(1);
+            self.target_x = self.pos_in_doc.x;
+            self.q_move(term)?;
+            term.flush()?;
+        }
+        Ok(())
+    }
+
     /// Move the cursor one line up, if possible.
     ///
     /// If the line the cursor moves to is long enough, the cursor will stay in the same terminal
@@ -166,7 +485,7 @@ impl Window {
         if self.doc.is_none() { return Ok(()) }
         if self.pos_in_doc.y > 0 {
             self.pos_in_doc.y -= 1;
-            self.pos_in_doc.x = min(self.target_x, self.doc.as_ref().unwrap().line(self.pos_in_doc.y).unwrap().graphemes);
+            self.pos_in_doc.x = min(self.target_x, self.line_graphemes(self.pos_in_doc.y));
 
             if self.pos_in_doc.y + 1 == self.first_line {
                 self.first_line -= 1;
@@ -188,7 +507,7 @@ impl Window {
         if self.doc.is_none() { return Ok(()) }
         if self.pos_in_doc.y + 1 < self.doc.as_ref().unwrap().num_lines() {
             self.pos_in_doc.y += 1;
-            self.pos_in_doc.x = min(self.target_x, self.doc.as_ref().unwrap().line(self.pos_in_doc.y).unwrap().graphemes);
+            self.pos_in_doc.x = min(self.target_x, self.line_graphemes(self.pos_in_doc.y));
 
             if self.pos_in_doc.y == self.first_line + self.raw_window_size.height as usize {
                 self.first_line += 1;
@@ -214,7 +533,7 @@ impl Window {
     /// Move the cursor to the end of the current line.
     pub fn end(&mut self, term: &mut Terminal) -> Result<()> {
         if self.doc.is_none() { return Ok(()) }
-        let last = self.doc.as_ref().unwrap().line(self.pos_in_doc.y).unwrap().graphemes - 1;
+        let last = self.line_graphemes(self.pos_in_doc.y).saturating_sub(1);
         self.pos_in_doc.x = last;
         self.target_x = self.pos_in_doc.x;
         self.q_move(term)?;
@@ -223,9 +542,25 @@ impl Window {
     
     /// Inserts a 'tab' according to the current tab options at the current position in the
     /// document.
+    ///
+    /// The cursor is placed after the inserted tab.
     pub fn tab(&mut self, term: &mut Terminal) -> Result<()> {
-        // TODO
-        Ok(())
+        if self.doc.is_none() || self.read_only { return Ok(()); }
+        let (tab, graphemes) = match self.opt.tab_type {
+            TabType::Tab => ("\t".to_string(), 1),
+            TabType::Spaces => {
+                let spaces = i32::from(self.opt.tab_spaces) as usize;
+                (" ".repeat(spaces), spaces)
+            }
+        };
+        let old_lines = self.line_properties(self.pos_in_doc.y).lines;
+        let offset = self.offset();
+        self.change(Delta::Insert{ offset, text: tab });
+        self.pos_in_doc.x += graphemes;
+        self.target_x = self.pos_in_doc.x;
+        self.update_render(old_lines, term)?;
+        self.q_move(term)?;
+        term.flush()
     }
 
     /// Simulates an 'Enter' press.
@@ -234,28 +569,23 @@ impl Window {
     /// current character to the end of the line (inclusive) and moves them to the new line. (This
     /// is the regular 'Enter' behavior you should be used to.)
     pub fn enter(&mut self, term: &mut Terminal) -> Result<()> {
-        // TODO
-        Ok(())
+        if self.doc.is_none() || self.read_only { return Ok(()); }
+        let offset = self.offset();
+        self.change(Delta::Insert{ offset, text: "\n".to_string() });
+        let y = self.pos_in_doc.y;
+        self.pos_in_doc = DocPosition{ x: 0, y: y + 1 };
+        self.target_x = 0;
+        self.lines_changed(term)
     }
 
     /// Deletes the grapheme under the cursor.
     pub fn delete(&mut self, term: &mut Terminal) -> Result<()> {
-        if self.doc.is_none() { return Ok(()); }
-        let mut line = self.doc.as_mut().unwrap().line_mut(self.pos_in_doc.y).unwrap();
-        let new_text = line.text.graphemes(true)
-                           .enumerate()
-                           .flat_map(|(i, x)| {
-                               let output: Box<dyn Iterator<Item = &str>> = if i == self.pos_in_doc.x {
-                                   Box::new(empty())
-                               } else {
-                                   Box::new(once(x))
-                               };
-                               output
-                           })
-                           .collect::<String>();
-        line.text = new_text;
-        line.graphemes -= 1;
-        self.update_render(term)
+        if self.doc.is_none() || self.read_only { return Ok(()); }
+        if self.pos_in_doc.x >= self.line_graphemes(self.pos_in_doc.y) { return Ok(()); }
+        let old_lines = self.line_properties(self.pos_in_doc.y).lines;
+        let offset = self.offset();
+        self.delete_grapheme(offset);
+        self.update_render(old_lines, term)
     }
 
     /// Deletes the character preceding the cursor.
@@ -263,26 +593,319 @@ impl Window {
     /// If the cursor is at the beginning of the line, the current line is appended to the end of
     /// the line immediately above it.
     pub fn backspace(&mut self, term: &mut Terminal) -> Result<()> {
-        // TODO
-        Ok(())
+        if self.doc.is_none() || self.read_only { return Ok(()); }
+        let DocPosition{ x, y } = self.pos_in_doc;
+        if x == 0 && y == 0 { return Ok(()); }
+        let offset = self.offset();
+        if x > 0 {
+            let old_lines = self.line_properties(y).lines;
+            self.delete_grapheme(offset - 1);
+            self.pos_in_doc.x -= 1;
+            self.target_x = self.pos_in_doc.x;
+            self.update_render(old_lines, term)?;
+            self.q_move(term)?;
+            term.flush()
+        } else {
+            let previous_graphemes = self.line_graphemes(y - 1);
+            self.delete_grapheme(offset - 1);
+            self.pos_in_doc = DocPosition{ x: previous_graphemes, y: y - 1 };
+            self.target_x = previous_graphemes;
+            self.lines_changed(term)
+        }
+    }
+
+    /// Deletes the lines in `lines` (zero-based, inclusive), leaving the cursor at the start of the
+    /// line after them.
+    ///
+    /// Lines past the end of the document are ignored. Deleting every line leaves one empty line.
+    pub fn delete_lines(&mut self, lines: RangeInclusive<usize>, term: &mut Terminal) -> Result<()> {
+        let doc = match self.doc.as_ref() {
+            Some(doc) if !self.read_only => doc,
+            _ => return Ok(()),
+        };
+        let last = doc.num_lines() - 1;
+        let (first, end) = (min(*lines.start(), last), min(*lines.end(), last));
+        let mut start = doc.line_start(first).expect("line should be in the document");
+        let stop = if end < last { doc.line_start(end + 1).expect("line should be in the document") } else { doc.graphemes() };
+        // the last line has no newline of its own, so take the one before it
+        if end == last && first > 0 { start -= 1; }
+        if start == stop { return Ok(()); }
+        let text = doc.text(start..stop).expect("range should be in the document");
+        self.pos_in_doc = DocPosition{ x: 0, y: first };
+        self.change(Delta::Delete{ offset: start, text });
+        self.restore_cursor(DocPosition{ x: 0, y: first }, term)
+    }
+
+    /// Returns the text of the lines in `lines` (zero-based, inclusive) as linewise text.
+    ///
+    /// Lines past the end of the document are ignored.
+    pub fn yank_lines(&self, lines: RangeInclusive<usize>) -> Option<Register> {
+        let doc = self.doc.as_ref()?;
+        let last = doc.num_lines() - 1;
+        let (first, end) = (min(*lines.start(), last), min(*lines.end(), last));
+        let start = doc.line_start(first)?;
+        let text = doc.text(start..doc.line_start(end)? + self.line_graphemes(end))?;
+        Some(Register::new(RegisterKind::Line, &text))
+    }
+
+    /// Moves the cursor to the start of line `line` (zero-based), or the last line if there aren't
+    /// that many lines.
+    pub fn goto_line(&mut self, line: usize, term: &mut Terminal) -> Result<()> {
+        if self.doc.is_none() { return Ok(()); }
+        self.restore_cursor(DocPosition{ x: 0, y: line }, term)
+    }
+
+    /// Returns the position of the cursor in the document.
+    pub fn cursor(&self) -> DocPosition {
+        self.pos_in_doc
+    }
+
+    /// Returns the range that vim's `cw` changes from the cursor (see [`motion::change_word`]).
+    pub fn change_word(&self, big: bool, count: Option<usize>) -> Option<TextRange> {
+        motion::change_word(self.doc.as_ref()?, self.pos_in_doc, big, count)
+    }
+
+    /// Returns the range from the cursor to where a motion goes (see [`motion::motion`]).
+    ///
+    /// This also makes the motions that need the window: the screen motions (`H`, `M` and `L`), and
+    /// repeating the last `f`, `F`, `t` or `T` made in this window (`;` and `,`).
+    pub fn motion(&mut self, kind: MotionKind, count: Option<usize>, c: Option<char>) -> Option<TextRange> {
+        self.doc.as_ref()?;
+        let pos = self.pos_in_doc;
+        match kind {
+            MotionKind::ScreenTop | MotionKind::ScreenMiddle | MotionKind::ScreenBottom => {
+                let (first, last) = (self.first_line, self.last_line_on_screen());
+                let n = count.unwrap_or(1).max(1) - 1;
+                let line = match kind {
+                    MotionKind::ScreenTop => min(first + n, last),
+                    MotionKind::ScreenBottom => max(last.saturating_sub(n), first),
+                    _ => (first + last) / 2,
+                };
+                Some(motion::to_line(self.doc.as_ref()?, pos, line))
+            },
+            MotionKind::RepeatFind | MotionKind::RepeatFindReverse => {
+                let (find, c) = self.last_find?;
+                let find = if kind == MotionKind::RepeatFindReverse { find.reversed() } else { find };
+                let doc = self.doc.as_ref()?;
+                // like vim, repeating `t` doesn't get stuck just before the character it found
+                motion::motion(doc, pos, find, count, Some(c)).or_else(|| match find {
+                    MotionKind::TillForward | MotionKind::TillBackward => motion::motion(doc, pos, find, Some(count.unwrap_or(1) + 1), Some(c)),
+                    _ => None,
+                })
+            },
+            _ => {
+                if let (true, Some(c)) = (kind.needs_char(), c) {
+                    self.last_find = Some((kind, c));
+                }
+                motion::motion(self.doc.as_ref()?, pos, kind, count, c)
+            },
+        }
+    }
+
+    /// Returns the range of a text object around the cursor (see [`motion::text_object`]).
+    pub fn text_object(&self, kind: ObjectKind, count: usize) -> Option<TextRange> {
+        motion::text_object(self.doc.as_ref()?, self.pos_in_doc, kind, count)
+    }
+
+    /// Moves the cursor to a grapheme offset in the document.
+    pub fn move_to_offset(&mut self, offset: usize, term: &mut Terminal) -> Result<()> {
+        let pos = match self.position_of(offset) {
+            Some(pos) => pos,
+            None => return Ok(()),
+        };
+        let y = self.pos_in_doc.y;
+        self.pos_in_doc = pos;
+        self.target_x = pos.x;
+        if pos.y >= self.first_line && self.to_window_text().is_some() {
+            if pos.y != y {
+                self.update_line_numbers(term)?;
+            }
+            self.q_move(term)?;
+            term.flush()
+        } else {
+            self.lines_changed(term)
+        }
+    }
+
+    /// Applies an operator to a range of text (see [`Operator`]).
+    ///
+    /// The cursor is left at the start of the range. Only yanking is done in a read-only window.
+    /// Returns the text that was yanked, deleted or changed, for a register.
+    pub fn operate(&mut self, op: Operator, range: &TextRange, term: &mut Terminal) -> Result<Option<Register>> {
+        let doc = match self.doc.as_ref() {
+            Some(doc) if !self.read_only || op == Operator::Yank => doc,
+            _ => return Ok(None),
+        };
+        let TextRange{ range, linewise, .. } = range.clone();
+        let first = doc.line_of(range.start).expect("range should be in the document");
+        let last = doc.line_of(if linewise { range.end } else { range.end.saturating_sub(1).max(range.start) })
+            .expect("range should be in the document");
+        let register = match op {
+            Operator::Delete | Operator::Change | Operator::Yank => {
+                let kind = if linewise { RegisterKind::Line } else { RegisterKind::Char };
+                Some(Register::new(kind, &doc.text(range.clone()).expect("range should be in the document")))
+            },
+            _ => None,
+        };
+        match op {
+            Operator::Delete if linewise => self.delete_lines(first..=last, term),
+            Operator::Delete => {
+                if !range.is_empty() {
+                    let text = doc.text(range.clone()).expect("range should be in the document");
+                    self.change(Delta::Delete{ offset: range.start, text });
+                }
+                let pos = self.position_of(range.start).expect("range should be in the document");
+                // the cursor can't be past the end of the line outside of InsertMode
+                let x = min(pos.x, self.line_graphemes(pos.y).saturating_sub(1));
+                self.restore_cursor(DocPosition{ x, y: pos.y }, term)
+            },
+            Operator::Change => {
+                let (delta, pos) = changed_range(doc, &range);
+                // the change and what is typed after it are undone at once
+                self.begin_undo_group();
+                if let Some(delta) = delta {
+                    self.change(delta);
+                }
+                self.restore_cursor(pos, term)
+            },
+            Operator::Yank if linewise => {
+                let pos = DocPosition{ x: self.pos_in_doc.x, y: min(first, self.pos_in_doc.y) };
+                self.restore_cursor(pos, term)
+            },
+            Operator::Yank => self.move_to_offset(range.start, term),
+            Operator::Indent | Operator::Outdent => {
+                let tab = match self.opt.tab_type {
+                    TabType::Tab => "\t".to_string(),
+                    TabType::Spaces => " ".repeat(i32::from(self.opt.tab_spaces) as usize),
+                };
+                self.begin_undo_group();
+                for line in first..=last {
+                    let doc = self.doc.as_ref().unwrap();
+                    let offset = doc.line_start(line).expect("line should be in the document");
+                    let text = doc.line(line).expect("line should be in the document").text;
+                    if op == Operator::Indent {
+                        // like vim, empty lines aren't indented
+                        if !text.is_empty() {
+                            self.change(Delta::Insert{ offset, text: tab.clone() });
+                        }
+                    } else {
+                        // removes a tab, or up to a tab's worth of spaces
+                        let len = if text.starts_with('\t') {
+                            1
+                        } else {
+                            text.chars().take(tab.len().max(1)).take_while(|c| *c == ' ').count()
+                        };
+                        if len > 0 {
+                            self.change(Delta::Delete{ offset, text: text[..len].to_string() });
+                        }
+                    }
+                }
+                self.end_undo_group();
+                let text = self.doc.as_ref().unwrap().line(first).expect("line should be in the document").text;
+                let x = text.graphemes(true).take_while(|g| *g == " " || *g == "\t").count();
+                self.restore_cursor(DocPosition{ x: min(x, text.graphemes(true).count().saturating_sub(1)), y: first }, term)
+            },
+            Operator::Lowercase | Operator::Uppercase => {
+                let old = doc.text(range.clone()).expect("range should be in the document");
+                let new = if op == Operator::Lowercase { old.to_lowercase() } else { old.to_uppercase() };
+                if old != new {
+                    self.change(Delta::Replace{ offset: range.start, old, new });
+                }
+                let pos = self.position_of(range.start).expect("range should be in the document");
+                self.restore_cursor(pos, term)
+            },
+        }?;
+        Ok(register)
+    }
+
+    /// Puts the text in a register after the cursor (or before it), `count` times (like vim's `p`
+    /// and `P`).
+    ///
+    /// Linewise text goes on new lines below (or above) the cursor's line, and blockwise text goes
+    /// into the lines from the cursor's line down, padding lines that are too short with spaces.
+    /// The cursor is left at the start of the text, except for text within a line, where it's left
+    /// at the end.
+    pub fn put(&mut self, register: &Register, before: bool, count: usize, term: &mut Terminal) -> Result<()> {
+        let doc = match self.doc.as_ref() {
+            Some(doc) if !self.read_only => doc,
+            _ => return Ok(()),
+        };
+        let count = count.max(1);
+        let DocPosition{ x, y } = self.pos_in_doc;
+        let line_start = doc.line_start(y).expect("cursor should be in the document");
+        let graphemes = self.line_graphemes(y);
+        // the column the text goes in
+        let column = if before || graphemes == 0 { x } else { min(x + 1, graphemes) };
+        let lines: Vec<String> = register.lines.iter().map(|line| line.concat()).collect();
+        match register.kind {
+            RegisterKind::Char => {
+                let text = lines.join("\n").repeat(count);
+                let len = count * (register.lines.iter().map(Vec::len).sum::<usize>() + register.lines.len() - 1);
+                let offset = line_start + column;
+                self.change(Delta::Insert{ offset, text });
+                let cursor = if register.lines.len() > 1 { offset } else { offset + len.saturating_sub(1) };
+                let pos = self.position_of(cursor).expect("text should be in the document");
+                self.restore_cursor(pos, term)
+            },
+            RegisterKind::Line => {
+                let text = vec![lines.join("\n"); count].join("\n");
+                // the last line has no newline after it, so the text goes after the newline before it
+                let (offset, text, line) = if before {
+                    (line_start, text + "\n", y)
+                } else {
+                    (line_start + graphemes, String::from("\n") + &text, y + 1)
+                };
+                self.change(Delta::Insert{ offset, text });
+                let x = register.lines.first().map_or(0, |l| l.iter().take_while(|g| *g == " " || *g == "\t").count());
+                self.restore_cursor(DocPosition{ x, y: line }, term)
+            },
+            RegisterKind::Block => {
+                let width = register.width();
+                self.begin_undo_group();
+                for (i, (line, graphemes)) in lines.iter().zip(register.lines.iter()).enumerate() {
+                    let doc = self.doc.as_ref().unwrap();
+                    if y + i == doc.num_lines() {
+                        self.change(Delta::Insert{ offset: doc.graphemes(), text: String::from("\n") });
+                    }
+                    let len = self.line_graphemes(y + i);
+                    let start = self.doc.as_ref().unwrap().line_start(y + i).expect("line should be in the document");
+                    // each copy is padded to the width of the block, unless nothing comes after it
+                    let padding = " ".repeat(width - graphemes.len());
+                    let mut text = " ".repeat(column.saturating_sub(len));
+                    for n in 0..count {
+                        text.push_str(line);
+                        if n + 1 < count || column < len {
+                            text.push_str(&padding);
+                        }
+                    }
+                    self.change(Delta::Insert{ offset: start + min(column, len), text });
+                }
+                self.end_undo_group();
+                self.restore_cursor(DocPosition{ x: column, y }, term)
+            },
+        }
     }
 
     /// Insert a character at the current position.
     /// 
     /// The character must be a graphic ASCII character or a space. Sorry Unicode.
+    /// The cursor is placed after the inserted character, which can be past the end of the line.
     /// Returns Ok(true) if the character was inserted, and Ok(false) if it wasn't, but there were
     /// no errors.
     pub fn insert(&mut self, c: char, term: &mut Terminal) -> Result<bool> {
-        if self.doc.is_none() || !(c.is_ascii_graphic() || c == ' ') { return Ok(false); }
-        let line = self.doc.as_mut().unwrap().line_mut(self.pos_in_doc.y).unwrap();
-        if let Some((byte_idx, _)) = line.text.grapheme_indices(true).nth(self.pos_in_doc.x) {
-            line.text.insert(byte_idx, c);
-            line.graphemes += 1;
-            self.update_render(term)?;
-            Ok(true)
-        } else {
-            Ok(false)
-        }
+        if self.read_only { return Ok(false); }
+        let (delta, pos) = match self.doc.as_ref().and_then(|doc| typed_char(doc, self.pos_in_doc, c)) {
+            Some(typed) => typed,
+            None => return Ok(false),
+        };
+        let old_lines = self.line_properties(self.pos_in_doc.y).lines;
+        self.change(delta);
+        self.pos_in_doc = pos;
+        self.target_x = pos.x;
+        self.update_render(old_lines, term)?;
+        self.q_move(term)?;
+        term.flush()?;
+        Ok(true)
     }
 
     // NOTE: when you implement splitting, make sure that all split windows have
@@ -305,13 +928,222 @@ impl Window {
         Position{ x: x + self.raw_window_pos.x, y: y + self.raw_window_pos.y }
     }
 
+    // number of graphemes in a line of the document; there must be a document
+    fn line_graphemes(&self, line: usize) -> usize {
+        self.doc.as_ref().unwrap().line_graphemes(line).expect("line should be in the document")
+    }
+
+    // grapheme offset of the cursor in the document; there must be a document
+    fn offset(&self) -> usize {
+        let start = self.doc.as_ref().unwrap().line_start(self.pos_in_doc.y).expect("cursor should be in the document");
+        start + self.pos_in_doc.x
+    }
+
+    /// Starts selecting text from the cursor.
+    ///
+    /// Returns `Ok(false)` if there is nothing to select (i.e. there is no document).
+    pub fn start_selection(&mut self, kind: SelectionKind, term: &mut Terminal) -> Result<bool> {
+        if self.doc.is_none() { return Ok(false); }
+        self.selection = Some(Selection{ anchor: self.pos_in_doc, kind });
+        self.lines_changed(term)?;
+        Ok(true)
+    }
+
+    /// Selects the last selection in this window again, with the cursor where it was (like vim's
+    /// `gv`).
+    ///
+    /// Returns the kind of the selection, or `None` if nothing has been selected in this window.
+    pub fn reselect(&mut self, term: &mut Terminal) -> Result<Option<SelectionKind>> {
+        let (selection, cursor) = match (self.doc.as_ref(), self.last_selection) {
+            (Some(_), Some(last)) => last,
+            _ => return Ok(None),
+        };
+        // the document may have changed since then
+        let clamp = |w: &Self, pos: DocPosition| {
+            let y = min(pos.y, w.doc.as_ref().unwrap().num_lines() - 1);
+            DocPosition{ x: min(pos.x, w.line_graphemes(y)), y }
+        };
+        self.selection = Some(Selection{ anchor: clamp(self, selection.anchor), kind: selection.kind });
+        self.pos_in_doc = clamp(self, cursor);
+        self.target_x = self.pos_in_doc.x;
+        self.lines_changed(term)?;
+        Ok(Some(selection.kind))
+    }
+
+    /// Returns the current selection, if there is one.
+    pub fn selection(&self) -> Option<Selection> {
+        self.selection
+    }
+
+    /// Changes what is selected between the anchor and the cursor.
+    pub fn set_selection_kind(&mut self, kind: SelectionKind, term: &mut Terminal) -> Result<()> {
+        if let Some(selection) = self.selection.as_mut() {
+            selection.kind = kind;
+            self.lines_changed(term)?;
+        }
+        Ok(())
+    }
+
+    /// Swaps the anchor of the selection and the cursor (like vim's `o` in visual mode).
+    pub fn swap_selection_ends(&mut self, term: &mut Terminal) -> Result<()> {
+        if let Some(selection) = self.selection.as_mut() {
+            std::mem::swap(&mut selection.anchor, &mut self.pos_in_doc);
+            self.target_x = self.pos_in_doc.x;
+            self.lines_changed(term)?;
+        }
+        Ok(())
+    }
+
+    /// Draws the selection again, after the cursor has moved.
+    pub fn update_selection(&mut self, term: &mut Terminal) -> Result<()> {
+        if self.selection.is_some() {
+            self.lines_changed(term)?;
+        }
+        Ok(())
+    }
+
+    /// Stops selecting text, keeping the selection so it can be selected again with
+    /// [`Window::reselect`].
+    pub fn end_selection(&mut self, term: &mut Terminal) -> Result<()> {
+        if let Some(selection) = self.selection.take() {
+            self.last_selection = Some((selection, self.pos_in_doc));
+            self.lines_changed(term)?;
+        }
+        Ok(())
+    }
+
+    /// Applies an operator to the selection, and stops selecting text.
+    ///
+    /// A block selection is operated on one line at a time (changing it deletes the block, and
+    /// what is typed after it only goes into the first line), except for indenting, which acts on
+    /// all of its lines. Returns the text that was yanked, deleted or changed, for a register.
+    pub fn operate_on_selection(&mut self, op: Operator, term: &mut Terminal) -> Result<Option<Register>> {
+        let (selection, doc) = match (self.selection, self.doc.as_ref()) {
+            (Some(selection), Some(doc)) => (selection, doc),
+            _ => return Ok(None),
+        };
+        let (ranges, block) = selection_operands(doc, selection, self.pos_in_doc, op);
+        let start = min_max(selection.anchor, self.pos_in_doc).0;
+        self.end_selection(term)?;
+        match ranges.as_slice() {
+            [] => self.restore_cursor(start, term).map(|_| None),
+            [range] if !block => self.operate(op, range, term),
+            _ => {
+                let doc = self.doc.as_ref().unwrap();
+                let texts: Vec<String> = ranges.iter().rev().map(|r| doc.text(r.range.clone()).expect("range should be in the document")).collect();
+                self.begin_undo_group();
+                for range in ranges.iter() {
+                    self.operate(op, range, term)?;
+                }
+                // the undo group of a change ends when typing ends
+                if op != Operator::Change {
+                    self.end_undo_group();
+                }
+                let yanked = matches!(op, Operator::Delete | Operator::Change | Operator::Yank);
+                Ok(yanked.then(|| Register::block(texts.iter().map(String::as_str))))
+            },
+        }
+    }
+
+    // prints a piece of a line that starts at column `column` of `line`, highlighting the
+    // part of it that is selected
+    fn q_print_text(&self, text: &str, line: usize, column: usize, term: &mut Terminal) -> Result<()> {
+        let selected = match self.selection.and_then(|s| selected_columns(self.doc.as_ref().unwrap(), s, self.pos_in_doc, line)) {
+            Some(columns) => columns,
+            None => {
+                term.q(Print(text))?;
+                return Ok(());
+            },
+        };
+        // an empty line is shown as one selected cell
+        if text.is_empty() && column == 0 && selected.contains(&0) {
+            term.q(Print(" ".reverse()))?;
+            return Ok(());
+        }
+        let byte = |col: usize| text.grapheme_indices(true).nth(col.saturating_sub(column)).map_or(text.len(), |(i, _)| i);
+        let (left, right) = (byte(max(selected.start, column)), byte(max(selected.end, column)));
+        term.q(Print(&text[..left]))?.q(Print(text[left..right].reverse()))?.q(Print(&text[right..]))?;
+        Ok(())
+    }
+
+    // the last line of the document that starts on the screen; there must be a document
+    fn last_line_on_screen(&self) -> usize {
+        let num_lines = self.doc.as_ref().unwrap().num_lines();
+        let mut window_lines = 0;
+        let mut line = self.first_line;
+        while line + 1 < num_lines {
+            window_lines += self.line_properties(line).lines;
+            if window_lines >= self.raw_window_size.height as usize { break; }
+            line += 1;
+        }
+        line
+    }
+
+    // position of a grapheme offset in the document
+    fn position_of(&self, offset: usize) -> Option<DocPosition> {
+        let doc = self.doc.as_ref()?;
+        let y = doc.line_of(offset)?;
+        Some(DocPosition{ x: offset - doc.line_start(y)?, y })
+    }
+
+    // makes a change to the document and records it, so it can be undone; there must be a document
+    fn change(&mut self, delta: Delta) {
+        let cursor = self.pos_in_doc;
+        if let Some(history) = self.history_mut() {
+            history.record(delta.clone(), cursor);
+        }
+        self.apply(delta);
+    }
+
+    fn history_mut(&mut self) -> Option<&mut UndoHistory> {
+        self.diff.as_mut().map(|d| d.history_mut())
+    }
+
+    // undoes the current undo step without rendering, returning where the cursor should go
+    fn step_back(&mut self) -> Option<DocPosition> {
+        let step = self.history_mut()?.undo()?.clone();
+        step.deltas().iter().rev().for_each(|delta| self.apply(delta.inverse()));
+        Some(step.cursor())
+    }
+
+    // redoes the next undo step without rendering, returning where the cursor should go
+    fn step_forward(&mut self) -> Option<DocPosition> {
+        let step = self.history_mut()?.redo()?.clone();
+        step.deltas().iter().for_each(|delta| self.apply(delta.clone()));
+        Some(step.cursor())
+    }
+
+    // makes a change to the document and records it in the differential; there must be a document
+    fn apply(&mut self, delta: Delta) {
+        delta.apply(self.doc.as_mut().unwrap()).expect("change should be in the document");
+        if let Some(diff) = self.diff.as_mut() {
+            diff.push(delta);
+            self.backup_pending = true;
+        }
+    }
+
+    // moves the cursor to `pos` (or as close as possible) after undoing or redoing
+    fn restore_cursor(&mut self, pos: DocPosition, term: &mut Terminal) -> Result<()> {
+        let y = min(pos.y, self.doc.as_ref().unwrap().num_lines() - 1);
+        let x = min(pos.x, self.line_graphemes(y));
+        self.pos_in_doc = DocPosition{ x, y };
+        self.target_x = x;
+        self.lines_changed(term)
+    }
+
+    // deletes the grapheme at `offset`; there must be a document
+    fn delete_grapheme(&mut self, offset: usize) {
+        let text = self.doc.as_ref().unwrap().text(offset..offset + 1).expect("offset should be in the document");
+        self.change(Delta::Delete{ offset, text });
+    }
+
     fn to_window_text(&self) -> Option<Position> {
         if self.pos_in_doc.y < self.first_line { return None; }
         let lines_from_line = div_ceil(self.pos_in_doc.x, self.text_width);
         let x = (self.pos_in_doc.x % self.text_width as usize) as u16;
         let mut y = 0;
         for line in self.first_line..self.pos_in_doc.y {
-            y += self.line_properties[line].lines
+            y += self.line_properties(line).lines
         }
         y += lines_from_line - 1;
         if y >= self.raw_window_size.height.into() || x >= self.text_width { None }
@@ -330,11 +1162,13 @@ impl Window {
         // TODO: revamp for multicell characters
         let rem = (length % text_width as usize) as u16;
         let lines = div_ceil(length, text_width);
-        WindowLineProperties{ lines, graphemes: rem }
+        WindowLineProperties{ lines, cells: rem as usize }
     }
 
-    fn setup_line_properties(doc: &Document, text_width: u16) -> Vec<WindowLineProperties> {
-        doc.into_iter().map(|l| Self::calc_line_properties(l.length, text_width)).collect::<Vec<WindowLineProperties>>()
+    // computed as needed (rather than for every line up front), since finding a line in the
+    // document is cheap; there must be a document
+    fn line_properties(&self, line: usize) -> WindowLineProperties {
+        Self::calc_line_properties(self.line_graphemes(line), self.text_width)
     }
 
     fn make_clear_lines(size: Size, text_start: u16, text_width: u16) -> HashMap<ClearType, String> {
@@ -350,7 +1184,7 @@ impl Window {
         let mut doc_line = self.first_line;
         let line_count = self.doc.as_ref().unwrap().num_lines();
         while window_line < line && doc_line < line_count {
-            if let Some(l) = self.line_properties[doc_line].lines_u16() {
+            if let Some(l) = self.line_properties(doc_line).lines_u16() {
                 window_line += l;
                 doc_line += 1;
             } else {
@@ -378,7 +1212,7 @@ impl Window {
                                                 .chain(repeat(' ')).take(self.text_start as usize).collect::<String>()
                                                 .chars().rev().collect::<String>(),
                     LineNumbers::Relative => {
-                        if self.pos_in_doc.y == doc_line as usize {
+                        if self.pos_in_doc.y == doc_line {
                             (doc_line + 1).to_string().chars().chain(repeat(' ')).take(self.text_start as usize).collect::<String>()
                         } else {
                             once(' ').chain(abs_diff(self.pos_in_doc.y, doc_line).to_string().chars().rev())
@@ -391,15 +1225,13 @@ impl Window {
         }
     }
 
-    // checks if line `self.pos_in_doc.y` has changed line wrapping
+    // checks if line `self.pos_in_doc.y` has changed line wrapping (`old_lines` is the number of
+    // window lines it took up before it was changed)
     // if it has, rerenders the whole screen
     // otherwise, rerenders the line
-    // updates the line properties
-    fn update_render(&mut self, term: &mut Terminal) -> Result<()> {
+    fn update_render(&mut self, old_lines: usize, term: &mut Terminal) -> Result<()> {
         let line = self.doc.as_ref().unwrap().line(self.pos_in_doc.y).unwrap();
-        let old_lines = self.line_properties[self.pos_in_doc.y].lines;
-        self.line_properties[self.pos_in_doc.y] = Self::calc_line_properties(line.length, self.text_width);
-        let new_lines = self.line_properties[self.pos_in_doc.y].lines;
+        let new_lines = Self::calc_line_properties(line.graphemes, self.text_width).lines;
         if old_lines != new_lines {
             self.render(term)?;
         } else {
@@ -413,7 +1245,6 @@ impl Window {
                 let line = match line_type {
                     LineType::Content(a) => a,
                     LineType::Continued(a) => a,
-                    _ => panic!("found line type that isn't content or continued (this should never happen)")
                 };
                 let line_number = line_number + i;
                 self.q_clear(ClearType::Text, line_number, term)?;
@@ -426,6 +1257,27 @@ impl Window {
         Ok(())
     }
 
+    // called after lines have been added to or removed from the document
+    // keeps the cursor on the screen, makes room for wider line numbers, and rerenders
+    fn lines_changed(&mut self, term: &mut Terminal) -> Result<()> {
+        let num_lines = self.doc.as_ref().unwrap().num_lines();
+        let (text_start, text_width) = Self::compute_text_attrs(&self.opt, &self.raw_window_size, num_lines);
+        if (text_start, text_width) != (self.text_start, self.text_width) {
+            self.text_start = text_start;
+            self.text_width = text_width;
+            self.clear_lines = Self::make_clear_lines(self.raw_window_size, text_start, text_width);
+        }
+        if self.pos_in_doc.y < self.first_line {
+            self.first_line = self.pos_in_doc.y;
+        }
+        while self.to_window_text().is_none() && self.first_line < self.pos_in_doc.y {
+            self.first_line += 1;
+        }
+        self.render(term)?;
+        self.q_move(term)?;
+        term.flush()
+    }
+
     fn update_line_numbers(&self, term: &mut Terminal) -> Result<()> {
         if let LineNumbers::Off = self.opt.line_numbering { return Ok(()); }
         term.q(Hide)?.save_cursor();
@@ -436,7 +1288,7 @@ impl Window {
         while window_line < self.raw_window_size.height && doc_line < line_count {
             let Position{ x, y } = self.raw_to_term(0, window_line);
             term.cursor_to(x, y).q_move_cursor()?.q(Print(self.line_number(window_line)))?;
-            let end = min(self.line_properties[doc_line].lines_u16().unwrap_or(u16::MAX), self.raw_window_size.height - window_line); 
+            let end = min(self.line_properties(doc_line).lines_u16().unwrap_or(u16::MAX), self.raw_window_size.height - window_line); 
             window_line += end;
             doc_line += 1;
         }
@@ -498,7 +1350,7 @@ impl Window {
                     pieces.push(&l[indices[i]..indices[i + 1]]);
                 }
                 pieces.push(&l[*indices.last().unwrap()..]);
-                Box::new(once(LineType::Content(first)).chain(pieces.into_iter().map(|p| LineType::Continued(p))))
+                Box::new(once(LineType::Content(first)).chain(pieces.into_iter().map(LineType::Continued)))
             } else {
                 Box::new(once(LineType::Content(l)))
             };
@@ -510,20 +1362,32 @@ impl Window {
         if let Some(doc) = self.doc.as_ref() {
             term.q(Hide)?.save_cursor();
             self.q_clear(ClearType::All, 0, term)?;
-            self.split_lines(doc.iter_from(self.first_line).unwrap().map(|l| l.text.as_str()))
-               .chain(repeat(LineType::Tilde))
-               .enumerate()
-               .take(self.raw_window_size.height.into())
-               .try_for_each(|(terminal_line, lt)| { 
+            let height: usize = self.raw_window_size.height.into();
+            let lines = doc.iter_from(self.first_line).unwrap().take(height).collect::<Vec<Line>>();
+            let mut terminal_line = 0;
+            for (line, doc_line) in lines.iter().zip(self.first_line..) {
+                // the column of the document line that each piece of it starts at
+                let mut column = 0;
+                for lt in self.split_lines(once(line.text.as_str())).take(height.saturating_sub(terminal_line)) {
                     term.cursor_to(0, terminal_line as u16).q_move_cursor()?;
-                    if let LineType::Content(text) = lt {
-                        term.q(Print(self.line_number(terminal_line as u16)))?.q(Print(text))
-                    } else if let LineType::Continued(text) = lt {
-                        term.q(Print(" ".repeat(self.text_start as usize)))?.q(Print(text)) // TODO: check logic here
-                    } else {
-                        term.q(Print("~".blue()))
-                    }.map(|_| ())
-                })?;
+                    let text = match lt {
+                        LineType::Content(text) => {
+                            term.q(Print(self.line_number(terminal_line as u16)))?;
+                            text
+                        },
+                        LineType::Continued(text) => {
+                            term.q(Print(" ".repeat(self.text_start as usize)))?; // TODO: check logic here
+                            text
+                        },
+                    };
+                    self.q_print_text(text, doc_line, column, term)?;
+                    column += text.graphemes(true).count();
+                    terminal_line += 1;
+                }
+            }
+            for terminal_line in terminal_line..height {
+                term.cursor_to(0, terminal_line as u16).q_move_cursor()?.q(Print("~".blue()))?;
+            }
             term.q(Show)?.restore_cursor();
             term.q_move_cursor()?.flush()
         } else {
@@ -556,9 +1420,7 @@ impl Window {
             return Ok(())
         }
         let message_len = WELCOME_SIZE as u16;
-        let message_begin_line = if self.raw_window_size.height / 2 < message_len / 2 { 0 } else {
-            self.raw_window_size.height / 2 - message_len / 2
-        };
+        let message_begin_line = (self.raw_window_size.height / 2).saturating_sub(message_len / 2);
         let mut message_line: u16 = 0;
         term.q(Hide)?.save_cursor();
         self.q_clear(ClearType::All, 0, term)?;
@@ -577,22 +1439,195 @@ impl Window {
     }
 }
 
+// writes `doc` to a temporary file next to `path`, then renames it to `path`, so that `path` is
+// never left half-written; returns the number of bytes written
+//
+// symlinks are followed, so that the file they point to is replaced rather than the link. A file
+// with other hard links, or whose owner can't be kept, is written in place instead, since replacing
+// it would split it from its other links or give it a new owner
+fn write_atomically(doc: &Document, path: &Path) -> Result<u64> {
+    let (path, metadata) = match path.canonicalize() {
+        Ok(path) => {
+            let metadata = path.metadata()?;
+            (path, metadata)
+        },
+        // a new file (or a symlink to one, which has to be written through)
+        Err(e) if e.kind() == ErrorKind::NotFound => return write_in_place(doc, path),
+        Err(e) => return Err(e),
+    };
+    if has_other_links(&metadata) {
+        return write_in_place(doc, &path);
+    }
+    let (temp_path, file) = create_temp_file(&path)?;
+    let result: Result<Option<u64>> = (|| {
+        if !keep_owner(&file, &metadata) {
+            return Ok(None);
+        }
+        // after changing the owner, which can clear setuid and setgid bits
+        file.set_permissions(metadata.permissions())?;
+        doc.write_to(BufWriter::new(&file))?;
+        file.sync_all()?;
+        let bytes = file.metadata()?.len();
+        rename(&temp_path, &path)?;
+        Ok(Some(bytes))
+    })();
+    if !matches!(result, Ok(Some(_))) {
+        let _ = remove_file(&temp_path);
+    }
+    let bytes = match result? {
+        Some(bytes) => bytes,
+        None => return write_in_place(doc, &path),
+    };
+    // make sure the rename itself is on disk
+    if let Some(dir) = path.parent() {
+        let _ = File::open(dir).and_then(|d| d.sync_all());
+    }
+    Ok(bytes)
+}
+
+fn write_in_place(doc: &Document, path: &Path) -> Result<u64> {
+    let file = OpenOptions::new().write(true).create(true).truncate(true).open(path)?;
+    doc.write_to(BufWriter::new(&file))?;
+    file.sync_all()?;
+    Ok(file.metadata()?.len())
+}
+
+// creates a temporary file next to `path`, with a name that no other write (from this fim or
+// another one) is using
+fn create_temp_file(path: &Path) -> Result<(PathBuf, File)> {
+    static WRITES: AtomicUsize = AtomicUsize::new(0);
+    let name = path.file_name().ok_or_else(|| Error::other(format!("{} has no file name", path.display())))?;
+    loop {
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(name);
+        temp_name.push(format!(".{}-{}.fim-write", std::process::id(), WRITES.fetch_add(1, Ordering::Relaxed)));
+        let temp_path = path.with_file_name(temp_name);
+        match OpenOptions::new().write(true).create_new(true).open(&temp_path) {
+            // left behind by a fim that had the same pid
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            result => return result.map(|file| (temp_path, file)),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn has_other_links(metadata: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink() > 1
+}
+
+#[cfg(not(unix))]
+fn has_other_links(_metadata: &Metadata) -> bool {
+    false
+}
+
+// gives `file` the owner and group in `metadata`, returning `false` if that isn't allowed
+#[cfg(unix)]
+fn keep_owner(file: &File, metadata: &Metadata) -> bool {
+    use std::os::unix::fs::{fchown, MetadataExt};
+    let owned = file.metadata().is_ok_and(|m| m.uid() == metadata.uid() && m.gid() == metadata.gid());
+    owned || fchown(file, Some(metadata.uid()), Some(metadata.gid())).is_ok()
+}
+
+#[cfg(not(unix))]
+fn keep_owner(_file: &File, _metadata: &Metadata) -> bool {
+    true
+}
+
+// the deletion of the text in `range` for `Operator::Change` (if there is any), and where the cursor
+// goes to type its replacement; linewise ranges don't have the last newline, so the lines are
+// emptied rather than deleted, leaving a line to type in
+fn changed_range(doc: &Document, range: &Range<usize>) -> (Option<Delta>, DocPosition) {
+    let text = doc.text(range.clone()).expect("range should be in the document");
+    let y = doc.line_of(range.start).expect("range should be in the document");
+    let pos = DocPosition{ x: range.start - doc.line_start(y).unwrap(), y };
+    let delta = (!range.is_empty()).then_some(Delta::Delete{ offset: range.start, text });
+    (delta, pos)
+}
+
+// the insertion of `c` typed at `pos`, and where the cursor goes after it (which can be past the
+// end of the line), if `c` can be typed there (see `Window::insert`)
+fn typed_char(doc: &Document, pos: DocPosition, c: char) -> Option<(Delta, DocPosition)> {
+    if !(c.is_ascii_graphic() || c == ' ') { return None; }
+    if pos.x > doc.line_graphemes(pos.y)? { return None; }
+    let offset = doc.line_start(pos.y)? + pos.x;
+    Some((Delta::Insert{ offset, text: c.to_string() }, DocPosition{ x: pos.x + 1, y: pos.y }))
+}
+
 fn div_ceil(quotient: usize, divisor: u16) -> usize {
     if quotient == 0 { 1 } else { (quotient as f64 / divisor as f64).ceil() as usize }
 }
 
 fn abs_diff(x: usize, y: usize) -> usize {
-    if x > y { x - y } else { y - x }
+    x.abs_diff(y)
 }
 
-fn saturating_sub(x: u16, y: u16) -> u16 {
-    if y >= x {
-        0
-    } else {
-        x - y
+// the ranges of text in a selection from `selection.anchor` to `cursor` (one per line for a block)
+fn selection_ranges(doc: &Document, selection: Selection, cursor: DocPosition) -> Vec<TextRange> {
+    let (start, end) = min_max(selection.anchor, cursor);
+    let line_start = |line| doc.line_start(line).expect("line should be in the document");
+    let line_graphemes = |line| doc.line_graphemes(line).expect("line should be in the document");
+    match selection.kind {
+        SelectionKind::Char => {
+            let range = line_start(start.y) + start.x..min(line_start(end.y) + end.x + 1, doc.graphemes());
+            vec![TextRange{ cursor: range.start, range, linewise: false }]
+        },
+        SelectionKind::Line => {
+            let range = line_start(start.y)..line_start(end.y) + line_graphemes(end.y);
+            vec![TextRange{ cursor: range.start, range, linewise: true }]
+        },
+        SelectionKind::Block => (start.y..=end.y).map(|line| {
+            let (left, right) = (min(start.x, end.x), max(start.x, end.x));
+            // lines that end before the block have an empty range at their end
+            let graphemes = line_graphemes(line);
+            let range = line_start(line) + min(left, graphemes)..line_start(line) + min(right + 1, graphemes);
+            TextRange{ cursor: range.start, range, linewise: false }
+        }).collect(),
     }
 }
 
+// the ranges an operator acts on for a selection, in the order it acts on them, and whether they
+// are the lines of a block. A block is operated on one line at a time, from the bottom up so that
+// the ranges above are still where they were, except for indenting, which acts on all of its lines
+fn selection_operands(doc: &Document, selection: Selection, cursor: DocPosition, op: Operator) -> (Vec<TextRange>, bool) {
+    let ranges = selection_ranges(doc, selection, cursor);
+    if selection.kind != SelectionKind::Block {
+        return (ranges, false);
+    }
+    match ranges.as_slice() {
+        [first, .., last] if matches!(op, Operator::Indent | Operator::Outdent) => {
+            (vec![TextRange{ range: first.range.start..last.range.end, linewise: true, cursor: first.range.start }], false)
+        },
+        _ => (ranges.into_iter().rev().collect(), true),
+    }
+}
+
+// the columns of `line` that are selected (which can include the newline, one past the end of the
+// line)
+fn selected_columns(doc: &Document, selection: Selection, cursor: DocPosition, line: usize) -> Option<Range<usize>> {
+    let (start, end) = min_max(selection.anchor, cursor);
+    if line < start.y || line > end.y { return None; }
+    let line_end = doc.line_graphemes(line).expect("line should be in the document") + 1;
+    Some(match selection.kind {
+        SelectionKind::Char => {
+            let left = if line == start.y { start.x } else { 0 };
+            let right = if line == end.y { end.x + 1 } else { line_end };
+            left..right
+        },
+        SelectionKind::Line => 0..line_end,
+        SelectionKind::Block => min(start.x, end.x)..max(start.x, end.x) + 1,
+    })
+}
+
+// the earlier and the later of two positions
+fn min_max(a: DocPosition, b: DocPosition) -> (DocPosition, DocPosition) {
+    if (a.y, a.x) <= (b.y, b.x) { (a, b) } else { (b, a) }
+}
+
+fn saturating_sub(x: u16, y: u16) -> u16 {
+    x.saturating_sub(y)
+}
+
 fn log10(mut x: usize) -> u16 {
     let mut log = 0u16;
     while x > 0 {
@@ -601,3 +1636,184 @@ fn log10(mut x: usize) -> u16 {
     }
     log
 }
+
+#[cfg(unix)]
+#[test]
+fn test_write_atomically() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = std::env::temp_dir().join(format!("fim-test-write-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("file.txt");
+    std::fs::write(&file, "first\nsecond").unwrap();
+    std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o640)).unwrap();
+
+    let mut doc = Document::new(&file).unwrap();
+    doc.insert(5, " line").unwrap();
+    assert_eq!(write_atomically(&doc, &file).unwrap(), 17);
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "first line\nsecond");
+    assert_eq!(file.metadata().unwrap().permissions().mode() & 0o777, 0o640);
+    // a temporary file left behind by another write doesn't get in the way, and isn't removed
+    let leftovers: Vec<_> = (0..16).map(|n| dir.join(format!(".file.txt.{}-{}.fim-write", std::process::id(), n))).collect();
+    leftovers.iter().for_each(|leftover| std::fs::write(leftover, "leftover").unwrap());
+    assert_eq!(write_atomically(&doc, &file).unwrap(), 17);
+    assert!(leftovers.iter().all(|leftover| std::fs::read_to_string(leftover).unwrap() == "leftover"));
+    leftovers.iter().for_each(|leftover| std::fs::remove_file(leftover).unwrap());
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    let other = dir.join("other.txt");
+    assert_eq!(write_atomically(&doc, &other).unwrap(), 17);
+    assert_eq!(std::fs::read_to_string(&other).unwrap(), "first line\nsecond");
+
+    // the file a symlink points to is written, and the symlink is kept
+    let link = dir.join("link.txt");
+    std::os::unix::fs::symlink(&other, &link).unwrap();
+    doc.insert(0, "the ").unwrap();
+    write_atomically(&doc, &link).unwrap();
+    assert!(link.symlink_metadata().unwrap().file_type().is_symlink());
+    assert_eq!(std::fs::read_to_string(&other).unwrap(), "the first line\nsecond");
+
+    // so are hard links
+    let hard_link = dir.join("hard_link.txt");
+    std::fs::hard_link(&other, &hard_link).unwrap();
+    doc.insert(0, "> ").unwrap();
+    write_atomically(&doc, &hard_link).unwrap();
+    assert_eq!(std::fs::read_to_string(&other).unwrap(), "> the first line\nsecond");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_selection_ranges() {
+    let doc = Document::from("abcd\nef\nghijk");
+    let at = |x, y| DocPosition{ x, y };
+    let select = |kind, anchor, cursor| selection_ranges(&doc, Selection{ anchor, kind }, cursor);
+    let chars = |range: Range<usize>, linewise| TextRange{ cursor: range.start, range, linewise };
+    // the cursor can be before the anchor
+    assert_eq!(select(SelectionKind::Char, at(2, 0), at(1, 2)), vec![chars(2..10, false)]);
+    assert_eq!(select(SelectionKind::Char, at(1, 2), at(2, 0)), vec![chars(2..10, false)]);
+    assert_eq!(select(SelectionKind::Char, at(3, 2), at(4, 2)), vec![chars(11..13, false)]);
+    assert_eq!(select(SelectionKind::Line, at(3, 1), at(0, 0)), vec![chars(0..7, true)]);
+    // short lines have less of the block in them
+    assert_eq!(select(SelectionKind::Block, at(3, 2), at(1, 0)), vec![chars(1..4, false), chars(6..7, false), chars(9..12, false)]);
+    assert_eq!(select(SelectionKind::Block, at(3, 0), at(3, 1)), vec![chars(3..4, false), chars(7..7, false)]);
+}
+
+#[test]
+fn test_selected_columns() {
+    let doc = Document::from("abcd\nef\nghijk");
+    let at = |x, y| DocPosition{ x, y };
+    let columns = |kind, line| selected_columns(&doc, Selection{ anchor: at(2, 0), kind }, at(1, 2), line);
+    // including the newline of the lines before the last one
+    assert_eq!(columns(SelectionKind::Char, 0), Some(2..5));
+    assert_eq!(columns(SelectionKind::Char, 1), Some(0..3));
+    assert_eq!(columns(SelectionKind::Char, 2), Some(0..2));
+    assert_eq!(columns(SelectionKind::Line, 1), Some(0..3));
+    assert_eq!(columns(SelectionKind::Block, 1), Some(1..3));
+    let columns = |line| selected_columns(&doc, Selection{ anchor: at(1, 1), kind: SelectionKind::Char }, at(1, 2), line);
+    assert_eq!(columns(0), None);
+    assert_eq!(columns(2), Some(0..2));
+}
+
+#[test]
+fn test_operate_on_selection() {
+    let at = |x, y| DocPosition{ x, y };
+    // applies the operator the way `Window::operate_on_selection` does, deleting each range
+    let delete = |kind, anchor, cursor, op| {
+        let mut doc = Document::from("abcd\nef\nghijk");
+        let (ranges, block) = selection_operands(&doc, Selection{ anchor, kind }, cursor, op);
+        for range in ranges.iter() {
+            doc.delete(range.range.clone()).unwrap();
+        }
+        (doc.text(0..doc.graphemes()).unwrap(), ranges.len(), block)
+    };
+    assert_eq!(delete(SelectionKind::Char, at(2, 0), at(1, 2), Operator::Delete), (String::from("abijk"), 1, false));
+    assert_eq!(delete(SelectionKind::Line, at(0, 1), at(0, 1), Operator::Delete), (String::from("abcd\n\nghijk"), 1, false));
+    // a block is operated on a line at a time, from the bottom up
+    assert_eq!(delete(SelectionKind::Block, at(1, 0), at(3, 2), Operator::Delete), (String::from("a\ne\ngk"), 3, true));
+    assert_eq!(delete(SelectionKind::Block, at(1, 0), at(1, 0), Operator::Yank), (String::from("acd\nef\nghijk"), 1, true));
+    // except for indenting, which acts on all of its lines at once
+    let doc = Document::from("abcd\nef\nghijk");
+    let (ranges, block) = selection_operands(&doc, Selection{ anchor: at(1, 0), kind: SelectionKind::Block }, at(3, 2), Operator::Indent);
+    assert_eq!(ranges, vec![TextRange{ range: 1..12, linewise: true, cursor: 1 }]);
+    assert!(!block);
+}
+
+#[test]
+fn test_typed_char() {
+    let at = |x, y| DocPosition{ x, y };
+    // types `text` at `pos` the way `Window::insert` does, returning the line and the cursor
+    let type_text = |content, pos, text: &str| {
+        let mut doc = Document::from(content);
+        let cursor = text.chars().fold(pos, |pos, c| {
+            let (delta, pos) = typed_char(&doc, pos, c).unwrap();
+            delta.apply(&mut doc).unwrap();
+            pos
+        });
+        (doc.line(cursor.y).unwrap().text, cursor)
+    };
+    // into an empty document, or an empty line
+    assert_eq!(type_text("", at(0, 0), "hello"), (String::from("hello"), at(5, 0)));
+    assert_eq!(type_text("ab\n\ncd", at(0, 1), "hello"), (String::from("hello"), at(5, 1)));
+    // after the last grapheme of a line
+    assert_eq!(type_text("ab\ncd", at(2, 0), "xyz"), (String::from("abxyz"), at(5, 0)));
+    assert_eq!(type_text("ab\ncd", at(1, 1), " e"), (String::from("c ed"), at(3, 1)));
+    let doc = Document::from("ab");
+    assert!(typed_char(&doc, at(3, 0), 'x').is_none());
+    assert!(typed_char(&doc, at(0, 1), 'x').is_none());
+    assert!(typed_char(&doc, at(0, 0), '\n').is_none());
+}
+
+// changes the range of the motion `kind` from `pos`, and types `text` in its place, the way
+// `Window::operate` and `Window::insert` do, returning where the cursor ends up
+#[cfg(test)]
+fn change_and_type(doc: &mut Document, pos: DocPosition, kind: MotionKind, text: &str) -> DocPosition {
+    let range = match kind {
+        MotionKind::WordForward => motion::change_word(doc, pos, false, None),
+        _ => motion::motion(doc, pos, kind, None, None),
+    }.unwrap();
+    let (delta, pos) = changed_range(doc, &range.range);
+    if let Some(delta) = delta {
+        delta.apply(doc).unwrap();
+    }
+    text.chars().fold(pos, |pos, c| {
+        let (delta, pos) = typed_char(doc, pos, c).unwrap();
+        delta.apply(doc).unwrap();
+        pos
+    })
+}
+
+#[test]
+fn test_change_and_type() {
+    let at = |x, y| DocPosition{ x, y };
+    let change = |content, pos, kind, text| {
+        let mut doc = Document::from(content);
+        let cursor = change_and_type(&mut doc, pos, kind, text);
+        (doc.text(0..doc.graphemes()).unwrap(), cursor)
+    };
+    // `cc`, including on an empty line
+    assert_eq!(change("foo bar\nxx", at(4, 0), MotionKind::Line, "hi"), (String::from("hi\nxx"), at(2, 0)));
+    assert_eq!(change("foo\n\nxx", at(0, 1), MotionKind::Line, "hi"), (String::from("foo\nhi\nxx"), at(2, 1)));
+    // `c$`, `ce` and `cw` on the last word of a line
+    assert_eq!(change("foo bar\nxx", at(4, 0), MotionKind::LineEnd, "baz"), (String::from("foo baz\nxx"), at(7, 0)));
+    assert_eq!(change("foo bar\nxx", at(4, 0), MotionKind::WordEnd, "baz"), (String::from("foo baz\nxx"), at(7, 0)));
+    assert_eq!(change("foo bar\nxx", at(4, 0), MotionKind::WordForward, "baz"), (String::from("foo baz\nxx"), at(7, 0)));
+    // in the middle of a line
+    assert_eq!(change("foo bar\nxx", at(0, 0), MotionKind::WordForward, "baz"), (String::from("baz bar\nxx"), at(3, 0)));
+}
+
+#[test]
+fn test_repeat_change_and_type() {
+    let at = |x, y| DocPosition{ x, y };
+    // `.` replays the keys of the change, so it changes and types the same way on the next line
+    let repeat = |content, kind, text| {
+        let mut doc = Document::from(content);
+        change_and_type(&mut doc, at(0, 0), kind, text);
+        change_and_type(&mut doc, at(0, 1), kind, text);
+        doc.text(0..doc.graphemes()).unwrap()
+    };
+    // `cchi<Esc>j.`, onto a line and an empty line
+    assert_eq!(repeat("foo\nbar\nxx", MotionKind::Line, "hi"), "hi\nhi\nxx");
+    assert_eq!(repeat("foo\n\nxx", MotionKind::Line, "hi"), "hi\nhi\nxx");
+    // `c$baz<Esc>j0.`, and on a line that's shorter than the text
+    assert_eq!(repeat("foo bar\nx", MotionKind::LineEnd, "baz"), "baz\nbaz");
+    assert_eq!(repeat("foo\nbar qux", MotionKind::WordForward, "baz"), "baz\nbaz qux");
+}